@@ -21,6 +21,18 @@ const BUFFER_PUSH_SIZE: usize = SOUND_SAMPLE_RATE_IN_HERTZ as usize /
 const PID_CONST_PROPORTIONAL_TERM: f64 = 0.0075;
 const PID_CONST_INTEGRAL_TERM: f64 = 0.0001;
 
+// Saved state of the sound channels, excluding the audio output
+pub struct ApuSnapshot {
+    sample_clock: f64,
+    frame_sequencer_clock: u64,
+    frame_sequencer_clock_counts: u8,
+    sound_on: bool,
+    channel1: SquareChannel,
+    channel2: SquareChannel,
+    channel3: WaveChannel,
+    channel4: NoiseChannel,
+}
+
 pub struct Apu<'a> {
     audio_device: &'a AudioDevice,
     buffer: Vec<i16>,
@@ -58,6 +70,30 @@ impl<'a> Apu<'a> {
         }
     }
 
+    pub fn snapshot(&self) -> ApuSnapshot {
+        ApuSnapshot {
+            sample_clock: self.sample_clock,
+            frame_sequencer_clock: self.frame_sequencer_clock,
+            frame_sequencer_clock_counts: self.frame_sequencer_clock_counts,
+            sound_on: self.sound_on,
+            channel1: self.channel1.clone(),
+            channel2: self.channel2.clone(),
+            channel3: self.channel3.clone(),
+            channel4: self.channel4.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &ApuSnapshot) {
+        self.sample_clock = snapshot.sample_clock;
+        self.frame_sequencer_clock = snapshot.frame_sequencer_clock;
+        self.frame_sequencer_clock_counts = snapshot.frame_sequencer_clock_counts;
+        self.sound_on = snapshot.sound_on;
+        self.channel1 = snapshot.channel1.clone();
+        self.channel2 = snapshot.channel2.clone();
+        self.channel3 = snapshot.channel3.clone();
+        self.channel4 = snapshot.channel4.clone();
+    }
+
     pub fn step(&mut self, cycles_of_last_command: u8) {
         if self.first {
             let silence = vec![0; TARGET_QUEUE_LENGTH_IN_SAMPLES];
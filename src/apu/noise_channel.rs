@@ -2,11 +2,13 @@ use super::SOUND_SAMPLE_RATE_IN_HERTZ;
 use super::volume_envelope::VolumeEnvelope;
 const FREQUENCY_TIMER_TICKS_PER_PERIOD: u32 = 1;
 
+#[derive(Clone, Copy)]
 enum ShiftRegisterWidth {
     SevenBit,
     FifteenBit,
 }
 
+#[derive(Clone)]
 pub struct NoiseChannel {
     frequency_timer_ticks: u32,
     frequency_timer_tick_counts: u32,
@@ -2,6 +2,7 @@ use super::SOUND_SAMPLE_RATE_IN_HERTZ;
 use super::volume_envelope::VolumeEnvelope;
 const FREQUENCY_TIMER_TICKS_PER_PERIOD: u32 = 8;
 
+#[derive(Clone)]
 pub struct SquareChannel {
     frequency_timer_ticks: u32,
     frequency_timer_tick_counts: u32,
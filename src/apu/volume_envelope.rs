@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct VolumeEnvelope {
     volume: u8,
     envelope_starting_volume: u8,
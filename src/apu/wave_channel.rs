@@ -1,6 +1,7 @@
 use super::SOUND_SAMPLE_RATE_IN_HERTZ;
 const FREQUENCY_TIMER_TICKS_PER_PERIOD: u32 = 32;
 
+#[derive(Clone)]
 pub struct WaveChannel {
     frequency_timer_ticks: u32,
     frequency_timer_tick_counts: u32,
@@ -0,0 +1,78 @@
+use app_dirs::*;
+use memory::BlockMemory;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[cfg(test)]
+mod tests;
+
+const APP_INFO: AppInfo = AppInfo { name: "RustGB", author: "Michael Jung" };
+
+// Battery of a cartridge, which keeps the cartridge RAM when the emulator is
+// closed. The RAM is stored in a file named after the hash of the ROM.
+pub struct Battery {
+    path: PathBuf,
+    // Set if the file does not fit the RAM and must not be overwritten
+    read_only: Cell<bool>,
+}
+
+impl Battery {
+    pub fn new(rom: &BlockMemory) -> Battery {
+        let mut path = app_root(AppDataType::UserData, &APP_INFO).unwrap();
+        path.push(calc_rom_hash(rom));
+        path.set_extension("ram");
+        Battery::with_path(path)
+    }
+
+    fn with_path(path: PathBuf) -> Battery {
+        Battery { path, read_only: Cell::new(false) }
+    }
+
+    // Loads the saved RAM of the given size. It is all zeros if there is no
+    // save yet. Smaller saves, e.g. of older versions without the clock, are
+    // padded with zeros. Larger ones are kept as they are and not loaded.
+    pub fn load(&self, size: usize) -> BlockMemory {
+        let mut file = match OpenOptions::new().read(true).write(true).open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return BlockMemory::new(size),
+        };
+        let file_size = file.metadata().unwrap().len();
+        if file_size > size as u64 {
+            eprintln!("Warning: RAM file {} has {} bytes instead of {}, not using it",
+                      self.path.display(), file_size, size);
+            self.read_only.set(true);
+            return BlockMemory::new(size);
+        }
+        file.set_len(size as u64).unwrap();
+        BlockMemory::new_mut_from_file(&mut file)
+    }
+
+    pub fn save(&self, ram: &BlockMemory) {
+        if self.read_only.get() {
+            return;
+        }
+        let mut ram_file = File::create(&self.path).unwrap();
+        ram_file.write_all(&ram.get_memory()).unwrap();
+        ram_file.flush().unwrap();
+    }
+}
+
+// Creates cartridge RAM of the given size, which holds the saved RAM if the
+// cartridge has a battery
+pub fn create_ram(battery: &Option<Battery>, size: usize) -> BlockMemory {
+    match *battery {
+        Some(ref battery) => battery.load(size),
+        None => BlockMemory::new(size),
+    }
+}
+
+fn calc_rom_hash(rom: &BlockMemory) -> String {
+    let rom = rom.get_memory();
+    let mut hasher = DefaultHasher::new();
+    rom.hash(&mut hasher);
+    hasher.finish().to_string()
+}
@@ -0,0 +1,56 @@
+use super::*;
+use memory::Memory;
+
+use std::env;
+use std::fs;
+
+// Battery with a file of the given name in the temporary directory, which
+// is removed first
+fn create_battery(name: &str) -> Battery {
+    let path = env::temp_dir().join(format!("rustgb-{}-{}.ram", name, ::std::process::id()));
+    let _ = fs::remove_file(&path);
+    Battery::with_path(path)
+}
+
+#[test]
+fn test_create_ram_without_battery() {
+    let ram = create_ram(&None, 0x2000);
+    assert_eq!(ram.get_memory().len(), 0x2000);
+    assert!(ram.get_memory().iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn test_save_and_load() {
+    let battery = create_battery("round-trip");
+    let mut ram = battery.load(0x2000);
+    assert!(ram.get_memory().iter().all(|&byte| byte == 0));
+    ram.write_byte(0x0000, 0x12);
+    ram.write_byte(0x1FFF, 0x34);
+    battery.save(&ram);
+    let ram = battery.load(0x2000);
+    assert_eq!(ram.get_memory().len(), 0x2000);
+    assert_eq!((ram.read_byte(0x0000), ram.read_byte(0x1FFF)), (0x12, 0x34));
+    fs::remove_file(&battery.path).unwrap();
+}
+
+#[test]
+fn test_smaller_save_is_padded() {
+    let battery = create_battery("smaller");
+    fs::write(&battery.path, &[0x56; 0x10]).unwrap();
+    let ram = battery.load(0x20);
+    assert_eq!(ram.get_memory()[..0x10], [0x56; 0x10]);
+    assert_eq!(ram.get_memory()[0x10..], [0x00; 0x10]);
+    fs::remove_file(&battery.path).unwrap();
+}
+
+#[test]
+fn test_larger_save_is_kept() {
+    let battery = create_battery("larger");
+    fs::write(&battery.path, &[0x78; 0x20]).unwrap();
+    let mut ram = battery.load(0x10);
+    assert_eq!(ram.get_memory(), &[0x00; 0x10][..]);
+    ram.write_byte(0x0000, 0x9A);
+    battery.save(&ram);
+    assert_eq!(fs::read(&battery.path).unwrap(), vec![0x78; 0x20]);
+    fs::remove_file(&battery.path).unwrap();
+}
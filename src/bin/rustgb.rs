@@ -1,16 +1,95 @@
 extern crate rustgb;
 
 use std::env;
-use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+fn print_usage_and_exit(program: &str) -> ! {
+    println!("Usage: {} [OPTIONS] ROM_FILE", program);
+    println!();
+    println!("Options:");
+    println!("    --trace FILE           Write an instruction trace (Gameboy Doctor format)");
+    println!("    --trace-pc START:END   Only trace commands in this address range (hex)");
+    println!("    --trace-bank BANK      Only trace commands in this ROM bank");
+    println!("    --trace-labels         Write the labels of the symbol file into the trace");
+    println!("    --profile FILE         Write a profile of the executed code at exit");
+    println!("    --profile-folded FILE  Write the profiled call stacks for flamegraph tools");
+    println!("    --debug                Start in the debugger console (also opened with F12)");
+    println!("    --gdb PORT             Wait for a gdb connection on this local port");
+    println!("    --cdl FILE             Log code and data use of the ROM to this file at exit");
+    println!("    --symbols FILE         Load labels from this symbol file (default: ROM_FILE.sym)");
+    println!("    --boot-rom FILE        Run this boot ROM first (default: roms/bios.gb if it exists)");
+    println!("    --model MODEL          Emulate dmg0, dmg, mgb, sgb or cgb (default: from the ROM)");
+    println!("    --unlock-vram          Allow VRAM and OAM access while the GPU uses them");
+    ::std::process::exit(1);
+}
+
+fn parse_hex(value: &str) -> Option<u16> {
+    let value = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(value, 16).ok()
+}
+
+fn parse_address_range(value: &str) -> Option<(u16, u16)> {
+    let mut parts = value.splitn(2, ':');
+    let start = parts.next().and_then(parse_hex)?;
+    let end = parts.next().and_then(parse_hex)?;
+    Some((start, end))
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} ROM_FILE", args[0]);
-        ::std::process::exit(1);
+    let program = &args[0];
+    let mut options = rustgb::Options::default();
+    let mut rom_file = None;
+    let mut args_iter = args.iter().skip(1);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--trace" => {
+                let path = args_iter.next().unwrap_or_else(|| print_usage_and_exit(program));
+                options.trace_file = Some(PathBuf::from(path));
+            }
+            "--trace-pc" => {
+                let range = args_iter.next().and_then(|r| parse_address_range(r));
+                options.trace_pc_range = Some(range.unwrap_or_else(|| print_usage_and_exit(program)));
+            }
+            "--trace-bank" => {
+                let bank = args_iter.next().and_then(|b| b.parse().ok());
+                options.trace_rom_bank = Some(bank.unwrap_or_else(|| print_usage_and_exit(program)));
+            }
+            "--trace-labels" => options.trace_labels = true,
+            "--profile" => {
+                let path = args_iter.next().unwrap_or_else(|| print_usage_and_exit(program));
+                options.profile_file = Some(PathBuf::from(path));
+            }
+            "--profile-folded" => {
+                let path = args_iter.next().unwrap_or_else(|| print_usage_and_exit(program));
+                options.profile_folded_file = Some(PathBuf::from(path));
+            }
+            "--debug" => options.debug = true,
+            "--unlock-vram" => options.unlock_video_memory = true,
+            "--gdb" => {
+                let port = args_iter.next().and_then(|p| p.parse().ok());
+                options.gdb_port = Some(port.unwrap_or_else(|| print_usage_and_exit(program)));
+            }
+            "--cdl" => {
+                let path = args_iter.next().unwrap_or_else(|| print_usage_and_exit(program));
+                options.cdl_file = Some(PathBuf::from(path));
+            }
+            "--symbols" => {
+                let path = args_iter.next().unwrap_or_else(|| print_usage_and_exit(program));
+                options.symbol_file = Some(PathBuf::from(path));
+            }
+            "--boot-rom" => {
+                let path = args_iter.next().unwrap_or_else(|| print_usage_and_exit(program));
+                options.boot_rom = Some(PathBuf::from(path));
+            }
+            "--model" => {
+                let model = args_iter.next().and_then(|m| m.parse().ok());
+                options.model = Some(model.unwrap_or_else(|| print_usage_and_exit(program)));
+            }
+            _ if rom_file.is_none() && !arg.starts_with("--") => rom_file = Some(arg),
+            _ => print_usage_and_exit(program),
+        }
     }
-    let path = Path::new(&args[1]);
-    let mut file = File::open(path).expect(&format!("Error opening file: {}", args[1]));
-    rustgb::run(&mut file);
+    let rom_file = rom_file.unwrap_or_else(|| print_usage_and_exit(program));
+    rustgb::run(Path::new(rom_file), &options);
 }
@@ -0,0 +1,102 @@
+use cpu::Cpu;
+use memory::Memory;
+use model::Model;
+use timer::Timer;
+
+use std::cell::RefCell;
+
+#[cfg(test)]
+mod tests;
+
+// The ® tile drawn next to the logo, which is part of the boot ROM
+const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+// Sets up the state the boot ROM of the model leaves behind and starts the
+// cartridge at 0x0100, so that no boot ROM is needed. Values that are not
+// documented, as they depend on how long the boot ROM ran, are left at 0.
+pub fn skip_boot_rom<M: Memory>(cpu: &mut Cpu<M>, timer: &RefCell<Timer>, model: Model) {
+    cpu.memory_mut().leave_bios();
+    let cgb_rom = cpu.memory().read_byte(0x143) & 0x80 != 0;
+    // The DMG boot ROM leaves H and C set unless the header checksum is 0
+    let dmg_flags = if cpu.memory().read_byte(0x14D) == 0 { 0x80 } else { 0xB0 };
+
+    let mut state = cpu.state();
+    let registers = match model {
+        Model::Dmg0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+        Model::Dmg => [0x01, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        Model::Mgb => [0xFF, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+        Model::Cgb if cgb_rom => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        // In DMG compatibility mode, B depends on the title of the cartridge
+        Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C],
+    };
+    state.a = registers[0];
+    state.f = registers[1];
+    state.b = registers[2];
+    state.c = registers[3];
+    state.d = registers[4];
+    state.e = registers[5];
+    state.h = registers[6];
+    state.l = registers[7];
+    state.sp = 0xFFFE;
+    state.pc = 0x0100;
+    state.interrupt_master_enable = false;
+    state.interrupt_enable = 0x00;
+    state.interrupt_flags = 0x01;
+    cpu.set_state(&state);
+
+    let divider = match model {
+        Model::Dmg0 => 0x18,
+        Model::Dmg | Model::Mgb => 0xAB,
+        Model::Sgb | Model::Cgb => 0x00,
+    };
+    timer.borrow_mut().set_counter(divider << 8);
+
+    let memory = cpu.memory_mut();
+    if model != Model::Cgb {
+        write_logo(memory);
+    }
+    // The boot sound is played on channel 1, which has faded out by now. It
+    // is not restarted, so NR52 reads 0xF0 instead of 0xF1.
+    memory.write_byte(0xFF26, 0x80);
+    if model != Model::Sgb {
+        memory.write_byte(0xFF11, 0x80);
+        memory.write_byte(0xFF12, 0xF3);
+        memory.write_byte(0xFF25, 0xF3);
+        memory.write_byte(0xFF24, 0x77);
+    }
+    memory.write_byte(0xFF40, 0x91);
+    memory.write_byte(0xFF47, 0xFC);
+}
+
+// Writes the logo of the cartridge header to the VRAM like the DMG boot ROM:
+// every pixel is doubled in both directions and only the low bit plane is
+// used. The logo is shown in the middle of the background tile map.
+fn write_logo(memory: &mut Memory) {
+    let mut address = 0x8010;
+    for offset in 0x104..0x134 {
+        let byte = memory.read_byte(offset);
+        for &nibble in &[byte >> 4, byte & 0x0F] {
+            let row = double_pixels(nibble);
+            memory.write_byte(address, row);
+            memory.write_byte(address + 2, row);
+            address += 4;
+        }
+    }
+    for &row in &REGISTERED_TILE {
+        memory.write_byte(address, row);
+        address += 2;
+    }
+    for i in 0..12 {
+        memory.write_byte(0x9904 + i, i as u8 + 1);
+        memory.write_byte(0x9924 + i, i as u8 + 13);
+    }
+    memory.write_byte(0x9910, 0x19);
+}
+
+// Turns the four pixels of the lower nibble into a row of eight
+fn double_pixels(nibble: u8) -> u8 {
+    (0..4).fold(0, |row, bit| {
+        if nibble & (0x08 >> bit) != 0 { row | (0xC0 >> (bit * 2)) } else { row }
+    })
+}
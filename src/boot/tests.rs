@@ -0,0 +1,99 @@
+use super::*;
+use apu::Apu;
+use gpu::Gpu;
+use io_registers::IoRegisters;
+use mbc::{create_mbc, NINTENDO_LOGO};
+use memory::{BlockMemory, MemoryMap};
+use test_utils::{NullAudioDevice, NullDisplay};
+
+// Cartridge ROM with the logo and the given CGB flag in the header
+fn create_rom(cgb_flag: u8) -> BlockMemory {
+    let mut rom = BlockMemory::new(0x8000);
+    for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+        rom.write_byte_usize(0x104 + i, byte);
+    }
+    rom.write_byte(0x143, cgb_flag);
+    rom.write_byte(0x14D, 0x12);
+    rom
+}
+
+// Skips the boot ROM of the model and runs the test on the resulting state
+fn boot<F>(model: Model, rom: BlockMemory, test: F)
+where
+    F: FnOnce(&Cpu<MemoryMap<NullDisplay>>, &Timer),
+{
+    let cgb = model.is_cgb() && rom.read_byte(0x143) & 0x80 != 0;
+    let audio_device = NullAudioDevice::default();
+    let apu = RefCell::new(Apu::new(&audio_device));
+    let gpu = RefCell::new(Gpu::new(NullDisplay));
+    let timer = RefCell::new(Timer::new());
+    let io = RefCell::new(IoRegisters::new(&apu, &gpu, &timer, cgb));
+    let mut bios = BlockMemory::new(0x100);
+    let memory = MemoryMap::new(model, &mut bios, create_mbc(rom, None), &gpu, &io);
+    let mut cpu = Cpu::new(memory);
+    skip_boot_rom(&mut cpu, &timer, model);
+    test(&cpu, &timer.borrow());
+}
+
+#[test]
+fn test_registers() {
+    let models = [
+        (Model::Dmg0, 0x00, [0x0100, 0xFF13, 0x00C1, 0x8403], 0x18),
+        (Model::Dmg, 0x00, [0x01B0, 0x0013, 0x00D8, 0x014D], 0xAB),
+        (Model::Mgb, 0x00, [0xFFB0, 0x0013, 0x00D8, 0x014D], 0xAB),
+        (Model::Sgb, 0x00, [0x0100, 0x0014, 0x0000, 0xC060], 0x00),
+        (Model::Cgb, 0x00, [0x1180, 0x0000, 0x0008, 0x007C], 0x00),
+        (Model::Cgb, 0x80, [0x1180, 0x0000, 0xFF56, 0x000D], 0x00),
+    ];
+    for &(model, cgb_flag, registers, divider) in &models {
+        boot(model, create_rom(cgb_flag), |cpu, timer| {
+            let state = cpu.state();
+            assert_eq!([state.af(), state.bc(), state.de(), state.hl()], registers, "{:?}", model);
+            assert_eq!(state.sp, 0xFFFE);
+            assert_eq!(state.pc, 0x0100);
+            assert!(!state.interrupt_master_enable);
+            assert_eq!(state.interrupt_enable, 0x00);
+            assert_eq!(state.interrupt_flags & 0x1F, 0x01);
+            assert_eq!(timer.get_divider(), divider, "{:?}", model);
+            let memory = cpu.memory();
+            assert!(!memory.in_bios());
+            assert_eq!(memory.read_byte(0xFF04), divider);
+            assert_eq!(memory.read_byte(0xFF40), 0x91);
+            assert_eq!(memory.read_byte(0xFF47), 0xFC);
+            assert_eq!(memory.read_byte(0xFF26), 0xF0);
+        });
+    }
+}
+
+#[test]
+fn test_dmg_flags_without_header_checksum() {
+    let mut rom = create_rom(0x00);
+    rom.write_byte(0x14D, 0x00);
+    boot(Model::Dmg, rom, |cpu, _| assert_eq!(cpu.state().af(), 0x0180));
+}
+
+#[test]
+fn test_logo() {
+    for &model in &[Model::Dmg0, Model::Dmg, Model::Mgb, Model::Sgb] {
+        boot(model, create_rom(0x00), |cpu, _| {
+            let memory = cpu.memory();
+            // The first logo byte 0xCE doubled: 0xC and 0xE become two rows
+            // each
+            let rows: Vec<u8> = (0..8).map(|i| memory.read_byte(0x8010 + i)).collect();
+            assert_eq!(rows, [0xF0, 0x00, 0xF0, 0x00, 0xFC, 0x00, 0xFC, 0x00], "{:?}", model);
+            for (i, &row) in REGISTERED_TILE.iter().enumerate() {
+                assert_eq!(memory.read_byte(0x8190 + i as u16 * 2), row);
+            }
+            assert_eq!(memory.read_byte(0x9904), 0x01);
+            assert_eq!(memory.read_byte(0x990F), 0x0C);
+            assert_eq!(memory.read_byte(0x9910), 0x19);
+            assert_eq!(memory.read_byte(0x9924), 0x0D);
+            assert_eq!(memory.read_byte(0x992F), 0x18);
+        });
+    }
+    // The CGB boot ROM clears the VRAM it used
+    boot(Model::Cgb, create_rom(0x80), |cpu, _| {
+        assert_eq!(cpu.memory().read_byte(0x8010), 0x00);
+        assert_eq!(cpu.memory().read_byte(0x9904), 0x00);
+    });
+}
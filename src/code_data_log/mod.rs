@@ -0,0 +1,80 @@
+use memory::ReadKind;
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+// Flags stored for every byte of ROM and cartridge RAM
+pub const EXECUTED_OPCODE: u8 = 0x01;
+pub const EXECUTED_OPERAND: u8 = 0x02;
+pub const READ_AS_DATA: u8 = 0x04;
+pub const DMA_SOURCE: u8 = 0x08;
+
+fn flag(kind: ReadKind) -> u8 {
+    match kind {
+        ReadKind::Opcode => EXECUTED_OPCODE,
+        ReadKind::Operand => EXECUTED_OPERAND,
+        ReadKind::Data => READ_AS_DATA,
+        ReadKind::Dma => DMA_SOURCE,
+    }
+}
+
+// Code/data log: records how each byte of the cartridge is used. The file
+// contains one byte of flags per ROM byte followed by one per cartridge RAM
+// byte, like the CDL files of other emulators.
+pub struct CodeDataLog {
+    rom: RefCell<Vec<u8>>,
+    ram: RefCell<Vec<u8>>,
+}
+
+impl CodeDataLog {
+    pub fn new(rom_size: usize, ram_size: usize) -> CodeDataLog {
+        CodeDataLog {
+            rom: RefCell::new(vec![0; rom_size]),
+            ram: RefCell::new(vec![0; ram_size]),
+        }
+    }
+
+    // Continues the log in an existing file of the same size, so that
+    // several runs add up
+    pub fn load(&self, path: &Path) {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        let mut rom = self.rom.borrow_mut();
+        let mut ram = self.ram.borrow_mut();
+        if data.len() != rom.len() + ram.len() {
+            eprintln!("Ignoring code/data log of a different size: {}", path.display());
+            return;
+        }
+        let (rom_data, ram_data) = data.split_at(rom.len());
+        for (flags, &loaded) in rom.iter_mut().zip(rom_data).chain(ram.iter_mut().zip(ram_data)) {
+            *flags |= loaded;
+        }
+    }
+
+    pub fn log_rom(&self, offset: usize, kind: ReadKind) {
+        if let Some(flags) = self.rom.borrow_mut().get_mut(offset) {
+            *flags |= flag(kind);
+        }
+    }
+
+    pub fn log_ram(&self, offset: usize, kind: ReadKind) {
+        if let Some(flags) = self.ram.borrow_mut().get_mut(offset) {
+            *flags |= flag(kind);
+        }
+    }
+
+    pub fn write(&self, path: &Path) {
+        let mut file = File::create(path)
+            .unwrap_or_else(|e| panic!("Error creating code/data log {}: {}", path.display(), e));
+        file.write_all(&self.rom.borrow())
+            .and_then(|_| file.write_all(&self.ram.borrow()))
+            .expect("Error writing code/data log");
+    }
+}
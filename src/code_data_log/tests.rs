@@ -0,0 +1,47 @@
+use super::*;
+
+use std::env;
+use std::path::PathBuf;
+
+// Path of a log in the temporary directory, which is removed first
+fn log_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("rustgb-{}-{}.cdl", name, ::std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_log_flags() {
+    let code_data_log = CodeDataLog::new(4, 2);
+    code_data_log.log_rom(0, ReadKind::Opcode);
+    code_data_log.log_rom(1, ReadKind::Operand);
+    code_data_log.log_rom(1, ReadKind::Data);
+    code_data_log.log_ram(1, ReadKind::Dma);
+    // Offsets beyond the end are ignored
+    code_data_log.log_rom(4, ReadKind::Data);
+    code_data_log.log_ram(2, ReadKind::Data);
+    assert_eq!(*code_data_log.rom.borrow(), vec![EXECUTED_OPCODE, EXECUTED_OPERAND | READ_AS_DATA, 0, 0]);
+    assert_eq!(*code_data_log.ram.borrow(), vec![0, DMA_SOURCE]);
+}
+
+#[test]
+fn test_continue_log() {
+    let path = log_path("continue");
+    let code_data_log = CodeDataLog::new(2, 1);
+    code_data_log.log_rom(0, ReadKind::Opcode);
+    code_data_log.log_ram(0, ReadKind::Data);
+    code_data_log.write(&path);
+    assert_eq!(fs::read(&path).unwrap(), vec![EXECUTED_OPCODE, 0, READ_AS_DATA]);
+
+    let code_data_log = CodeDataLog::new(2, 1);
+    code_data_log.log_rom(0, ReadKind::Data);
+    code_data_log.load(&path);
+    assert_eq!(*code_data_log.rom.borrow(), vec![EXECUTED_OPCODE | READ_AS_DATA, 0]);
+    assert_eq!(*code_data_log.ram.borrow(), vec![READ_AS_DATA]);
+
+    // A log of another cartridge is not continued
+    let code_data_log = CodeDataLog::new(4, 0);
+    code_data_log.load(&path);
+    assert_eq!(*code_data_log.rom.borrow(), vec![0; 4]);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,251 @@
+use super::{EXTENDED_OPCODE_MAP, INSTRUCTION_LENGTHS, OPCODE_MAP};
+use memory::Memory;
+use symbols::Symbols;
+
+use std::fmt;
+
+// Clock cycles of each command, indexed by opcode. For conditional commands
+// this is the number of cycles if the condition is not met. Unused opcodes
+// and the 0xCB prefix are listed with 4 cycles. A test checks the counts
+// against the execution of every command.
+static INSTRUCTION_CYCLES: [u8; 256] = [
+//  x0  x1  x2  x3  x4  x5  x6  x7  x8  x9  xA  xB  xC  xD  xE  xF
+     4, 12,  8,  8,  4,  4,  8,  4, 20,  8,  8,  8,  4,  4,  8,  4, // 0x
+     4, 12,  8,  8,  4,  4,  8,  4, 12,  8,  8,  8,  4,  4,  8,  4, // 1x
+     8, 12,  8,  8,  4,  4,  8,  4,  8,  8,  8,  8,  4,  4,  8,  4, // 2x
+     8, 12,  8,  8, 12, 12, 12,  4,  8,  8,  8,  8,  4,  4,  8,  4, // 3x
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 4x
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 5x
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 6x
+     8,  8,  8,  8,  8,  8,  4,  8,  4,  4,  4,  4,  4,  4,  8,  4, // 7x
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 8x
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 9x
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // Ax
+     4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // Bx
+     8, 12, 12, 16, 12, 16,  8, 16,  8, 16, 12,  4, 12, 24,  8, 16, // Cx
+     8, 12, 12,  4, 12, 16,  8, 16,  8, 16, 12,  4, 12,  4,  8, 16, // Dx
+    12, 12,  8,  4,  4, 16,  8, 16, 16,  4, 16,  4,  4,  4,  8, 16, // Ex
+    12, 12,  8,  4,  4, 16,  8, 16, 12,  8, 16,  4,  4,  4,  8, 16, // Fx
+];
+
+// The value of an immediate operand
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Byte(u8),
+    SignedByte(i8),
+    Word(u16),
+}
+
+// A single decoded command
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub operand: Option<Operand>,
+    pub length: u8,
+    pub cycles: u8,
+    // Cycles of a conditional jump, call or return if the condition is met.
+    // Equal to `cycles` for all other commands.
+    pub cycles_branch_taken: u8,
+}
+
+impl Instruction {
+    // Returns the address the command jumps to or accesses through an
+    // immediate operand
+    pub fn referenced_address(&self) -> Option<u16> {
+        let next_address = self.address.wrapping_add(self.length as u16);
+        match (self.operand, self.bytes[0]) {
+            (Some(Operand::SignedByte(offset)), _) if self.mnemonic.starts_with("JR") => {
+                Some(next_address.wrapping_add(offset as i16 as u16))
+            }
+            (Some(Operand::Word(value)), _) => Some(value),
+            // LDH (n),A and LDH A,(n)
+            (Some(Operand::Byte(value)), 0xE0) | (Some(Operand::Byte(value)), 0xF0) => Some(0xFF00 + value as u16),
+            // RST
+            (None, opcode) if opcode & 0xC7 == 0xC7 => Some((opcode & 0x38) as u16),
+            _ => None,
+        }
+    }
+
+    // Formats the command with the referenced address replaced by its label
+    pub fn format_with_symbols(&self, symbols: &Symbols, memory: &Memory) -> String {
+        let address = match self.referenced_address() {
+            Some(address) => address,
+            None => return self.mnemonic.clone(),
+        };
+        match symbols.label(memory.rom_bank(address), address) {
+            Some(label) => {
+                let formatted = if self.mnemonic.starts_with("RST") {
+                    format!("${:02X}", address)
+                } else {
+                    format!("${:04X}", address)
+                };
+                self.mnemonic.replacen(&formatted, label, 1)
+            }
+            None => self.mnemonic.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)
+    }
+}
+
+// Decodes the command at the given address of the live memory
+pub fn disassemble(memory: &Memory, address: u16) -> Instruction {
+    let bytes = [
+        memory.read_byte(address),
+        memory.read_byte(address.wrapping_add(1)),
+        memory.read_byte(address.wrapping_add(2)),
+    ];
+    disassemble_bytes(&bytes, address).unwrap()
+}
+
+// Decodes the command at the start of the given bytes, e.g. taken from a ROM
+// bank. `address` is where the bytes are mapped in memory, which is needed to
+// resolve relative jumps. Returns None if the bytes end within the command.
+pub fn disassemble_bytes(bytes: &[u8], address: u16) -> Option<Instruction> {
+    let opcode = *bytes.first()?;
+    let length = INSTRUCTION_LENGTHS[opcode as usize];
+    if bytes.len() < length as usize {
+        return None;
+    }
+    let bytes = bytes[..length as usize].to_vec();
+    let instruction = if opcode == 0xCB {
+        decode_extended(bytes, address)
+    } else {
+        decode(bytes, address)
+    };
+    Some(instruction)
+}
+
+// Decodes consecutive commands until the bytes run out
+pub fn disassemble_all(bytes: &[u8], address: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while let Some(instruction) = disassemble_bytes(&bytes[offset..], address.wrapping_add(offset as u16)) {
+        offset += instruction.length as usize;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+fn decode(bytes: Vec<u8>, address: u16) -> Instruction {
+    let opcode = bytes[0];
+    let name = OPCODE_MAP[opcode as usize].name;
+    let length = bytes.len() as u8;
+    let operand = match length {
+        2 if takes_signed_operand(name) => Some(Operand::SignedByte(bytes[1] as i8)),
+        2 => Some(Operand::Byte(bytes[1])),
+        3 => Some(Operand::Word(((bytes[2] as u16) << 8) + bytes[1] as u16)),
+        _ => None,
+    };
+    let mnemonic = format_mnemonic(name, opcode, operand, address.wrapping_add(length as u16));
+    let cycles = INSTRUCTION_CYCLES[opcode as usize];
+    Instruction {
+        address,
+        bytes,
+        mnemonic,
+        operand,
+        length,
+        cycles,
+        cycles_branch_taken: cycles + extra_cycles_branch_taken(name),
+    }
+}
+
+fn decode_extended(bytes: Vec<u8>, address: u16) -> Instruction {
+    let opcode = bytes[1];
+    let name = EXTENDED_OPCODE_MAP[opcode as usize].name;
+    // Commands on (HL) need additional cycles to access the memory. BIT only
+    // reads from it.
+    let cycles = if opcode & 0x07 != 0x06 {
+        8
+    } else if opcode >= 0x40 && opcode < 0x80 {
+        12
+    } else {
+        16
+    };
+    Instruction {
+        address,
+        mnemonic: format_mnemonic(name, opcode, None, address.wrapping_add(2)),
+        bytes,
+        operand: None,
+        length: 2,
+        cycles,
+        cycles_branch_taken: cycles,
+    }
+}
+
+fn takes_signed_operand(name: &str) -> bool {
+    name.starts_with("JR_") || name == "ADD_SP_N" || name == "LDHL_SP_N"
+}
+
+fn extra_cycles_branch_taken(name: &str) -> u8 {
+    match name {
+        "JR_NZ_N" | "JR_Z_N" | "JR_NC_N" | "JR_C_N" => 4,
+        "JP_NZ_NN" | "JP_Z_NN" | "JP_NC_NN" | "JP_C_NN" => 4,
+        "CALL_NZ_NN" | "CALL_Z_NN" | "CALL_NC_NN" | "CALL_C_NN" => 12,
+        "RET_NZ" | "RET_Z" | "RET_NC" | "RET_C" => 12,
+        _ => 0,
+    }
+}
+
+fn format_signed(value: i8) -> String {
+    if value < 0 {
+        format!("-${:02X}", -(value as i16))
+    } else {
+        format!("+${:02X}", value)
+    }
+}
+
+// Builds the mnemonic from the name of the opcode class, following the naming
+// conventions of the opcode classes in the cpu module
+fn format_mnemonic(name: &str, opcode: u8, operand: Option<Operand>, next_address: u16) -> String {
+    let value = match operand {
+        Some(Operand::Byte(value)) => value as u16,
+        Some(Operand::Word(value)) => value,
+        _ => 0,
+    };
+    match name {
+        "XX" => return format!("DB ${:02X}", opcode),
+        "LDHL_SP_N" => {
+            if let Some(Operand::SignedByte(offset)) = operand {
+                return format!("LD HL,SP{}", format_signed(offset));
+            }
+        }
+        _ => {}
+    }
+    let mut tokens = name.split('_');
+    let command = tokens.next().unwrap();
+    let operands: Vec<String> = tokens
+        .map(|token| match token {
+            "N" => match operand {
+                Some(Operand::SignedByte(offset)) if command == "JR" => {
+                    format!("${:04X}", next_address.wrapping_add(offset as i16 as u16))
+                }
+                Some(Operand::SignedByte(offset)) => format_signed(offset),
+                _ => format!("${:02X}", value),
+            },
+            "NN" => format!("${:04X}", value),
+            "xN" => format!("(${:04X})", 0xFF00 + value),
+            "xNN" => format!("(${:04X})", value),
+            "xHL" if command == "LDI" => "(HL+)".to_string(),
+            "xHL" if command == "LDD" => "(HL-)".to_string(),
+            _ if token.starts_with("0x") => format!("${}", &token[2..]),
+            _ if token.starts_with('x') => format!("({})", &token[1..]),
+            _ => token.to_string(),
+        })
+        .collect();
+    let command = match command {
+        "LDI" | "LDD" => "LD",
+        _ => command,
+    };
+    if operands.is_empty() {
+        command.to_string()
+    } else {
+        format!("{} {}", command, operands.join(","))
+    }
+}
@@ -0,0 +1,163 @@
+use super::{EXTENDED_OPCODE_MAP, INSTRUCTION_LENGTHS, OPCODE_MAP, Opcode, Registers};
+use memory::Memory;
+
+// ROM is cached in pages of one bank
+const PAGE_SIZE: usize = 0x4000;
+
+// Executable RAM: working RAM and high RAM. Echo RAM is not cached.
+const WORKING_RAM_START: u16 = 0xC000;
+const WORKING_RAM_END: u16 = 0xDFFF;
+const HIGH_RAM_START: u16 = 0xFF80;
+const HIGH_RAM_END: u16 = 0xFFFE;
+
+type Execute = fn(opcode: Opcode, registers: &mut Registers, memory: &mut Memory);
+
+// A decoded command with the function executing it
+#[derive(Clone, Copy)]
+pub(super) struct DecodedCommand {
+    pub(super) execute: Execute,
+    pub(super) opcode: Opcode,
+    pub(super) length: u8,
+}
+
+impl DecodedCommand {
+    pub(super) fn new(opcode: Opcode) -> DecodedCommand {
+        // Commands with the 0xCB prefix are looked up directly
+        let execute = if opcode.b1 == 0xCB {
+            EXTENDED_OPCODE_MAP[opcode.b2 as usize].execute
+        } else {
+            OPCODE_MAP[opcode.b1 as usize].execute
+        };
+        DecodedCommand { execute, opcode, length: INSTRUCTION_LENGTHS[opcode.b1 as usize] }
+    }
+
+    pub(super) fn bytes(&self) -> [u8; 3] {
+        [self.opcode.b1, self.opcode.b2, self.opcode.b3]
+    }
+}
+
+// Where a command is stored: by its offset in the cartridge ROM, so that bank
+// switches do not need to invalidate anything, or by its address in RAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum CacheKey {
+    Rom(usize),
+    Ram(u16),
+}
+
+fn ram_area(address: u16) -> Option<(u16, u16)> {
+    match address {
+        WORKING_RAM_START...WORKING_RAM_END => Some((WORKING_RAM_START, WORKING_RAM_END)),
+        HIGH_RAM_START...HIGH_RAM_END => Some((HIGH_RAM_START, HIGH_RAM_END)),
+        _ => None,
+    }
+}
+
+// Decoded commands of ROM and RAM, so that the bytes of a command only have
+// to be read and decoded once. RAM entries are invalidated by writes.
+pub(super) struct InstructionCache {
+    rom_pages: Vec<Option<Box<[Option<DecodedCommand>]>>>,
+    working_ram: Vec<Option<(u32, DecodedCommand)>>,
+    high_ram: Vec<Option<(u32, DecodedCommand)>>,
+    // RAM entries of older generations are invalid, which allows to drop all
+    // of them at once
+    ram_generation: u32,
+}
+
+impl InstructionCache {
+    pub(super) fn new() -> InstructionCache {
+        InstructionCache {
+            rom_pages: Vec::new(),
+            working_ram: vec![None; (WORKING_RAM_END - WORKING_RAM_START) as usize + 1],
+            high_ram: vec![None; (HIGH_RAM_END - HIGH_RAM_START) as usize + 1],
+            ram_generation: 0,
+        }
+    }
+
+    // Returns where the command at the address would be cached, or None if
+    // it can not be cached
+    pub(super) fn key(&self, address: u16, memory: &Memory) -> Option<CacheKey> {
+        match memory.rom_offset(address) {
+            Some(offset) => Some(CacheKey::Rom(offset)),
+            None => ram_area(address).map(|_| CacheKey::Ram(address)),
+        }
+    }
+
+    pub(super) fn get(&self, key: CacheKey) -> Option<DecodedCommand> {
+        match key {
+            CacheKey::Rom(offset) => {
+                let page = self.rom_pages.get(offset / PAGE_SIZE)?.as_ref()?;
+                page[offset % PAGE_SIZE]
+            }
+            CacheKey::Ram(address) => match self.ram_entry(address) {
+                Some(&Some((generation, command))) if generation == self.ram_generation => Some(command),
+                _ => None,
+            },
+        }
+    }
+
+    // Commands whose bytes reach into the next page or memory area are not
+    // cached, as that area is mapped independently
+    pub(super) fn insert(&mut self, key: CacheKey, command: DecodedCommand) {
+        let length = command.length as usize;
+        match key {
+            CacheKey::Rom(offset) => {
+                if offset % PAGE_SIZE + length > PAGE_SIZE {
+                    return;
+                }
+                let index = offset / PAGE_SIZE;
+                if self.rom_pages.len() <= index {
+                    self.rom_pages.resize(index + 1, None);
+                }
+                let page = self.rom_pages[index].get_or_insert_with(|| vec![None; PAGE_SIZE].into_boxed_slice());
+                page[offset % PAGE_SIZE] = Some(command);
+            }
+            CacheKey::Ram(address) => {
+                let fits = ram_area(address).map_or(false, |(_, end)| end - address >= length as u16 - 1);
+                let generation = self.ram_generation;
+                if let (true, Some(entry)) = (fits, self.ram_entry_mut(address)) {
+                    *entry = Some((generation, command));
+                }
+            }
+        }
+    }
+
+    // Drops the commands containing the written byte. Writes to the ROM area
+    // only reach the registers of the MBC and leave the ROM commands valid.
+    pub(super) fn invalidate(&mut self, address: u16) {
+        // Writes to echo RAM change the working RAM
+        let address = match address {
+            0xE000...0xFDFF => address - 0x2000,
+            _ => address,
+        };
+        if ram_area(address).is_none() {
+            return;
+        }
+        for offset in 0..3 {
+            if let Some(entry) = self.ram_entry_mut(address.wrapping_sub(offset)) {
+                *entry = None;
+            }
+        }
+    }
+
+    // Drops all RAM commands, e.g. after the memory was changed from outside
+    // of the CPU
+    pub(super) fn invalidate_ram(&mut self) {
+        self.ram_generation = self.ram_generation.wrapping_add(1);
+    }
+
+    fn ram_entry(&self, address: u16) -> Option<&Option<(u32, DecodedCommand)>> {
+        match ram_area(address) {
+            Some((WORKING_RAM_START, _)) => self.working_ram.get((address - WORKING_RAM_START) as usize),
+            Some(_) => self.high_ram.get((address - HIGH_RAM_START) as usize),
+            None => None,
+        }
+    }
+
+    fn ram_entry_mut(&mut self, address: u16) -> Option<&mut Option<(u32, DecodedCommand)>> {
+        match ram_area(address) {
+            Some((WORKING_RAM_START, _)) => self.working_ram.get_mut((address - WORKING_RAM_START) as usize),
+            Some(_) => self.high_ram.get_mut((address - HIGH_RAM_START) as usize),
+            None => None,
+        }
+    }
+}
@@ -1,15 +1,57 @@
 // Allow non Camel case types for opcode classes
 #![allow(non_camel_case_types)]
 
-use memory::Memory;
+mod disassembler;
+mod instruction_cache;
+mod profiler;
+mod state;
+mod trace;
+
+#[cfg(test)]
+mod tests;
+
+use memory::{Memory, MemorySnapshot, ReadKind};
+
+pub use self::disassembler::{disassemble, disassemble_all, disassemble_bytes, Instruction, Operand};
+pub use self::profiler::Profiler;
+pub use self::state::CpuState;
+pub use self::trace::{TraceFilter, Tracer};
+
+use self::instruction_cache::{DecodedCommand, InstructionCache};
+
+use std::cell::{Cell, RefCell};
 
 pub const CLOCK_SPEED_IN_HERTZ: u64 = 4_194_304;
+// The CPU is paused for 2050 machine cycles while switching speeds
+const SPEED_SWITCH_CYCLES: u16 = 8200;
+const INTERRUPT_DISPATCH_CYCLES: u8 = 20;
+const HALT_EXIT_CYCLES: u8 = 4;
+
+// Saved state of the CPU and the memory, see Cpu::snapshot
+pub struct Snapshot {
+    registers: Registers,
+    clock: u64,
+    memory: MemorySnapshot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuStatus {
+    Running,
+    Halted,
+    Stopped,
+    // Executed an illegal opcode at the given address
+    Locked { pc: u16 },
+}
 
 pub struct Cpu<M>
 {
     registers: Registers,
     memory: M,
     clock: u64,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    access_log: Option<RefCell<Vec<MemoryAccess>>>,
+    instruction_cache: InstructionCache,
 }
 
 impl<M> Cpu<M>
@@ -20,78 +62,431 @@ impl<M> Cpu<M>
             registers: Registers::new(),
             memory,
             clock: 0,
+            tracer: None,
+            profiler: None,
+            access_log: None,
+            instruction_cache: InstructionCache::new(),
+        }
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    // Enables recording of all memory accesses made by the CPU
+    pub fn set_record_memory_accesses(&mut self, enabled: bool) {
+        self.access_log = if enabled { Some(RefCell::new(Vec::new())) } else { None };
+    }
+
+    // Returns the memory accesses recorded since the last call
+    pub fn take_memory_accesses(&mut self) -> Vec<MemoryAccess> {
+        match self.access_log {
+            Some(ref mut log) => log.get_mut().drain(..).collect(),
+            None => Vec::new(),
         }
     }
 
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    // As the CPU can not know what is changed, cached RAM commands are
+    // dropped. Use write_byte for single bytes.
+    pub fn memory_mut(&mut self) -> &mut M {
+        self.instruction_cache.invalidate_ram();
+        &mut self.memory
+    }
+
+    // Writes a byte without advancing the system
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.instruction_cache.invalidate(address);
+        self.memory.write_byte(address, value);
+    }
+
+    // Writes a byte for the frontend, which the memory hooks do not see
+    pub fn write_byte_without_hooks(&mut self, address: u16, value: u8) {
+        self.instruction_cache.invalidate(address);
+        self.memory.write_byte_without_hooks(address, value);
+    }
+
+    pub fn set_memory_hooks_suspended(&mut self, suspended: bool) {
+        self.memory.set_hooks_suspended(suspended);
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState::from_registers(
+            &self.registers,
+            self.memory.read_byte(0xFFFF),
+            self.memory.read_byte(0xFF0F))
+    }
+
+    // Overwrites the registers and the interrupt registers IE and IF
+    pub fn set_state(&mut self, state: &CpuState) {
+        state.to_registers(&mut self.registers);
+        self.memory.write_byte(0xFFFF, state.interrupt_enable);
+        self.memory.write_byte(0xFF0F, state.interrupt_flags);
+    }
+
+    // Saves the state of the CPU and the memory, so that execution can be
+    // repeated from this point. Returns None if the memory does not support
+    // snapshots.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            registers: self.registers.clone(),
+            clock: self.clock,
+            memory: self.memory.snapshot()?,
+        })
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.registers = snapshot.registers.clone();
+        self.clock = snapshot.clock;
+        self.memory.restore(&snapshot.memory);
+        self.instruction_cache.invalidate_ram();
+    }
+
     pub fn get_clock(&self) -> u64 {
         self.clock
     }
 
+    pub fn status(&self) -> CpuStatus {
+        if let Some(pc) = self.registers.locked_pc {
+            CpuStatus::Locked { pc }
+        } else if self.is_stopped() {
+            CpuStatus::Stopped
+        } else if self.registers.halt {
+            CpuStatus::Halted
+        } else {
+            CpuStatus::Running
+        }
+    }
+
+    // Returns true while the CPU is in STOP mode or pausing for a speed switch.
+    // In STOP mode the main clock is off, so the rest of the system does not
+    // advance, including the clock of an MBC3. During a speed switch the
+    // divider, the timer and the GPU keep running.
+    pub fn is_stopped(&self) -> bool {
+        self.registers.stop || self.registers.speed_switch_cycles_left > 0
+    }
+
     pub fn cycle(&mut self) -> u8 {
         if self.memory.in_bios() && self.registers.pc == 0x100 {
             self.memory.leave_bios();
         }
+        if self.registers.locked_pc.is_some() {
+            // A locked up CPU neither executes commands nor handles interrupts,
+            // but the rest of the system keeps running
+            self.memory.step(4);
+            self.clock = self.clock.wrapping_add(4);
+            return 4;
+        }
+        if self.is_stopped() {
+            self.continue_stop();
+            self.clock = self.clock.wrapping_add(4);
+            return 4;
+        }
         if self.registers.halt {
+            self.memory.step(4);
             self.registers.cycles_of_last_command = 4;
+            if let Some(ref mut profiler) = self.profiler {
+                profiler.record_halted(4);
+            }
         } else {
-            let opcode = self.fetch_opcode();
-            self.execute_opcode(opcode);
+            let enable_interrupts = self.registers.enable_interrupts_pending;
+            if let Some(ref mut tracer) = self.tracer {
+                tracer.trace(&self.registers, &self.memory);
+            }
+            let profile_start = if self.profiler.is_some() {
+                let pc = self.registers.pc;
+                Some(((self.memory.rom_bank(pc), pc), self.memory.read_byte(pc), self.registers.sp))
+            } else {
+                None
+            };
+            self.execute_next_command();
+            if let (Some(profiler), Some((location, opcode, sp))) = (self.profiler.as_mut(), profile_start) {
+                profiler.record_command(location, opcode, sp, self.registers.sp, self.registers.pc,
+                                        self.registers.cycles_of_last_command, &self.memory);
+            }
+            if self.registers.halt {
+                self.enter_halt();
+            } else if self.registers.stop {
+                self.enter_stop();
+            }
+            // EI takes effect after the instruction following it, unless that
+            // instruction disabled interrupts again
+            if enable_interrupts && self.registers.enable_interrupts_pending {
+                self.registers.enable_interrupts_pending = false;
+                self.registers.interrupt_master_enable = true;
+            }
         }
-        self.clock = self.clock.wrapping_add(self.registers.cycles_of_last_command as u64);
         let mut cycles = self.registers.cycles_of_last_command;
-        cycles += self.handle_interrupts();
+        let interrupt_cycles = self.handle_interrupts();
+        if interrupt_cycles > 0 {
+            if let Some(ref mut profiler) = self.profiler {
+                let pc = self.registers.pc;
+                profiler.record_interrupt((self.memory.rom_bank(pc), pc), interrupt_cycles);
+            }
+        }
+        cycles += interrupt_cycles;
+        self.clock = self.clock.wrapping_add(cycles as u64);
         cycles
     }
 
-    fn fetch_opcode(&self) -> Opcode {
-        let pc = self.registers.pc;
-        Opcode {
-            b1: self.memory.read_byte(pc),
-            b2: self.memory.read_byte(pc + 1),
-            b3: self.memory.read_byte(pc + 2),
+    fn interrupts_fired(&self) -> u8 {
+        self.memory.read_byte(0xFFFF) & self.memory.read_byte(0xFF0F) & 0x1F
+    }
+
+    fn enter_halt(&mut self) {
+        if !self.registers.interrupt_master_enable && self.interrupts_fired() != 0 {
+            // HALT bug: the CPU does not halt, but fails to increment PC
+            // after reading the next opcode
+            self.registers.halt = false;
+            self.registers.halt_bug = true;
         }
     }
 
-    fn execute_opcode(&mut self, opcode: Opcode) {
-        let opcode_function = OPCODE_MAP[opcode.b1 as usize];
-        opcode_function(opcode, &mut self.registers, &mut self.memory);
+    fn enter_stop(&mut self) {
+        let button_held = self.memory.read_byte(0xFF00) & 0x0F != 0x0F;
+        if button_held {
+            // STOP is not entered while a button is held. Depending on whether
+            // an interrupt is pending, the CPU halts or continues right away
+            // with STOP being a one-byte command.
+            self.registers.stop = false;
+            if self.interrupts_fired() != 0 {
+                self.registers.pc -= 1;
+            } else {
+                self.registers.halt = true;
+            }
+            return;
+        }
+        if self.memory.switch_speed() {
+            self.registers.stop = false;
+            self.registers.speed_switch_cycles_left = SPEED_SWITCH_CYCLES;
+        }
+        // Entering STOP mode resets the divider register
+        self.memory.write_byte(0xFF04, 0);
     }
 
-    fn handle_interrupts(&mut self) -> u8 {
-        let enabled_interrupts = self.memory.read_byte(0xFFFF);
-        let interrupts_fired = self.memory.read_byte(0xFF0F) & enabled_interrupts & 0x1F;
-        if interrupts_fired != 0 {
-            self.registers.halt = false;
+    fn continue_stop(&mut self) {
+        if self.registers.speed_switch_cycles_left > 0 {
+            self.memory.step(4);
+            self.registers.speed_switch_cycles_left -= 4;
+        } else if self.memory.read_byte(0xFF00) & 0x0F != 0x0F {
+            // Any joypad line going low ends STOP mode
+            self.registers.stop = false;
         }
-        if !self.registers.interrupt_master_enable { return 0; }
-        if interrupts_fired & Interrupt::VerticalBlank.to_bitmask() != 0 {
-            self.start_interrupt_handler(Interrupt::VerticalBlank)
-        } else if interrupts_fired & Interrupt::LcdStatus.to_bitmask() != 0 {
-            self.start_interrupt_handler(Interrupt::LcdStatus)
-        } else if interrupts_fired & Interrupt::Timer.to_bitmask() != 0 {
-            self.start_interrupt_handler(Interrupt::Timer)
-        } else if interrupts_fired & Interrupt::Serial.to_bitmask() != 0 {
-            self.start_interrupt_handler(Interrupt::Serial)
-        } else if interrupts_fired & Interrupt::Keypad.to_bitmask() != 0 {
-            self.start_interrupt_handler(Interrupt::Keypad)
+    }
+
+    fn execute_next_command(&mut self) {
+        let mut memory = TimedMemory::new(
+            &mut self.memory, self.access_log.as_ref(), &mut self.instruction_cache);
+        let command = memory.fetch_command(&mut self.registers);
+        (command.execute)(command.opcode, &mut self.registers, &mut memory);
+        memory.finish(self.registers.cycles_of_last_command);
+    }
+
+    fn handle_interrupts(&mut self) -> u8 {
+        if self.interrupts_fired() == 0 {
+            return 0;
+        }
+        let halted = self.registers.halt;
+        self.registers.halt = false;
+        if !self.registers.interrupt_master_enable {
+            return 0;
+        }
+        if halted {
+            // Waking up from HALT takes an additional machine cycle before the
+            // interrupt is dispatched
+            self.memory.step(HALT_EXIT_CYCLES);
+            HALT_EXIT_CYCLES + self.start_interrupt_handler()
         } else {
-            0
+            self.start_interrupt_handler()
         }
     }
 
-    fn start_interrupt_handler(&mut self, interrupt: Interrupt) -> u8 {
+    // Dispatches an interrupt in five machine cycles: two internal cycles,
+    // pushing the two bytes of PC and setting PC to the handler. The interrupt
+    // is only chosen while pushing, so a push that overwrites IE (0xFFFF) can
+    // redirect the dispatch to another interrupt or cancel it. In that case PC
+    // is set to 0x0000.
+    fn start_interrupt_handler(&mut self) -> u8 {
         self.registers.interrupt_master_enable = false;
-        let interrupts_fired = self.memory.read_byte(0xFF0F);
-        self.memory.write_byte(0xFF0F, interrupts_fired & !interrupt.to_bitmask());
-        self.registers.sp -= 2;
-        let sp = self.registers.sp;
-        let pc = self.registers.pc;
-        self.memory.write_word(sp, pc);
-        self.registers.pc = interrupt.handler_address();
-        12
+        let mut pc = self.registers.pc;
+        if self.registers.halt_bug {
+            // The interrupt returns to the HALT instruction that triggered the
+            // halt bug (EI followed by HALT with an interrupt pending)
+            self.registers.halt_bug = false;
+            pc = pc.wrapping_sub(1);
+        }
+        let interrupt = {
+            let mut memory = TimedMemory::new(
+                &mut self.memory, self.access_log.as_ref(), &mut self.instruction_cache);
+            memory.step(8);
+            self.registers.sp = self.registers.sp.wrapping_sub(1);
+            memory.write_byte(self.registers.sp, (pc >> 8) as u8);
+            let interrupts_enabled = memory.peek(0xFFFF);
+            self.registers.sp = self.registers.sp.wrapping_sub(1);
+            memory.write_byte(self.registers.sp, pc as u8);
+            let interrupts_requested = memory.peek(0xFF0F);
+            memory.finish(INTERRUPT_DISPATCH_CYCLES);
+            Interrupt::highest_priority(interrupts_enabled & interrupts_requested)
+        };
+        match interrupt {
+            Some(interrupt) => {
+                let interrupts_requested = self.memory.read_byte(0xFF0F);
+                self.memory.write_byte(0xFF0F, interrupts_requested & !interrupt.to_bitmask());
+                self.registers.pc = interrupt.handler_address();
+            }
+            None => self.registers.pc = 0x0000,
+        }
+        INTERRUPT_DISPATCH_CYCLES
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessKind {
+    // Read of an opcode or its operands
+    Fetch,
+    Read,
+    Write,
+}
+
+// A memory access on the CPU bus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryAccess {
+    pub address: u16,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+// Wraps the memory while the CPU executes a command. Every access advances
+// the rest of the system by one machine cycle (4 clock cycles), so that it
+// happens at the correct time within the command.
+struct TimedMemory<'a> {
+    memory: RefCell<&'a mut (Memory + 'a)>,
+    cycles: Cell<u8>,
+    access_log: Option<&'a RefCell<Vec<MemoryAccess>>>,
+    instruction_cache: &'a mut InstructionCache,
+}
+
+impl<'a> TimedMemory<'a> {
+    fn new(memory: &'a mut (Memory + 'a),
+           access_log: Option<&'a RefCell<Vec<MemoryAccess>>>,
+           instruction_cache: &'a mut InstructionCache) -> TimedMemory<'a> {
+        TimedMemory {
+            memory: RefCell::new(memory),
+            cycles: Cell::new(0),
+            access_log,
+            instruction_cache,
+        }
+    }
+
+    // Fetches the command at PC. Commands found in the instruction cache are
+    // not read again, but take the same time.
+    fn fetch_command(&mut self, registers: &mut Registers) -> DecodedCommand {
+        let key = if registers.halt_bug || self.memory.borrow().bypass_instruction_cache() {
+            None
+        } else {
+            self.instruction_cache.key(registers.pc, &**self.memory.borrow())
+        };
+        if let Some(command) = key.and_then(|key| self.instruction_cache.get(key)) {
+            let bytes = command.bytes();
+            for (i, &byte) in bytes[..command.length as usize].iter().enumerate() {
+                self.advance(4);
+                self.log_access(registers.pc.wrapping_add(i as u16), byte, AccessKind::Fetch);
+            }
+            return command;
+        }
+        let command = DecodedCommand::new(fetch_opcode(registers, self));
+        if let Some(key) = key {
+            self.instruction_cache.insert(key, command);
+        }
+        command
+    }
+
+    // Reads a byte without advancing the system
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.borrow().read_byte(address)
+    }
+
+    fn log_access(&self, address: u16, value: u8, kind: AccessKind) {
+        if let Some(log) = self.access_log {
+            log.borrow_mut().push(MemoryAccess { address, value, kind });
+        }
+    }
+
+    fn advance(&self, cycles: u8) {
+        self.cycles.set(self.cycles.get() + cycles);
+        self.memory.borrow_mut().step(cycles);
+    }
+
+    // Advances the system by the internal cycles of a command that are not
+    // spent on memory accesses
+    fn finish(&self, cycles_of_command: u8) {
+        let cycles = self.cycles.get();
+        if cycles_of_command > cycles {
+            self.advance(cycles_of_command - cycles);
+        }
+    }
+}
+
+impl<'a> Memory for TimedMemory<'a> {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read_byte_as(address, ReadKind::Data)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        self.advance(4);
+        let value = self.memory.borrow().read_byte_as(address, kind);
+        let access_kind = match kind {
+            ReadKind::Opcode | ReadKind::Operand => AccessKind::Fetch,
+            _ => AccessKind::Read,
+        };
+        self.log_access(address, value, access_kind);
+        value
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.advance(4);
+        self.instruction_cache.invalidate(address);
+        self.memory.get_mut().write_byte(address, value);
+        self.log_access(address, value, AccessKind::Write);
+    }
+
+    fn step(&mut self, cycles: u8) {
+        self.advance(cycles);
+    }
+
+    fn in_bios(&self) -> bool {
+        self.memory.borrow().in_bios()
+    }
+
+    fn leave_bios(&mut self) {
+        self.memory.get_mut().leave_bios();
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        self.memory.get_mut().switch_speed()
+    }
+
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        self.memory.borrow().rom_bank(address)
+    }
+}
+
+#[derive(Clone, Copy)]
 enum Interrupt {
     VerticalBlank,
     LcdStatus,
@@ -101,6 +496,17 @@ enum Interrupt {
 }
 
 impl Interrupt {
+    // Returns the interrupt with the highest priority of the given bitmask
+    fn highest_priority(interrupts: u8) -> Option<Interrupt> {
+        [
+            Interrupt::VerticalBlank,
+            Interrupt::LcdStatus,
+            Interrupt::Timer,
+            Interrupt::Serial,
+            Interrupt::Keypad,
+        ].iter().cloned().find(|interrupt| interrupts & interrupt.to_bitmask() != 0)
+    }
+
     fn to_bitmask(&self) -> u8 {
         match *self {
             Interrupt::VerticalBlank => 0b0000_0001,
@@ -122,526 +528,590 @@ impl Interrupt {
     }
 }
 
-static OPCODE_MAP: [fn(opcode: Opcode, registers: &mut Registers, memory: &mut Memory); 256] =
+struct OpcodeEntry {
+    execute: fn(opcode: Opcode, registers: &mut Registers, memory: &mut Memory),
+    // Name of the opcode class, used by the disassembler
+    name: &'static str,
+}
+
+macro_rules! opcode_entry {
+    ($name:ident) => {
+        OpcodeEntry {
+            execute: create_and_execute::<$name>,
+            name: stringify!($name),
+        }
+    }
+}
+
+macro_rules! extended_opcode_entry {
+    () => {
+        OpcodeEntry {
+            execute: execute_extended_opcode,
+            name: "PREFIX_CB",
+        }
+    }
+}
+
+static OPCODE_MAP: [OpcodeEntry; 256] =
 [
-    create_and_execute::<NOP>,             // 0x00
-    create_and_execute::<LD_BC_NN>,        // 0x01
-    create_and_execute::<LD_xBC_A>,        // 0x02
-    create_and_execute::<INC_BC>,          // 0x03
-    create_and_execute::<INC_B>,           // 0x04
-    create_and_execute::<DEC_B>,           // 0x05
-    create_and_execute::<LD_B_N>,          // 0x06
-    create_and_execute::<RLCA>,            // 0x07
-    create_and_execute::<LD_xNN_SP>,       // 0x08
-    create_and_execute::<ADD_HL_BC>,       // 0x09
-    create_and_execute::<LD_A_xBC>,        // 0x0A
-    create_and_execute::<DEC_BC>,          // 0x0B
-    create_and_execute::<INC_C>,           // 0x0C
-    create_and_execute::<DEC_C>,           // 0x0D
-    create_and_execute::<LD_C_N>,          // 0x0E
-    create_and_execute::<RRCA>,            // 0x0F
-    create_and_execute::<STOP>,            // 0x10
-    create_and_execute::<LD_DE_NN>,        // 0x11
-    create_and_execute::<LD_xDE_A>,        // 0x12
-    create_and_execute::<INC_DE>,          // 0x13
-    create_and_execute::<INC_D>,           // 0x14
-    create_and_execute::<DEC_D>,           // 0x15
-    create_and_execute::<LD_D_N>,          // 0x16
-    create_and_execute::<RLA>,             // 0x17
-    create_and_execute::<JR_N>,            // 0x18
-    create_and_execute::<ADD_HL_DE>,       // 0x19
-    create_and_execute::<LD_A_xDE>,        // 0x1A
-    create_and_execute::<DEC_DE>,          // 0x1B
-    create_and_execute::<INC_E>,           // 0x1C
-    create_and_execute::<DEC_E>,           // 0x1D
-    create_and_execute::<LD_E_N>,          // 0x1E
-    create_and_execute::<RRA>,             // 0x1F
-    create_and_execute::<JR_NZ_N>,         // 0x20
-    create_and_execute::<LD_HL_NN>,        // 0x21
-    create_and_execute::<LDI_xHL_A>,       // 0x22
-    create_and_execute::<INC_HL>,          // 0x23
-    create_and_execute::<INC_H>,           // 0x24
-    create_and_execute::<DEC_H>,           // 0x25
-    create_and_execute::<LD_H_N>,          // 0x26
-    create_and_execute::<DAA>,             // 0x27
-    create_and_execute::<JR_Z_N>,          // 0x28
-    create_and_execute::<ADD_HL_HL>,       // 0x29
-    create_and_execute::<LDI_A_xHL>,       // 0x2A
-    create_and_execute::<DEC_HL>,          // 0x2B
-    create_and_execute::<INC_L>,           // 0x2C
-    create_and_execute::<DEC_L>,           // 0x2D
-    create_and_execute::<LD_L_N>,          // 0x2E
-    create_and_execute::<CPL>,             // 0x2F
-    create_and_execute::<JR_NC_N>,         // 0x30
-    create_and_execute::<LD_SP_NN>,        // 0x31
-    create_and_execute::<LDD_xHL_A>,       // 0x32
-    create_and_execute::<INC_SP>,          // 0x33
-    create_and_execute::<INC_xHL>,         // 0x34
-    create_and_execute::<DEC_xHL>,         // 0x35
-    create_and_execute::<LD_xHL_N>,        // 0x36
-    create_and_execute::<SCF>,             // 0x37
-    create_and_execute::<JR_C_N>,          // 0x38
-    create_and_execute::<ADD_HL_SP>,       // 0x39
-    create_and_execute::<LDD_A_xHL>,       // 0x3A
-    create_and_execute::<DEC_SP>,          // 0x3B
-    create_and_execute::<INC_A>,           // 0x3C
-    create_and_execute::<DEC_A>,           // 0x3D
-    create_and_execute::<LD_A_N>,          // 0x3E
-    create_and_execute::<CCF>,             // 0x3F
-    create_and_execute::<LD_B_B>,          // 0x40
-    create_and_execute::<LD_B_C>,          // 0x41
-    create_and_execute::<LD_B_D>,          // 0x42
-    create_and_execute::<LD_B_E>,          // 0x43
-    create_and_execute::<LD_B_H>,          // 0x44
-    create_and_execute::<LD_B_L>,          // 0x45
-    create_and_execute::<LD_B_xHL>,        // 0x46
-    create_and_execute::<LD_B_A>,          // 0x47
-    create_and_execute::<LD_C_B>,          // 0x48
-    create_and_execute::<LD_C_C>,          // 0x49
-    create_and_execute::<LD_C_D>,          // 0x4A
-    create_and_execute::<LD_C_E>,          // 0x4B
-    create_and_execute::<LD_C_H>,          // 0x4C
-    create_and_execute::<LD_C_L>,          // 0x4D
-    create_and_execute::<LD_C_xHL>,        // 0x4E
-    create_and_execute::<LD_C_A>,          // 0x4F
-    create_and_execute::<LD_D_B>,          // 0x50
-    create_and_execute::<LD_D_C>,          // 0x51
-    create_and_execute::<LD_D_D>,          // 0x52
-    create_and_execute::<LD_D_E>,          // 0x53
-    create_and_execute::<LD_D_H>,          // 0x54
-    create_and_execute::<LD_D_L>,          // 0x55
-    create_and_execute::<LD_D_xHL>,        // 0x56
-    create_and_execute::<LD_D_A>,          // 0x57
-    create_and_execute::<LD_E_B>,          // 0x58
-    create_and_execute::<LD_E_C>,          // 0x59
-    create_and_execute::<LD_E_D>,          // 0x5A
-    create_and_execute::<LD_E_E>,          // 0x5B
-    create_and_execute::<LD_E_H>,          // 0x5C
-    create_and_execute::<LD_E_L>,          // 0x5D
-    create_and_execute::<LD_E_xHL>,        // 0x5E
-    create_and_execute::<LD_E_A>,          // 0x5F
-    create_and_execute::<LD_H_B>,          // 0x60
-    create_and_execute::<LD_H_C>,          // 0x61
-    create_and_execute::<LD_H_D>,          // 0x62
-    create_and_execute::<LD_H_E>,          // 0x63
-    create_and_execute::<LD_H_H>,          // 0x64
-    create_and_execute::<LD_H_L>,          // 0x65
-    create_and_execute::<LD_H_xHL>,        // 0x66
-    create_and_execute::<LD_H_A>,          // 0x67
-    create_and_execute::<LD_L_B>,          // 0x68
-    create_and_execute::<LD_L_C>,          // 0x69
-    create_and_execute::<LD_L_D>,          // 0x6A
-    create_and_execute::<LD_L_E>,          // 0x6B
-    create_and_execute::<LD_L_H>,          // 0x6C
-    create_and_execute::<LD_L_L>,          // 0x6D
-    create_and_execute::<LD_L_xHL>,        // 0x6E
-    create_and_execute::<LD_L_A>,          // 0x6F
-    create_and_execute::<LD_xHL_B>,        // 0x70
-    create_and_execute::<LD_xHL_C>,        // 0x71
-    create_and_execute::<LD_xHL_D>,        // 0x72
-    create_and_execute::<LD_xHL_E>,        // 0x73
-    create_and_execute::<LD_xHL_H>,        // 0x74
-    create_and_execute::<LD_xHL_L>,        // 0x75
-    create_and_execute::<HALT>,            // 0x76
-    create_and_execute::<LD_xHL_A>,        // 0x77
-    create_and_execute::<LD_A_B>,          // 0x78
-    create_and_execute::<LD_A_C>,          // 0x79
-    create_and_execute::<LD_A_D>,          // 0x7A
-    create_and_execute::<LD_A_E>,          // 0x7B
-    create_and_execute::<LD_A_H>,          // 0x7C
-    create_and_execute::<LD_A_L>,          // 0x7D
-    create_and_execute::<LD_A_xHL>,        // 0x7E
-    create_and_execute::<LD_A_A>,          // 0x7F
-    create_and_execute::<ADD_A_B>,         // 0x80
-    create_and_execute::<ADD_A_C>,         // 0x81
-    create_and_execute::<ADD_A_D>,         // 0x82
-    create_and_execute::<ADD_A_E>,         // 0x83
-    create_and_execute::<ADD_A_H>,         // 0x84
-    create_and_execute::<ADD_A_L>,         // 0x85
-    create_and_execute::<ADD_A_xHL>,       // 0x86
-    create_and_execute::<ADD_A_A>,         // 0x87
-    create_and_execute::<ADC_A_B>,         // 0x88
-    create_and_execute::<ADC_A_C>,         // 0x89
-    create_and_execute::<ADC_A_D>,         // 0x8A
-    create_and_execute::<ADC_A_E>,         // 0x8B
-    create_and_execute::<ADC_A_H>,         // 0x8C
-    create_and_execute::<ADC_A_L>,         // 0x8D
-    create_and_execute::<ADC_A_xHL>,       // 0x8E
-    create_and_execute::<ADC_A_A>,         // 0x8F
-    create_and_execute::<SUB_A_B>,         // 0x90
-    create_and_execute::<SUB_A_C>,         // 0x91
-    create_and_execute::<SUB_A_D>,         // 0x92
-    create_and_execute::<SUB_A_E>,         // 0x93
-    create_and_execute::<SUB_A_H>,         // 0x94
-    create_and_execute::<SUB_A_L>,         // 0x95
-    create_and_execute::<SUB_A_xHL>,       // 0x96
-    create_and_execute::<SUB_A_A>,         // 0x97
-    create_and_execute::<SBC_A_B>,         // 0x98
-    create_and_execute::<SBC_A_C>,         // 0x99
-    create_and_execute::<SBC_A_D>,         // 0x9A
-    create_and_execute::<SBC_A_E>,         // 0x9B
-    create_and_execute::<SBC_A_H>,         // 0x9C
-    create_and_execute::<SBC_A_L>,         // 0x9D
-    create_and_execute::<SBC_A_xHL>,       // 0x9E
-    create_and_execute::<SBC_A_A>,         // 0x9F
-    create_and_execute::<AND_A_B>,         // 0xA0
-    create_and_execute::<AND_A_C>,         // 0xA1
-    create_and_execute::<AND_A_D>,         // 0xA2
-    create_and_execute::<AND_A_E>,         // 0xA3
-    create_and_execute::<AND_A_H>,         // 0xA4
-    create_and_execute::<AND_A_L>,         // 0xA5
-    create_and_execute::<AND_A_xHL>,       // 0xA6
-    create_and_execute::<AND_A_A>,         // 0xA7
-    create_and_execute::<XOR_A_B>,         // 0xA8
-    create_and_execute::<XOR_A_C>,         // 0xA9
-    create_and_execute::<XOR_A_D>,         // 0xAA
-    create_and_execute::<XOR_A_E>,         // 0xAB
-    create_and_execute::<XOR_A_H>,         // 0xAC
-    create_and_execute::<XOR_A_L>,         // 0xAD
-    create_and_execute::<XOR_A_xHL>,       // 0xAE
-    create_and_execute::<XOR_A_A>,         // 0xAF
-    create_and_execute::<OR_A_B>,          // 0xB0
-    create_and_execute::<OR_A_C>,          // 0xB1
-    create_and_execute::<OR_A_D>,          // 0xB2
-    create_and_execute::<OR_A_E>,          // 0xB3
-    create_and_execute::<OR_A_H>,          // 0xB4
-    create_and_execute::<OR_A_L>,          // 0xB5
-    create_and_execute::<OR_A_xHL>,        // 0xB6
-    create_and_execute::<OR_A_A>,          // 0xB7
-    create_and_execute::<CP_B>,            // 0xB8
-    create_and_execute::<CP_C>,            // 0xB9
-    create_and_execute::<CP_D>,            // 0xBA
-    create_and_execute::<CP_E>,            // 0xBB
-    create_and_execute::<CP_H>,            // 0xBC
-    create_and_execute::<CP_L>,            // 0xBD
-    create_and_execute::<CP_xHL>,          // 0xBE
-    create_and_execute::<CP_A>,            // 0xBF
-    create_and_execute::<RET_NZ>,          // 0xC0
-    create_and_execute::<POP_BC>,          // 0xC1
-    create_and_execute::<JP_NZ_NN>,        // 0xC2
-    create_and_execute::<JP_NN>,           // 0xC3
-    create_and_execute::<CALL_NZ_NN>,      // 0xC4
-    create_and_execute::<PUSH_BC>,         // 0xC5
-    create_and_execute::<ADD_A_N>,         // 0xC6
-    create_and_execute::<RST_0x00>,        // 0xC7
-    create_and_execute::<RET_Z>,           // 0xC8
-    create_and_execute::<RET>,             // 0xC9
-    create_and_execute::<JP_Z_NN>,         // 0xCA
-    execute_extended_opcode,               // 0xCB
-    create_and_execute::<CALL_Z_NN>,       // 0xCC
-    create_and_execute::<CALL_NN>,         // 0xCD
-    create_and_execute::<ADC_A_N>,         // 0xCE
-    create_and_execute::<RST_0x08>,        // 0xCF
-    create_and_execute::<RET_NC>,          // 0xD0
-    create_and_execute::<POP_DE>,          // 0xD1
-    create_and_execute::<JP_NC_NN>,        // 0xD2
-    create_and_execute::<XX>,              // 0xD3
-    create_and_execute::<CALL_NC_NN>,      // 0xD4
-    create_and_execute::<PUSH_DE>,         // 0xD5
-    create_and_execute::<SUB_A_N>,         // 0xD6
-    create_and_execute::<RST_0x10>,        // 0xD7
-    create_and_execute::<RET_C>,           // 0xD8
-    create_and_execute::<RETI>,            // 0xD9
-    create_and_execute::<JP_C_NN>,         // 0xDA
-    create_and_execute::<XX>,              // 0xDB
-    create_and_execute::<CALL_C_NN>,       // 0xDC
-    create_and_execute::<XX>,              // 0xDD
-    create_and_execute::<SBC_A_N>,         // 0xDE
-    create_and_execute::<RST_0x18>,        // 0xDF
-    create_and_execute::<LDH_xN_A>,        // 0xE0
-    create_and_execute::<POP_HL>,          // 0xE1
-    create_and_execute::<LDH_xC_A>,        // 0xE2
-    create_and_execute::<XX>,              // 0xE3
-    create_and_execute::<XX>,              // 0xE4
-    create_and_execute::<PUSH_HL>,         // 0xE5
-    create_and_execute::<AND_A_N>,         // 0xE6
-    create_and_execute::<RST_0x20>,        // 0xE7
-    create_and_execute::<ADD_SP_N>,        // 0xE8
-    create_and_execute::<JP_xHL>,          // 0xE9
-    create_and_execute::<LD_xNN_A>,        // 0xEA
-    create_and_execute::<XX>,              // 0xEB
-    create_and_execute::<XX>,              // 0xEC
-    create_and_execute::<XX>,              // 0xED
-    create_and_execute::<XOR_A_N>,         // 0xEE
-    create_and_execute::<RST_0x28>,        // 0xEF
-    create_and_execute::<LDH_A_xN>,        // 0xF0
-    create_and_execute::<POP_AF>,          // 0xF1
-    create_and_execute::<LDH_A_xC>,        // 0xF2  removed???
-    create_and_execute::<DI>,              // 0xF3
-    create_and_execute::<XX>,              // 0xE4
-    create_and_execute::<PUSH_AF>,         // 0xF5
-    create_and_execute::<OR_A_N>,          // 0xF6
-    create_and_execute::<RST_0x30>,        // 0xF7
-    create_and_execute::<LDHL_SP_N>,       // 0xF8
-    create_and_execute::<LD_SP_HL>,        // 0xF9
-    create_and_execute::<LD_A_xNN>,        // 0xFA
-    create_and_execute::<EI>,              // 0xFB
-    create_and_execute::<XX>,              // 0xFC
-    create_and_execute::<XX>,              // 0xFD
-    create_and_execute::<CP_N>,            // 0xFE
-    create_and_execute::<RST_0x38>,        // 0xFF
+    opcode_entry!(NOP),                    // 0x00
+    opcode_entry!(LD_BC_NN),               // 0x01
+    opcode_entry!(LD_xBC_A),               // 0x02
+    opcode_entry!(INC_BC),                 // 0x03
+    opcode_entry!(INC_B),                  // 0x04
+    opcode_entry!(DEC_B),                  // 0x05
+    opcode_entry!(LD_B_N),                 // 0x06
+    opcode_entry!(RLCA),                   // 0x07
+    opcode_entry!(LD_xNN_SP),              // 0x08
+    opcode_entry!(ADD_HL_BC),              // 0x09
+    opcode_entry!(LD_A_xBC),               // 0x0A
+    opcode_entry!(DEC_BC),                 // 0x0B
+    opcode_entry!(INC_C),                  // 0x0C
+    opcode_entry!(DEC_C),                  // 0x0D
+    opcode_entry!(LD_C_N),                 // 0x0E
+    opcode_entry!(RRCA),                   // 0x0F
+    opcode_entry!(STOP),                   // 0x10
+    opcode_entry!(LD_DE_NN),               // 0x11
+    opcode_entry!(LD_xDE_A),               // 0x12
+    opcode_entry!(INC_DE),                 // 0x13
+    opcode_entry!(INC_D),                  // 0x14
+    opcode_entry!(DEC_D),                  // 0x15
+    opcode_entry!(LD_D_N),                 // 0x16
+    opcode_entry!(RLA),                    // 0x17
+    opcode_entry!(JR_N),                   // 0x18
+    opcode_entry!(ADD_HL_DE),              // 0x19
+    opcode_entry!(LD_A_xDE),               // 0x1A
+    opcode_entry!(DEC_DE),                 // 0x1B
+    opcode_entry!(INC_E),                  // 0x1C
+    opcode_entry!(DEC_E),                  // 0x1D
+    opcode_entry!(LD_E_N),                 // 0x1E
+    opcode_entry!(RRA),                    // 0x1F
+    opcode_entry!(JR_NZ_N),                // 0x20
+    opcode_entry!(LD_HL_NN),               // 0x21
+    opcode_entry!(LDI_xHL_A),              // 0x22
+    opcode_entry!(INC_HL),                 // 0x23
+    opcode_entry!(INC_H),                  // 0x24
+    opcode_entry!(DEC_H),                  // 0x25
+    opcode_entry!(LD_H_N),                 // 0x26
+    opcode_entry!(DAA),                    // 0x27
+    opcode_entry!(JR_Z_N),                 // 0x28
+    opcode_entry!(ADD_HL_HL),              // 0x29
+    opcode_entry!(LDI_A_xHL),              // 0x2A
+    opcode_entry!(DEC_HL),                 // 0x2B
+    opcode_entry!(INC_L),                  // 0x2C
+    opcode_entry!(DEC_L),                  // 0x2D
+    opcode_entry!(LD_L_N),                 // 0x2E
+    opcode_entry!(CPL),                    // 0x2F
+    opcode_entry!(JR_NC_N),                // 0x30
+    opcode_entry!(LD_SP_NN),               // 0x31
+    opcode_entry!(LDD_xHL_A),              // 0x32
+    opcode_entry!(INC_SP),                 // 0x33
+    opcode_entry!(INC_xHL),                // 0x34
+    opcode_entry!(DEC_xHL),                // 0x35
+    opcode_entry!(LD_xHL_N),               // 0x36
+    opcode_entry!(SCF),                    // 0x37
+    opcode_entry!(JR_C_N),                 // 0x38
+    opcode_entry!(ADD_HL_SP),              // 0x39
+    opcode_entry!(LDD_A_xHL),              // 0x3A
+    opcode_entry!(DEC_SP),                 // 0x3B
+    opcode_entry!(INC_A),                  // 0x3C
+    opcode_entry!(DEC_A),                  // 0x3D
+    opcode_entry!(LD_A_N),                 // 0x3E
+    opcode_entry!(CCF),                    // 0x3F
+    opcode_entry!(LD_B_B),                 // 0x40
+    opcode_entry!(LD_B_C),                 // 0x41
+    opcode_entry!(LD_B_D),                 // 0x42
+    opcode_entry!(LD_B_E),                 // 0x43
+    opcode_entry!(LD_B_H),                 // 0x44
+    opcode_entry!(LD_B_L),                 // 0x45
+    opcode_entry!(LD_B_xHL),               // 0x46
+    opcode_entry!(LD_B_A),                 // 0x47
+    opcode_entry!(LD_C_B),                 // 0x48
+    opcode_entry!(LD_C_C),                 // 0x49
+    opcode_entry!(LD_C_D),                 // 0x4A
+    opcode_entry!(LD_C_E),                 // 0x4B
+    opcode_entry!(LD_C_H),                 // 0x4C
+    opcode_entry!(LD_C_L),                 // 0x4D
+    opcode_entry!(LD_C_xHL),               // 0x4E
+    opcode_entry!(LD_C_A),                 // 0x4F
+    opcode_entry!(LD_D_B),                 // 0x50
+    opcode_entry!(LD_D_C),                 // 0x51
+    opcode_entry!(LD_D_D),                 // 0x52
+    opcode_entry!(LD_D_E),                 // 0x53
+    opcode_entry!(LD_D_H),                 // 0x54
+    opcode_entry!(LD_D_L),                 // 0x55
+    opcode_entry!(LD_D_xHL),               // 0x56
+    opcode_entry!(LD_D_A),                 // 0x57
+    opcode_entry!(LD_E_B),                 // 0x58
+    opcode_entry!(LD_E_C),                 // 0x59
+    opcode_entry!(LD_E_D),                 // 0x5A
+    opcode_entry!(LD_E_E),                 // 0x5B
+    opcode_entry!(LD_E_H),                 // 0x5C
+    opcode_entry!(LD_E_L),                 // 0x5D
+    opcode_entry!(LD_E_xHL),               // 0x5E
+    opcode_entry!(LD_E_A),                 // 0x5F
+    opcode_entry!(LD_H_B),                 // 0x60
+    opcode_entry!(LD_H_C),                 // 0x61
+    opcode_entry!(LD_H_D),                 // 0x62
+    opcode_entry!(LD_H_E),                 // 0x63
+    opcode_entry!(LD_H_H),                 // 0x64
+    opcode_entry!(LD_H_L),                 // 0x65
+    opcode_entry!(LD_H_xHL),               // 0x66
+    opcode_entry!(LD_H_A),                 // 0x67
+    opcode_entry!(LD_L_B),                 // 0x68
+    opcode_entry!(LD_L_C),                 // 0x69
+    opcode_entry!(LD_L_D),                 // 0x6A
+    opcode_entry!(LD_L_E),                 // 0x6B
+    opcode_entry!(LD_L_H),                 // 0x6C
+    opcode_entry!(LD_L_L),                 // 0x6D
+    opcode_entry!(LD_L_xHL),               // 0x6E
+    opcode_entry!(LD_L_A),                 // 0x6F
+    opcode_entry!(LD_xHL_B),               // 0x70
+    opcode_entry!(LD_xHL_C),               // 0x71
+    opcode_entry!(LD_xHL_D),               // 0x72
+    opcode_entry!(LD_xHL_E),               // 0x73
+    opcode_entry!(LD_xHL_H),               // 0x74
+    opcode_entry!(LD_xHL_L),               // 0x75
+    opcode_entry!(HALT),                   // 0x76
+    opcode_entry!(LD_xHL_A),               // 0x77
+    opcode_entry!(LD_A_B),                 // 0x78
+    opcode_entry!(LD_A_C),                 // 0x79
+    opcode_entry!(LD_A_D),                 // 0x7A
+    opcode_entry!(LD_A_E),                 // 0x7B
+    opcode_entry!(LD_A_H),                 // 0x7C
+    opcode_entry!(LD_A_L),                 // 0x7D
+    opcode_entry!(LD_A_xHL),               // 0x7E
+    opcode_entry!(LD_A_A),                 // 0x7F
+    opcode_entry!(ADD_A_B),                // 0x80
+    opcode_entry!(ADD_A_C),                // 0x81
+    opcode_entry!(ADD_A_D),                // 0x82
+    opcode_entry!(ADD_A_E),                // 0x83
+    opcode_entry!(ADD_A_H),                // 0x84
+    opcode_entry!(ADD_A_L),                // 0x85
+    opcode_entry!(ADD_A_xHL),              // 0x86
+    opcode_entry!(ADD_A_A),                // 0x87
+    opcode_entry!(ADC_A_B),                // 0x88
+    opcode_entry!(ADC_A_C),                // 0x89
+    opcode_entry!(ADC_A_D),                // 0x8A
+    opcode_entry!(ADC_A_E),                // 0x8B
+    opcode_entry!(ADC_A_H),                // 0x8C
+    opcode_entry!(ADC_A_L),                // 0x8D
+    opcode_entry!(ADC_A_xHL),              // 0x8E
+    opcode_entry!(ADC_A_A),                // 0x8F
+    opcode_entry!(SUB_A_B),                // 0x90
+    opcode_entry!(SUB_A_C),                // 0x91
+    opcode_entry!(SUB_A_D),                // 0x92
+    opcode_entry!(SUB_A_E),                // 0x93
+    opcode_entry!(SUB_A_H),                // 0x94
+    opcode_entry!(SUB_A_L),                // 0x95
+    opcode_entry!(SUB_A_xHL),              // 0x96
+    opcode_entry!(SUB_A_A),                // 0x97
+    opcode_entry!(SBC_A_B),                // 0x98
+    opcode_entry!(SBC_A_C),                // 0x99
+    opcode_entry!(SBC_A_D),                // 0x9A
+    opcode_entry!(SBC_A_E),                // 0x9B
+    opcode_entry!(SBC_A_H),                // 0x9C
+    opcode_entry!(SBC_A_L),                // 0x9D
+    opcode_entry!(SBC_A_xHL),              // 0x9E
+    opcode_entry!(SBC_A_A),                // 0x9F
+    opcode_entry!(AND_A_B),                // 0xA0
+    opcode_entry!(AND_A_C),                // 0xA1
+    opcode_entry!(AND_A_D),                // 0xA2
+    opcode_entry!(AND_A_E),                // 0xA3
+    opcode_entry!(AND_A_H),                // 0xA4
+    opcode_entry!(AND_A_L),                // 0xA5
+    opcode_entry!(AND_A_xHL),              // 0xA6
+    opcode_entry!(AND_A_A),                // 0xA7
+    opcode_entry!(XOR_A_B),                // 0xA8
+    opcode_entry!(XOR_A_C),                // 0xA9
+    opcode_entry!(XOR_A_D),                // 0xAA
+    opcode_entry!(XOR_A_E),                // 0xAB
+    opcode_entry!(XOR_A_H),                // 0xAC
+    opcode_entry!(XOR_A_L),                // 0xAD
+    opcode_entry!(XOR_A_xHL),              // 0xAE
+    opcode_entry!(XOR_A_A),                // 0xAF
+    opcode_entry!(OR_A_B),                 // 0xB0
+    opcode_entry!(OR_A_C),                 // 0xB1
+    opcode_entry!(OR_A_D),                 // 0xB2
+    opcode_entry!(OR_A_E),                 // 0xB3
+    opcode_entry!(OR_A_H),                 // 0xB4
+    opcode_entry!(OR_A_L),                 // 0xB5
+    opcode_entry!(OR_A_xHL),               // 0xB6
+    opcode_entry!(OR_A_A),                 // 0xB7
+    opcode_entry!(CP_B),                   // 0xB8
+    opcode_entry!(CP_C),                   // 0xB9
+    opcode_entry!(CP_D),                   // 0xBA
+    opcode_entry!(CP_E),                   // 0xBB
+    opcode_entry!(CP_H),                   // 0xBC
+    opcode_entry!(CP_L),                   // 0xBD
+    opcode_entry!(CP_xHL),                 // 0xBE
+    opcode_entry!(CP_A),                   // 0xBF
+    opcode_entry!(RET_NZ),                 // 0xC0
+    opcode_entry!(POP_BC),                 // 0xC1
+    opcode_entry!(JP_NZ_NN),               // 0xC2
+    opcode_entry!(JP_NN),                  // 0xC3
+    opcode_entry!(CALL_NZ_NN),             // 0xC4
+    opcode_entry!(PUSH_BC),                // 0xC5
+    opcode_entry!(ADD_A_N),                // 0xC6
+    opcode_entry!(RST_0x00),               // 0xC7
+    opcode_entry!(RET_Z),                  // 0xC8
+    opcode_entry!(RET),                    // 0xC9
+    opcode_entry!(JP_Z_NN),                // 0xCA
+    extended_opcode_entry!(),              // 0xCB
+    opcode_entry!(CALL_Z_NN),              // 0xCC
+    opcode_entry!(CALL_NN),                // 0xCD
+    opcode_entry!(ADC_A_N),                // 0xCE
+    opcode_entry!(RST_0x08),               // 0xCF
+    opcode_entry!(RET_NC),                 // 0xD0
+    opcode_entry!(POP_DE),                 // 0xD1
+    opcode_entry!(JP_NC_NN),               // 0xD2
+    opcode_entry!(XX),                     // 0xD3
+    opcode_entry!(CALL_NC_NN),             // 0xD4
+    opcode_entry!(PUSH_DE),                // 0xD5
+    opcode_entry!(SUB_A_N),                // 0xD6
+    opcode_entry!(RST_0x10),               // 0xD7
+    opcode_entry!(RET_C),                  // 0xD8
+    opcode_entry!(RETI),                   // 0xD9
+    opcode_entry!(JP_C_NN),                // 0xDA
+    opcode_entry!(XX),                     // 0xDB
+    opcode_entry!(CALL_C_NN),              // 0xDC
+    opcode_entry!(XX),                     // 0xDD
+    opcode_entry!(SBC_A_N),                // 0xDE
+    opcode_entry!(RST_0x18),               // 0xDF
+    opcode_entry!(LDH_xN_A),               // 0xE0
+    opcode_entry!(POP_HL),                 // 0xE1
+    opcode_entry!(LDH_xC_A),               // 0xE2
+    opcode_entry!(XX),                     // 0xE3
+    opcode_entry!(XX),                     // 0xE4
+    opcode_entry!(PUSH_HL),                // 0xE5
+    opcode_entry!(AND_A_N),                // 0xE6
+    opcode_entry!(RST_0x20),               // 0xE7
+    opcode_entry!(ADD_SP_N),               // 0xE8
+    opcode_entry!(JP_xHL),                 // 0xE9
+    opcode_entry!(LD_xNN_A),               // 0xEA
+    opcode_entry!(XX),                     // 0xEB
+    opcode_entry!(XX),                     // 0xEC
+    opcode_entry!(XX),                     // 0xED
+    opcode_entry!(XOR_A_N),                // 0xEE
+    opcode_entry!(RST_0x28),               // 0xEF
+    opcode_entry!(LDH_A_xN),               // 0xF0
+    opcode_entry!(POP_AF),                 // 0xF1
+    opcode_entry!(LDH_A_xC),               // 0xF2  removed???
+    opcode_entry!(DI),                     // 0xF3
+    opcode_entry!(XX),                     // 0xF4
+    opcode_entry!(PUSH_AF),                // 0xF5
+    opcode_entry!(OR_A_N),                 // 0xF6
+    opcode_entry!(RST_0x30),               // 0xF7
+    opcode_entry!(LDHL_SP_N),              // 0xF8
+    opcode_entry!(LD_SP_HL),               // 0xF9
+    opcode_entry!(LD_A_xNN),               // 0xFA
+    opcode_entry!(EI),                     // 0xFB
+    opcode_entry!(XX),                     // 0xFC
+    opcode_entry!(XX),                     // 0xFD
+    opcode_entry!(CP_N),                   // 0xFE
+    opcode_entry!(RST_0x38),               // 0xFF
 ];
 
-static EXTENDED_OPCODE_MAP: [fn(opcode: Opcode, registers: &mut Registers, memory: &mut Memory); 256] =
+static EXTENDED_OPCODE_MAP: [OpcodeEntry; 256] =
 [
-    create_and_execute::<RLC_B>,     // 0x00
-    create_and_execute::<RLC_C>,     // 0x01
-    create_and_execute::<RLC_D>,     // 0x02
-    create_and_execute::<RLC_E>,     // 0x03
-    create_and_execute::<RLC_H>,     // 0x04
-    create_and_execute::<RLC_L>,     // 0x05
-    create_and_execute::<RLC_xHL>,   // 0x06
-    create_and_execute::<RLC_A>,     // 0x07
-    create_and_execute::<RRC_B>,     // 0x08
-    create_and_execute::<RRC_C>,     // 0x09
-    create_and_execute::<RRC_D>,     // 0x0A
-    create_and_execute::<RRC_E>,     // 0x0B
-    create_and_execute::<RRC_H>,     // 0x0C
-    create_and_execute::<RRC_L>,     // 0x0D
-    create_and_execute::<RRC_xHL>,   // 0x0E
-    create_and_execute::<RRC_A>,     // 0x0F
-    create_and_execute::<RL_B>,      // 0x10
-    create_and_execute::<RL_C>,      // 0x11
-    create_and_execute::<RL_D>,      // 0x12
-    create_and_execute::<RL_E>,      // 0x13
-    create_and_execute::<RL_H>,      // 0x14
-    create_and_execute::<RL_L>,      // 0x15
-    create_and_execute::<RL_xHL>,    // 0x16
-    create_and_execute::<RL_A>,      // 0x17
-    create_and_execute::<RR_B>,      // 0x18
-    create_and_execute::<RR_C>,      // 0x19
-    create_and_execute::<RR_D>,      // 0x1A
-    create_and_execute::<RR_E>,      // 0x1B
-    create_and_execute::<RR_H>,      // 0x1C
-    create_and_execute::<RR_L>,      // 0x1D
-    create_and_execute::<RR_xHL>,    // 0x1E
-    create_and_execute::<RR_A>,      // 0x1F
-    create_and_execute::<SLA_B>,     // 0x20
-    create_and_execute::<SLA_C>,     // 0x21
-    create_and_execute::<SLA_D>,     // 0x22
-    create_and_execute::<SLA_E>,     // 0x23
-    create_and_execute::<SLA_H>,     // 0x24
-    create_and_execute::<SLA_L>,     // 0x25
-    create_and_execute::<SLA_xHL>,   // 0x26
-    create_and_execute::<SLA_A>,     // 0x27
-    create_and_execute::<SRA_B>,     // 0x28
-    create_and_execute::<SRA_C>,     // 0x29
-    create_and_execute::<SRA_D>,     // 0x2A
-    create_and_execute::<SRA_E>,     // 0x2B
-    create_and_execute::<SRA_H>,     // 0x2C
-    create_and_execute::<SRA_L>,     // 0x2D
-    create_and_execute::<SRA_xHL>,   // 0x2E
-    create_and_execute::<SRA_A>,     // 0x2F
-    create_and_execute::<SWAP_B>,    // 0x30
-    create_and_execute::<SWAP_C>,    // 0x31
-    create_and_execute::<SWAP_D>,    // 0x32
-    create_and_execute::<SWAP_E>,    // 0x33
-    create_and_execute::<SWAP_H>,    // 0x34
-    create_and_execute::<SWAP_L>,    // 0x35
-    create_and_execute::<SWAP_xHL>,  // 0x36
-    create_and_execute::<SWAP_A>,    // 0x37
-    create_and_execute::<SRL_B>,     // 0x38
-    create_and_execute::<SRL_C>,     // 0x39
-    create_and_execute::<SRL_D>,     // 0x3A
-    create_and_execute::<SRL_E>,     // 0x3B
-    create_and_execute::<SRL_H>,     // 0x3C
-    create_and_execute::<SRL_L>,     // 0x3D
-    create_and_execute::<SRL_xHL>,   // 0x3E
-    create_and_execute::<SRL_A>,     // 0x3F
-    create_and_execute::<BIT_0_B>,   // 0x40
-    create_and_execute::<BIT_0_C>,   // 0x41
-    create_and_execute::<BIT_0_D>,   // 0x42
-    create_and_execute::<BIT_0_E>,   // 0x43
-    create_and_execute::<BIT_0_H>,   // 0x44
-    create_and_execute::<BIT_0_L>,   // 0x45
-    create_and_execute::<BIT_0_xHL>, // 0x46
-    create_and_execute::<BIT_0_A>,   // 0x47
-    create_and_execute::<BIT_1_B>,   // 0x48
-    create_and_execute::<BIT_1_C>,   // 0x49
-    create_and_execute::<BIT_1_D>,   // 0x4A
-    create_and_execute::<BIT_1_E>,   // 0x4B
-    create_and_execute::<BIT_1_H>,   // 0x4C
-    create_and_execute::<BIT_1_L>,   // 0x4D
-    create_and_execute::<BIT_1_xHL>, // 0x4E
-    create_and_execute::<BIT_1_A>,   // 0x4F
-    create_and_execute::<BIT_2_B>,   // 0x50
-    create_and_execute::<BIT_2_C>,   // 0x51
-    create_and_execute::<BIT_2_D>,   // 0x52
-    create_and_execute::<BIT_2_E>,   // 0x53
-    create_and_execute::<BIT_2_H>,   // 0x54
-    create_and_execute::<BIT_2_L>,   // 0x55
-    create_and_execute::<BIT_2_xHL>, // 0x56
-    create_and_execute::<BIT_2_A>,   // 0x57
-    create_and_execute::<BIT_3_B>,   // 0x58
-    create_and_execute::<BIT_3_C>,   // 0x59
-    create_and_execute::<BIT_3_D>,   // 0x5A
-    create_and_execute::<BIT_3_E>,   // 0x5B
-    create_and_execute::<BIT_3_H>,   // 0x5C
-    create_and_execute::<BIT_3_L>,   // 0x5D
-    create_and_execute::<BIT_3_xHL>, // 0x5E
-    create_and_execute::<BIT_3_A>,   // 0x5F
-    create_and_execute::<BIT_4_B>,   // 0x60
-    create_and_execute::<BIT_4_C>,   // 0x61
-    create_and_execute::<BIT_4_D>,   // 0x62
-    create_and_execute::<BIT_4_E>,   // 0x63
-    create_and_execute::<BIT_4_H>,   // 0x64
-    create_and_execute::<BIT_4_L>,   // 0x65
-    create_and_execute::<BIT_4_xHL>, // 0x66
-    create_and_execute::<BIT_4_A>,   // 0x67
-    create_and_execute::<BIT_5_B>,   // 0x68
-    create_and_execute::<BIT_5_C>,   // 0x69
-    create_and_execute::<BIT_5_D>,   // 0x6A
-    create_and_execute::<BIT_5_E>,   // 0x6B
-    create_and_execute::<BIT_5_H>,   // 0x6C
-    create_and_execute::<BIT_5_L>,   // 0x6D
-    create_and_execute::<BIT_5_xHL>, // 0x6E
-    create_and_execute::<BIT_5_A>,   // 0x6F
-    create_and_execute::<BIT_6_B>,   // 0x70
-    create_and_execute::<BIT_6_C>,   // 0x71
-    create_and_execute::<BIT_6_D>,   // 0x72
-    create_and_execute::<BIT_6_E>,   // 0x73
-    create_and_execute::<BIT_6_H>,   // 0x74
-    create_and_execute::<BIT_6_L>,   // 0x75
-    create_and_execute::<BIT_6_xHL>, // 0x76
-    create_and_execute::<BIT_6_A>,   // 0x77
-    create_and_execute::<BIT_7_B>,   // 0x78
-    create_and_execute::<BIT_7_C>,   // 0x79
-    create_and_execute::<BIT_7_D>,   // 0x7A
-    create_and_execute::<BIT_7_E>,   // 0x7B
-    create_and_execute::<BIT_7_H>,   // 0x7C
-    create_and_execute::<BIT_7_L>,   // 0x7D
-    create_and_execute::<BIT_7_xHL>, // 0x7E
-    create_and_execute::<BIT_7_A>,   // 0x7F
-    create_and_execute::<RES_0_B>,   // 0x80
-    create_and_execute::<RES_0_C>,   // 0x81
-    create_and_execute::<RES_0_D>,   // 0x82
-    create_and_execute::<RES_0_E>,   // 0x83
-    create_and_execute::<RES_0_H>,   // 0x84
-    create_and_execute::<RES_0_L>,   // 0x85
-    create_and_execute::<RES_0_xHL>, // 0x86
-    create_and_execute::<RES_0_A>,   // 0x87
-    create_and_execute::<RES_1_B>,   // 0x88
-    create_and_execute::<RES_1_C>,   // 0x89
-    create_and_execute::<RES_1_D>,   // 0x8A
-    create_and_execute::<RES_1_E>,   // 0x8B
-    create_and_execute::<RES_1_H>,   // 0x8C
-    create_and_execute::<RES_1_L>,   // 0x8D
-    create_and_execute::<RES_1_xHL>, // 0x8E
-    create_and_execute::<RES_1_A>,   // 0x8F
-    create_and_execute::<RES_2_B>,   // 0x90
-    create_and_execute::<RES_2_C>,   // 0x91
-    create_and_execute::<RES_2_D>,   // 0x92
-    create_and_execute::<RES_2_E>,   // 0x93
-    create_and_execute::<RES_2_H>,   // 0x94
-    create_and_execute::<RES_2_L>,   // 0x95
-    create_and_execute::<RES_2_xHL>, // 0x96
-    create_and_execute::<RES_2_A>,   // 0x97
-    create_and_execute::<RES_3_B>,   // 0x98
-    create_and_execute::<RES_3_C>,   // 0x99
-    create_and_execute::<RES_3_D>,   // 0x9A
-    create_and_execute::<RES_3_E>,   // 0x9B
-    create_and_execute::<RES_3_H>,   // 0x9C
-    create_and_execute::<RES_3_L>,   // 0x9D
-    create_and_execute::<RES_3_xHL>, // 0x9E
-    create_and_execute::<RES_3_A>,   // 0x9F
-    create_and_execute::<RES_4_B>,   // 0xA0
-    create_and_execute::<RES_4_C>,   // 0xA1
-    create_and_execute::<RES_4_D>,   // 0xA2
-    create_and_execute::<RES_4_E>,   // 0xA3
-    create_and_execute::<RES_4_H>,   // 0xA4
-    create_and_execute::<RES_4_L>,   // 0xA5
-    create_and_execute::<RES_4_xHL>, // 0xA6
-    create_and_execute::<RES_4_A>,   // 0xA7
-    create_and_execute::<RES_5_B>,   // 0xA8
-    create_and_execute::<RES_5_C>,   // 0xA9
-    create_and_execute::<RES_5_D>,   // 0xAA
-    create_and_execute::<RES_5_E>,   // 0xAB
-    create_and_execute::<RES_5_H>,   // 0xAC
-    create_and_execute::<RES_5_L>,   // 0xAD
-    create_and_execute::<RES_5_xHL>, // 0xAE
-    create_and_execute::<RES_5_A>,   // 0xAF
-    create_and_execute::<RES_6_B>,   // 0xB0
-    create_and_execute::<RES_6_C>,   // 0xB1
-    create_and_execute::<RES_6_D>,   // 0xB2
-    create_and_execute::<RES_6_E>,   // 0xB3
-    create_and_execute::<RES_6_H>,   // 0xB4
-    create_and_execute::<RES_6_L>,   // 0xB5
-    create_and_execute::<RES_6_xHL>, // 0xB6
-    create_and_execute::<RES_6_A>,   // 0xB7
-    create_and_execute::<RES_7_B>,   // 0xB8
-    create_and_execute::<RES_7_C>,   // 0xB9
-    create_and_execute::<RES_7_D>,   // 0xBA
-    create_and_execute::<RES_7_E>,   // 0xBB
-    create_and_execute::<RES_7_H>,   // 0xBC
-    create_and_execute::<RES_7_L>,   // 0xBD
-    create_and_execute::<RES_7_xHL>, // 0xBE
-    create_and_execute::<RES_7_A>,   // 0xBF
-    create_and_execute::<SET_0_B>,   // 0xC0
-    create_and_execute::<SET_0_C>,   // 0xC1
-    create_and_execute::<SET_0_D>,   // 0xC2
-    create_and_execute::<SET_0_E>,   // 0xC3
-    create_and_execute::<SET_0_H>,   // 0xC4
-    create_and_execute::<SET_0_L>,   // 0xC5
-    create_and_execute::<SET_0_xHL>, // 0xC6
-    create_and_execute::<SET_0_A>,   // 0xC7
-    create_and_execute::<SET_1_B>,   // 0xC8
-    create_and_execute::<SET_1_C>,   // 0xC9
-    create_and_execute::<SET_1_D>,   // 0xCA
-    create_and_execute::<SET_1_E>,   // 0xCB
-    create_and_execute::<SET_1_H>,   // 0xCC
-    create_and_execute::<SET_1_L>,   // 0xCD
-    create_and_execute::<SET_1_xHL>, // 0xCE
-    create_and_execute::<SET_1_A>,   // 0xCF
-    create_and_execute::<SET_2_B>,   // 0xD0
-    create_and_execute::<SET_2_C>,   // 0xD1
-    create_and_execute::<SET_2_D>,   // 0xD2
-    create_and_execute::<SET_2_E>,   // 0xD3
-    create_and_execute::<SET_2_H>,   // 0xD4
-    create_and_execute::<SET_2_L>,   // 0xD5
-    create_and_execute::<SET_2_xHL>, // 0xD6
-    create_and_execute::<SET_2_A>,   // 0xD7
-    create_and_execute::<SET_3_B>,   // 0xD8
-    create_and_execute::<SET_3_C>,   // 0xD9
-    create_and_execute::<SET_3_D>,   // 0xDA
-    create_and_execute::<SET_3_E>,   // 0xDB
-    create_and_execute::<SET_3_H>,   // 0xDC
-    create_and_execute::<SET_3_L>,   // 0xDD
-    create_and_execute::<SET_3_xHL>, // 0xDE
-    create_and_execute::<SET_3_A>,   // 0xDF
-    create_and_execute::<SET_4_B>,   // 0xE0
-    create_and_execute::<SET_4_C>,   // 0xE1
-    create_and_execute::<SET_4_D>,   // 0xE2
-    create_and_execute::<SET_4_E>,   // 0xE3
-    create_and_execute::<SET_4_H>,   // 0xE4
-    create_and_execute::<SET_4_L>,   // 0xE5
-    create_and_execute::<SET_4_xHL>, // 0xE6
-    create_and_execute::<SET_4_A>,   // 0xE7
-    create_and_execute::<SET_5_B>,   // 0xE8
-    create_and_execute::<SET_5_C>,   // 0xE9
-    create_and_execute::<SET_5_D>,   // 0xEA
-    create_and_execute::<SET_5_E>,   // 0xEB
-    create_and_execute::<SET_5_H>,   // 0xEC
-    create_and_execute::<SET_5_L>,   // 0xED
-    create_and_execute::<SET_5_xHL>, // 0xEE
-    create_and_execute::<SET_5_A>,   // 0xEF
-    create_and_execute::<SET_6_B>,   // 0xF0
-    create_and_execute::<SET_6_C>,   // 0xF1
-    create_and_execute::<SET_6_D>,   // 0xF2
-    create_and_execute::<SET_6_E>,   // 0xF3
-    create_and_execute::<SET_6_H>,   // 0xF4
-    create_and_execute::<SET_6_L>,   // 0xF5
-    create_and_execute::<SET_6_xHL>, // 0xF6
-    create_and_execute::<SET_6_A>,   // 0xF7
-    create_and_execute::<SET_7_B>,   // 0xF8
-    create_and_execute::<SET_7_C>,   // 0xF9
-    create_and_execute::<SET_7_D>,   // 0xFA
-    create_and_execute::<SET_7_E>,   // 0xFB
-    create_and_execute::<SET_7_H>,   // 0xFC
-    create_and_execute::<SET_7_L>,   // 0xFD
-    create_and_execute::<SET_7_xHL>, // 0xFE
-    create_and_execute::<SET_7_A>,   // 0xFF
+    opcode_entry!(RLC_B),            // 0x00
+    opcode_entry!(RLC_C),            // 0x01
+    opcode_entry!(RLC_D),            // 0x02
+    opcode_entry!(RLC_E),            // 0x03
+    opcode_entry!(RLC_H),            // 0x04
+    opcode_entry!(RLC_L),            // 0x05
+    opcode_entry!(RLC_xHL),          // 0x06
+    opcode_entry!(RLC_A),            // 0x07
+    opcode_entry!(RRC_B),            // 0x08
+    opcode_entry!(RRC_C),            // 0x09
+    opcode_entry!(RRC_D),            // 0x0A
+    opcode_entry!(RRC_E),            // 0x0B
+    opcode_entry!(RRC_H),            // 0x0C
+    opcode_entry!(RRC_L),            // 0x0D
+    opcode_entry!(RRC_xHL),          // 0x0E
+    opcode_entry!(RRC_A),            // 0x0F
+    opcode_entry!(RL_B),             // 0x10
+    opcode_entry!(RL_C),             // 0x11
+    opcode_entry!(RL_D),             // 0x12
+    opcode_entry!(RL_E),             // 0x13
+    opcode_entry!(RL_H),             // 0x14
+    opcode_entry!(RL_L),             // 0x15
+    opcode_entry!(RL_xHL),           // 0x16
+    opcode_entry!(RL_A),             // 0x17
+    opcode_entry!(RR_B),             // 0x18
+    opcode_entry!(RR_C),             // 0x19
+    opcode_entry!(RR_D),             // 0x1A
+    opcode_entry!(RR_E),             // 0x1B
+    opcode_entry!(RR_H),             // 0x1C
+    opcode_entry!(RR_L),             // 0x1D
+    opcode_entry!(RR_xHL),           // 0x1E
+    opcode_entry!(RR_A),             // 0x1F
+    opcode_entry!(SLA_B),            // 0x20
+    opcode_entry!(SLA_C),            // 0x21
+    opcode_entry!(SLA_D),            // 0x22
+    opcode_entry!(SLA_E),            // 0x23
+    opcode_entry!(SLA_H),            // 0x24
+    opcode_entry!(SLA_L),            // 0x25
+    opcode_entry!(SLA_xHL),          // 0x26
+    opcode_entry!(SLA_A),            // 0x27
+    opcode_entry!(SRA_B),            // 0x28
+    opcode_entry!(SRA_C),            // 0x29
+    opcode_entry!(SRA_D),            // 0x2A
+    opcode_entry!(SRA_E),            // 0x2B
+    opcode_entry!(SRA_H),            // 0x2C
+    opcode_entry!(SRA_L),            // 0x2D
+    opcode_entry!(SRA_xHL),          // 0x2E
+    opcode_entry!(SRA_A),            // 0x2F
+    opcode_entry!(SWAP_B),           // 0x30
+    opcode_entry!(SWAP_C),           // 0x31
+    opcode_entry!(SWAP_D),           // 0x32
+    opcode_entry!(SWAP_E),           // 0x33
+    opcode_entry!(SWAP_H),           // 0x34
+    opcode_entry!(SWAP_L),           // 0x35
+    opcode_entry!(SWAP_xHL),         // 0x36
+    opcode_entry!(SWAP_A),           // 0x37
+    opcode_entry!(SRL_B),            // 0x38
+    opcode_entry!(SRL_C),            // 0x39
+    opcode_entry!(SRL_D),            // 0x3A
+    opcode_entry!(SRL_E),            // 0x3B
+    opcode_entry!(SRL_H),            // 0x3C
+    opcode_entry!(SRL_L),            // 0x3D
+    opcode_entry!(SRL_xHL),          // 0x3E
+    opcode_entry!(SRL_A),            // 0x3F
+    opcode_entry!(BIT_0_B),          // 0x40
+    opcode_entry!(BIT_0_C),          // 0x41
+    opcode_entry!(BIT_0_D),          // 0x42
+    opcode_entry!(BIT_0_E),          // 0x43
+    opcode_entry!(BIT_0_H),          // 0x44
+    opcode_entry!(BIT_0_L),          // 0x45
+    opcode_entry!(BIT_0_xHL),        // 0x46
+    opcode_entry!(BIT_0_A),          // 0x47
+    opcode_entry!(BIT_1_B),          // 0x48
+    opcode_entry!(BIT_1_C),          // 0x49
+    opcode_entry!(BIT_1_D),          // 0x4A
+    opcode_entry!(BIT_1_E),          // 0x4B
+    opcode_entry!(BIT_1_H),          // 0x4C
+    opcode_entry!(BIT_1_L),          // 0x4D
+    opcode_entry!(BIT_1_xHL),        // 0x4E
+    opcode_entry!(BIT_1_A),          // 0x4F
+    opcode_entry!(BIT_2_B),          // 0x50
+    opcode_entry!(BIT_2_C),          // 0x51
+    opcode_entry!(BIT_2_D),          // 0x52
+    opcode_entry!(BIT_2_E),          // 0x53
+    opcode_entry!(BIT_2_H),          // 0x54
+    opcode_entry!(BIT_2_L),          // 0x55
+    opcode_entry!(BIT_2_xHL),        // 0x56
+    opcode_entry!(BIT_2_A),          // 0x57
+    opcode_entry!(BIT_3_B),          // 0x58
+    opcode_entry!(BIT_3_C),          // 0x59
+    opcode_entry!(BIT_3_D),          // 0x5A
+    opcode_entry!(BIT_3_E),          // 0x5B
+    opcode_entry!(BIT_3_H),          // 0x5C
+    opcode_entry!(BIT_3_L),          // 0x5D
+    opcode_entry!(BIT_3_xHL),        // 0x5E
+    opcode_entry!(BIT_3_A),          // 0x5F
+    opcode_entry!(BIT_4_B),          // 0x60
+    opcode_entry!(BIT_4_C),          // 0x61
+    opcode_entry!(BIT_4_D),          // 0x62
+    opcode_entry!(BIT_4_E),          // 0x63
+    opcode_entry!(BIT_4_H),          // 0x64
+    opcode_entry!(BIT_4_L),          // 0x65
+    opcode_entry!(BIT_4_xHL),        // 0x66
+    opcode_entry!(BIT_4_A),          // 0x67
+    opcode_entry!(BIT_5_B),          // 0x68
+    opcode_entry!(BIT_5_C),          // 0x69
+    opcode_entry!(BIT_5_D),          // 0x6A
+    opcode_entry!(BIT_5_E),          // 0x6B
+    opcode_entry!(BIT_5_H),          // 0x6C
+    opcode_entry!(BIT_5_L),          // 0x6D
+    opcode_entry!(BIT_5_xHL),        // 0x6E
+    opcode_entry!(BIT_5_A),          // 0x6F
+    opcode_entry!(BIT_6_B),          // 0x70
+    opcode_entry!(BIT_6_C),          // 0x71
+    opcode_entry!(BIT_6_D),          // 0x72
+    opcode_entry!(BIT_6_E),          // 0x73
+    opcode_entry!(BIT_6_H),          // 0x74
+    opcode_entry!(BIT_6_L),          // 0x75
+    opcode_entry!(BIT_6_xHL),        // 0x76
+    opcode_entry!(BIT_6_A),          // 0x77
+    opcode_entry!(BIT_7_B),          // 0x78
+    opcode_entry!(BIT_7_C),          // 0x79
+    opcode_entry!(BIT_7_D),          // 0x7A
+    opcode_entry!(BIT_7_E),          // 0x7B
+    opcode_entry!(BIT_7_H),          // 0x7C
+    opcode_entry!(BIT_7_L),          // 0x7D
+    opcode_entry!(BIT_7_xHL),        // 0x7E
+    opcode_entry!(BIT_7_A),          // 0x7F
+    opcode_entry!(RES_0_B),          // 0x80
+    opcode_entry!(RES_0_C),          // 0x81
+    opcode_entry!(RES_0_D),          // 0x82
+    opcode_entry!(RES_0_E),          // 0x83
+    opcode_entry!(RES_0_H),          // 0x84
+    opcode_entry!(RES_0_L),          // 0x85
+    opcode_entry!(RES_0_xHL),        // 0x86
+    opcode_entry!(RES_0_A),          // 0x87
+    opcode_entry!(RES_1_B),          // 0x88
+    opcode_entry!(RES_1_C),          // 0x89
+    opcode_entry!(RES_1_D),          // 0x8A
+    opcode_entry!(RES_1_E),          // 0x8B
+    opcode_entry!(RES_1_H),          // 0x8C
+    opcode_entry!(RES_1_L),          // 0x8D
+    opcode_entry!(RES_1_xHL),        // 0x8E
+    opcode_entry!(RES_1_A),          // 0x8F
+    opcode_entry!(RES_2_B),          // 0x90
+    opcode_entry!(RES_2_C),          // 0x91
+    opcode_entry!(RES_2_D),          // 0x92
+    opcode_entry!(RES_2_E),          // 0x93
+    opcode_entry!(RES_2_H),          // 0x94
+    opcode_entry!(RES_2_L),          // 0x95
+    opcode_entry!(RES_2_xHL),        // 0x96
+    opcode_entry!(RES_2_A),          // 0x97
+    opcode_entry!(RES_3_B),          // 0x98
+    opcode_entry!(RES_3_C),          // 0x99
+    opcode_entry!(RES_3_D),          // 0x9A
+    opcode_entry!(RES_3_E),          // 0x9B
+    opcode_entry!(RES_3_H),          // 0x9C
+    opcode_entry!(RES_3_L),          // 0x9D
+    opcode_entry!(RES_3_xHL),        // 0x9E
+    opcode_entry!(RES_3_A),          // 0x9F
+    opcode_entry!(RES_4_B),          // 0xA0
+    opcode_entry!(RES_4_C),          // 0xA1
+    opcode_entry!(RES_4_D),          // 0xA2
+    opcode_entry!(RES_4_E),          // 0xA3
+    opcode_entry!(RES_4_H),          // 0xA4
+    opcode_entry!(RES_4_L),          // 0xA5
+    opcode_entry!(RES_4_xHL),        // 0xA6
+    opcode_entry!(RES_4_A),          // 0xA7
+    opcode_entry!(RES_5_B),          // 0xA8
+    opcode_entry!(RES_5_C),          // 0xA9
+    opcode_entry!(RES_5_D),          // 0xAA
+    opcode_entry!(RES_5_E),          // 0xAB
+    opcode_entry!(RES_5_H),          // 0xAC
+    opcode_entry!(RES_5_L),          // 0xAD
+    opcode_entry!(RES_5_xHL),        // 0xAE
+    opcode_entry!(RES_5_A),          // 0xAF
+    opcode_entry!(RES_6_B),          // 0xB0
+    opcode_entry!(RES_6_C),          // 0xB1
+    opcode_entry!(RES_6_D),          // 0xB2
+    opcode_entry!(RES_6_E),          // 0xB3
+    opcode_entry!(RES_6_H),          // 0xB4
+    opcode_entry!(RES_6_L),          // 0xB5
+    opcode_entry!(RES_6_xHL),        // 0xB6
+    opcode_entry!(RES_6_A),          // 0xB7
+    opcode_entry!(RES_7_B),          // 0xB8
+    opcode_entry!(RES_7_C),          // 0xB9
+    opcode_entry!(RES_7_D),          // 0xBA
+    opcode_entry!(RES_7_E),          // 0xBB
+    opcode_entry!(RES_7_H),          // 0xBC
+    opcode_entry!(RES_7_L),          // 0xBD
+    opcode_entry!(RES_7_xHL),        // 0xBE
+    opcode_entry!(RES_7_A),          // 0xBF
+    opcode_entry!(SET_0_B),          // 0xC0
+    opcode_entry!(SET_0_C),          // 0xC1
+    opcode_entry!(SET_0_D),          // 0xC2
+    opcode_entry!(SET_0_E),          // 0xC3
+    opcode_entry!(SET_0_H),          // 0xC4
+    opcode_entry!(SET_0_L),          // 0xC5
+    opcode_entry!(SET_0_xHL),        // 0xC6
+    opcode_entry!(SET_0_A),          // 0xC7
+    opcode_entry!(SET_1_B),          // 0xC8
+    opcode_entry!(SET_1_C),          // 0xC9
+    opcode_entry!(SET_1_D),          // 0xCA
+    opcode_entry!(SET_1_E),          // 0xCB
+    opcode_entry!(SET_1_H),          // 0xCC
+    opcode_entry!(SET_1_L),          // 0xCD
+    opcode_entry!(SET_1_xHL),        // 0xCE
+    opcode_entry!(SET_1_A),          // 0xCF
+    opcode_entry!(SET_2_B),          // 0xD0
+    opcode_entry!(SET_2_C),          // 0xD1
+    opcode_entry!(SET_2_D),          // 0xD2
+    opcode_entry!(SET_2_E),          // 0xD3
+    opcode_entry!(SET_2_H),          // 0xD4
+    opcode_entry!(SET_2_L),          // 0xD5
+    opcode_entry!(SET_2_xHL),        // 0xD6
+    opcode_entry!(SET_2_A),          // 0xD7
+    opcode_entry!(SET_3_B),          // 0xD8
+    opcode_entry!(SET_3_C),          // 0xD9
+    opcode_entry!(SET_3_D),          // 0xDA
+    opcode_entry!(SET_3_E),          // 0xDB
+    opcode_entry!(SET_3_H),          // 0xDC
+    opcode_entry!(SET_3_L),          // 0xDD
+    opcode_entry!(SET_3_xHL),        // 0xDE
+    opcode_entry!(SET_3_A),          // 0xDF
+    opcode_entry!(SET_4_B),          // 0xE0
+    opcode_entry!(SET_4_C),          // 0xE1
+    opcode_entry!(SET_4_D),          // 0xE2
+    opcode_entry!(SET_4_E),          // 0xE3
+    opcode_entry!(SET_4_H),          // 0xE4
+    opcode_entry!(SET_4_L),          // 0xE5
+    opcode_entry!(SET_4_xHL),        // 0xE6
+    opcode_entry!(SET_4_A),          // 0xE7
+    opcode_entry!(SET_5_B),          // 0xE8
+    opcode_entry!(SET_5_C),          // 0xE9
+    opcode_entry!(SET_5_D),          // 0xEA
+    opcode_entry!(SET_5_E),          // 0xEB
+    opcode_entry!(SET_5_H),          // 0xEC
+    opcode_entry!(SET_5_L),          // 0xED
+    opcode_entry!(SET_5_xHL),        // 0xEE
+    opcode_entry!(SET_5_A),          // 0xEF
+    opcode_entry!(SET_6_B),          // 0xF0
+    opcode_entry!(SET_6_C),          // 0xF1
+    opcode_entry!(SET_6_D),          // 0xF2
+    opcode_entry!(SET_6_E),          // 0xF3
+    opcode_entry!(SET_6_H),          // 0xF4
+    opcode_entry!(SET_6_L),          // 0xF5
+    opcode_entry!(SET_6_xHL),        // 0xF6
+    opcode_entry!(SET_6_A),          // 0xF7
+    opcode_entry!(SET_7_B),          // 0xF8
+    opcode_entry!(SET_7_C),          // 0xF9
+    opcode_entry!(SET_7_D),          // 0xFA
+    opcode_entry!(SET_7_E),          // 0xFB
+    opcode_entry!(SET_7_H),          // 0xFC
+    opcode_entry!(SET_7_L),          // 0xFD
+    opcode_entry!(SET_7_xHL),        // 0xFE
+    opcode_entry!(SET_7_A),          // 0xFF
+];
+
+// Length of each command in bytes, including the opcode. All extended
+// commands are two bytes long.
+static INSTRUCTION_LENGTHS: [u8; 256] = [
+//  x0 x1 x2 x3 x4 x5 x6 x7 x8 x9 xA xB xC xD xE xF
+    1, 3, 1, 1, 1, 1, 2, 1, 3, 1, 1, 1, 1, 1, 2, 1, // 0x
+    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 1x
+    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 2x
+    2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, // 3x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 4x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 5x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 6x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 7x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 8x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 9x
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // Ax
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // Bx
+    1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 2, 3, 3, 2, 1, // Cx
+    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 1, 2, 1, // Dx
+    2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, // Ex
+    2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, // Fx
 ];
 
+// Reads the opcode at PC and as many operand bytes as the command needs
+fn fetch_opcode(registers: &mut Registers, memory: &Memory) -> Opcode {
+    let pc = registers.pc;
+    let mut operand_address = pc.wrapping_add(1);
+    if registers.halt_bug {
+        // PC fails to increment after the opcode is read, so the byte
+        // following HALT is read twice
+        registers.halt_bug = false;
+        registers.pc = pc.wrapping_sub(1);
+        operand_address = pc;
+    }
+    let b1 = memory.read_byte_as(pc, ReadKind::Opcode);
+    let length = INSTRUCTION_LENGTHS[b1 as usize];
+    let b2 = if length > 1 { memory.read_byte_as(operand_address, ReadKind::Operand) } else { 0 };
+    let b3 = if length > 2 { memory.read_byte_as(operand_address.wrapping_add(1), ReadKind::Operand) } else { 0 };
+    Opcode { b1, b2, b3 }
+}
+
 fn create_and_execute<Op: OpConstruct + OpExecute>(
     opcode: Opcode, registers: &mut Registers, memory: &mut Memory) {
     let op = Op::new(opcode);
@@ -650,7 +1120,7 @@ fn create_and_execute<Op: OpConstruct + OpExecute>(
 
 fn execute_extended_opcode(
     opcode: Opcode, registers: &mut Registers, memory: &mut Memory) {
-    let opcode_function = EXTENDED_OPCODE_MAP[opcode.b2 as usize];
+    let opcode_function = EXTENDED_OPCODE_MAP[opcode.b2 as usize].execute;
     opcode_function(opcode, registers, memory);
 }
 
@@ -661,6 +1131,7 @@ struct Opcode {
     b3: u8,
 }
 
+#[derive(Clone)]
 struct Registers {
     a: u8,
     f: u8,
@@ -679,8 +1150,14 @@ struct Registers {
 
     cycles_of_last_command: u8,
     interrupt_master_enable: bool,
+    enable_interrupts_pending: bool,
 
     halt: bool,
+    halt_bug: bool,
+    stop: bool,
+    speed_switch_cycles_left: u16,
+    // Address of the illegal opcode that locked up the CPU
+    locked_pc: Option<u16>,
 }
 
 macro_rules! generate_flag_getter_and_setter {
@@ -714,7 +1191,12 @@ impl Registers {
             sp: 0xFFFE,
             cycles_of_last_command: 0,
             interrupt_master_enable: false,
+            enable_interrupts_pending: false,
             halt: false,
+            halt_bug: false,
+            stop: false,
+            speed_switch_cycles_left: 0,
+            locked_pc: None,
         }
     }
 
@@ -751,6 +1233,14 @@ fn increment_register_pair(h: &mut u8, l: &mut u8) {
     store_value_in_register_pair(value, h, l);
 }
 
+// Pushes a word onto the stack. The high byte is written first.
+fn push_word(value: u16, registers: &mut Registers, memory: &mut Memory) {
+    registers.sp = registers.sp.wrapping_sub(1);
+    memory.write_byte(registers.sp, ((value & 0xFF00) >> 8) as u8);
+    registers.sp = registers.sp.wrapping_sub(1);
+    memory.write_byte(registers.sp, (value & 0xFF) as u8);
+}
+
 fn test_carry_u16(a: u16, b: u16) -> (bool, bool) {
     let carry_mask = 0xFF;
     let halfcarry_mask = 0xF;
@@ -1125,9 +1615,10 @@ macro_rules! push_rr {
         create_opcode_struct!($name);
         impl OpExecute for $name {
             fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
-                registers.sp -= 2;
                 let value = to_u16(registers.$reg_high, registers.$reg_low);
-                memory.write_word(registers.sp, value);
+                // Internal delay before the stack is written
+                memory.step(4);
+                push_word(value, registers, memory);
                 registers.pc += 1;
                 registers.cycles_of_last_command = 16;
             }
@@ -2050,22 +2541,23 @@ impl OpExecute for HALT {
     }
 }
 
-// Halt CPU & LCD display until button pressed
+// Halt CPU & LCD display until button pressed. On CGB, switch CPU speed
+// instead if a speed switch has been prepared. See Cpu::enter_stop.
 create_opcode_struct!(STOP);
 impl OpExecute for STOP {
     fn execute(&self, registers: &mut Registers, _memory: &mut Memory) {
-        //TODO: Implement
-        println!("STOP is not yet implemented!");
+        registers.stop = true;
         registers.pc += 2;
         registers.cycles_of_last_command = 4;
     }
 }
 
-// Disables interrupts after the next instruction is executed
+// Disables interrupts immediately, also cancelling a preceding EI
 create_opcode_struct!(DI);
 impl OpExecute for DI {
     fn execute(&self, registers: &mut Registers, _memory: &mut Memory) {
         registers.interrupt_master_enable = false;
+        registers.enable_interrupts_pending = false;
         registers.pc += 1;
         registers.cycles_of_last_command = 4;
     }
@@ -2075,7 +2567,7 @@ impl OpExecute for DI {
 create_opcode_struct!(EI);
 impl OpExecute for EI {
     fn execute(&self, registers: &mut Registers, _memory: &mut Memory) {
-        registers.interrupt_master_enable = true;
+        registers.enable_interrupts_pending = true;
         registers.pc += 1;
         registers.cycles_of_last_command = 4;
     }
@@ -2576,7 +3068,7 @@ macro_rules! bit_b_hl {
                 registers.set_operation(false);
                 registers.set_halfcarry(true);
                 registers.pc += 2;
-                registers.cycles_of_last_command = 16;
+                registers.cycles_of_last_command = 12;
             }
         }
     )*}
@@ -2802,7 +3294,7 @@ impl OpExecute for JP_NN {
     fn execute(&self, registers: &mut Registers, _memory: &mut Memory) {
         let address = to_u16(self._b3, self._b2);
         registers.pc = address;
-        registers.cycles_of_last_command = 12;
+        registers.cycles_of_last_command = 16;
     }
 }
 
@@ -2813,10 +3305,11 @@ impl OpExecute for JP_NZ_NN {
         if !registers.get_zero() {
             let address = to_u16(self._b3, self._b2);
             registers.pc = address;
+            registers.cycles_of_last_command = 16;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2827,10 +3320,11 @@ impl OpExecute for JP_Z_NN {
         if registers.get_zero() {
             let address = to_u16(self._b3, self._b2);
             registers.pc = address;
+            registers.cycles_of_last_command = 16;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2841,10 +3335,11 @@ impl OpExecute for JP_NC_NN {
         if !registers.get_carry() {
             let address = to_u16(self._b3, self._b2);
             registers.pc = address;
+            registers.cycles_of_last_command = 16;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2855,10 +3350,11 @@ impl OpExecute for JP_C_NN {
         if registers.get_carry() {
             let address = to_u16(self._b3, self._b2);
             registers.pc = address;
+            registers.cycles_of_last_command = 16;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2878,7 +3374,7 @@ impl OpExecute for JR_N {
     fn execute(&self, registers: &mut Registers, _memory: &mut Memory) {
         registers.pc += 2;
         registers.pc = ((registers.pc as i32) + self._b2 as i8 as i32) as u16;
-        registers.cycles_of_last_command = 8;
+        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2889,8 +3385,10 @@ impl OpExecute for JR_NZ_N {
         registers.pc += 2;
         if !registers.get_zero() {
             registers.pc = ((registers.pc as i32) + self._b2 as i8 as i32) as u16;
+            registers.cycles_of_last_command = 12;
+        } else {
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -2901,8 +3399,10 @@ impl OpExecute for JR_Z_N {
         registers.pc += 2;
         if registers.get_zero() {
             registers.pc = ((registers.pc as i32) + self._b2 as i8 as i32) as u16;
+            registers.cycles_of_last_command = 12;
+        } else {
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -2913,8 +3413,10 @@ impl OpExecute for JR_NC_N {
         registers.pc += 2;
         if !registers.get_carry() {
             registers.pc = ((registers.pc as i32) + self._b2 as i8 as i32) as u16;
+            registers.cycles_of_last_command = 12;
+        } else {
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -2925,8 +3427,10 @@ impl OpExecute for JR_C_N {
         registers.pc += 2;
         if registers.get_carry() {
             registers.pc = ((registers.pc as i32) + self._b2 as i8 as i32) as u16;
+            registers.cycles_of_last_command = 12;
+        } else {
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -2935,10 +3439,12 @@ create_opcode_struct!(CALL_NN);
 impl OpExecute for CALL_NN {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
         let address = to_u16(self._b3, self._b2);
-        registers.sp -= 2;
-        memory.write_word(registers.sp, registers.pc + 3);
+        let return_address = registers.pc + 3;
+        // Internal delay before the return address is pushed
+        memory.step(4);
+        push_word(return_address, registers, memory);
         registers.pc = address;
-        registers.cycles_of_last_command = 12;
+        registers.cycles_of_last_command = 24;
     }
 }
 
@@ -2948,13 +3454,15 @@ impl OpExecute for CALL_NZ_NN {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
         if !registers.get_zero() {
             let address = to_u16(self._b3, self._b2);
-            registers.sp -= 2;
-            memory.write_word(registers.sp, registers.pc + 3);
+            let return_address = registers.pc + 3;
+            memory.step(4);
+            push_word(return_address, registers, memory);
             registers.pc = address;
+            registers.cycles_of_last_command = 24;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2964,13 +3472,15 @@ impl OpExecute for CALL_Z_NN {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
         if registers.get_zero() {
             let address = to_u16(self._b3, self._b2);
-            registers.sp -= 2;
-            memory.write_word(registers.sp, registers.pc + 3);
+            let return_address = registers.pc + 3;
+            memory.step(4);
+            push_word(return_address, registers, memory);
             registers.pc = address;
+            registers.cycles_of_last_command = 24;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2980,13 +3490,15 @@ impl OpExecute for CALL_NC_NN {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
         if !registers.get_carry() {
             let address = to_u16(self._b3, self._b2);
-            registers.sp -= 2;
-            memory.write_word(registers.sp, registers.pc + 3);
+            let return_address = registers.pc + 3;
+            memory.step(4);
+            push_word(return_address, registers, memory);
             registers.pc = address;
+            registers.cycles_of_last_command = 24;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -2996,13 +3508,15 @@ impl OpExecute for CALL_C_NN {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
         if registers.get_carry() {
             let address = to_u16(self._b3, self._b2);
-            registers.sp -= 2;
-            memory.write_word(registers.sp, registers.pc + 3);
+            let return_address = registers.pc + 3;
+            memory.step(4);
+            push_word(return_address, registers, memory);
             registers.pc = address;
+            registers.cycles_of_last_command = 24;
         } else {
             registers.pc += 3;
+            registers.cycles_of_last_command = 12;
         }
-        registers.cycles_of_last_command = 12;
     }
 }
 
@@ -3011,10 +3525,12 @@ macro_rules! rst_n {
         create_opcode_struct!($name);
         impl OpExecute for $name {
             fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
-                registers.sp -= 2;
-                memory.write_word(registers.sp, registers.pc + 1);
+                let return_address = registers.pc + 1;
+                // Internal delay before the return address is pushed
+                memory.step(4);
+                push_word(return_address, registers, memory);
                 registers.pc = $address;
-                registers.cycles_of_last_command = 32;
+                registers.cycles_of_last_command = 16;
             }
         }
     )*}
@@ -3037,7 +3553,7 @@ impl OpExecute for RET {
         let address = memory.read_word(registers.sp);
         registers.sp += 2;
         registers.pc = address;
-        registers.cycles_of_last_command = 8;
+        registers.cycles_of_last_command = 16;
     }
 }
 
@@ -3045,14 +3561,17 @@ impl OpExecute for RET {
 create_opcode_struct!(RET_NZ);
 impl OpExecute for RET_NZ {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
+        // Internal delay for checking the condition
+        memory.step(4);
         if !registers.get_zero() {
             let address = memory.read_word(registers.sp);
             registers.sp += 2;
             registers.pc = address;
+            registers.cycles_of_last_command = 20;
         } else {
             registers.pc += 1;
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -3060,14 +3579,17 @@ impl OpExecute for RET_NZ {
 create_opcode_struct!(RET_Z);
 impl OpExecute for RET_Z {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
+        // Internal delay for checking the condition
+        memory.step(4);
         if registers.get_zero() {
             let address = memory.read_word(registers.sp);
             registers.sp += 2;
             registers.pc = address;
+            registers.cycles_of_last_command = 20;
         } else {
             registers.pc += 1;
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -3075,14 +3597,17 @@ impl OpExecute for RET_Z {
 create_opcode_struct!(RET_NC);
 impl OpExecute for RET_NC {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
+        // Internal delay for checking the condition
+        memory.step(4);
         if !registers.get_carry() {
             let address = memory.read_word(registers.sp);
             registers.sp += 2;
             registers.pc = address;
+            registers.cycles_of_last_command = 20;
         } else {
             registers.pc += 1;
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
@@ -3090,33 +3615,38 @@ impl OpExecute for RET_NC {
 create_opcode_struct!(RET_C);
 impl OpExecute for RET_C {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
+        // Internal delay for checking the condition
+        memory.step(4);
         if registers.get_carry() {
             let address = memory.read_word(registers.sp);
             registers.sp += 2;
             registers.pc = address;
+            registers.cycles_of_last_command = 20;
         } else {
             registers.pc += 1;
+            registers.cycles_of_last_command = 8;
         }
-        registers.cycles_of_last_command = 8;
     }
 }
 
-// Return, then enable interrupts
+// Return, then enable interrupts. Unlike EI, this takes effect immediately.
 create_opcode_struct!(RETI);
 impl OpExecute for RETI {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory) {
         registers.interrupt_master_enable = true;
+        registers.enable_interrupts_pending = false;
         let address = memory.read_word(registers.sp);
         registers.sp += 2;
         registers.pc = address;
-        registers.cycles_of_last_command = 8;
+        registers.cycles_of_last_command = 16;
     }
 }
 
-// Unused operation
+// Illegal operation, locks up the CPU until it is reset
 create_opcode_struct!(XX);
 impl OpExecute for XX {
-    fn execute(&self, _registers: &mut Registers, _memory: &mut Memory) {
-        panic!("Tried to call unused opcode");
+    fn execute(&self, registers: &mut Registers, _memory: &mut Memory) {
+        registers.locked_pc = Some(registers.pc);
+        registers.cycles_of_last_command = 4;
     }
 }
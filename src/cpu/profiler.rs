@@ -0,0 +1,223 @@
+use memory::Memory;
+use symbols::Symbols;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+// Calls nested deeper than this are attributed to the deepest function, so
+// that code which never returns does not grow the call tree forever
+const MAX_CALL_DEPTH: usize = 64;
+
+// A code location: ROM bank (None outside of the cartridge ROM) and address
+pub type Location = (Option<u16>, u16);
+
+fn format_location(location: Location) -> String {
+    match location.0 {
+        Some(bank) => format!("{:02X}:{:04X}", bank, location.1),
+        None => format!("--:{:04X}", location.1),
+    }
+}
+
+fn describe_location(location: Location, symbols: &Symbols) -> String {
+    symbols.describe(location.0, location.1).unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Counter {
+    instructions: u64,
+    cycles: u64,
+}
+
+// Node of the call tree. The root node stands for code outside of any
+// tracked call.
+struct CallNode {
+    function: Option<Location>,
+    parent: usize,
+    children: HashMap<Location, usize>,
+    calls: u64,
+    cycles: u64,
+}
+
+// Counts executed commands and cycles per location and attributes them to
+// the called functions by following CALL, RST, RET and interrupts
+pub struct Profiler {
+    locations: HashMap<Location, Counter>,
+    nodes: Vec<CallNode>,
+    current_node: usize,
+    depth: usize,
+    // Calls beyond the maximum depth, whose returns must not leave a node
+    overflow_depth: usize,
+    halted_cycles: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            locations: HashMap::new(),
+            nodes: vec![CallNode {
+                function: None,
+                parent: 0,
+                children: HashMap::new(),
+                calls: 0,
+                cycles: 0,
+            }],
+            current_node: 0,
+            depth: 0,
+            overflow_depth: 0,
+            halted_cycles: 0,
+        }
+    }
+
+    // Records an executed command. The stack pointer before and after the
+    // command tells whether a conditional call or return was taken.
+    pub fn record_command(&mut self, location: Location, opcode: u8, sp_before: u16,
+                          sp_after: u16, pc_after: u16, cycles: u8, memory: &Memory) {
+        let counter = self.locations.entry(location).or_insert_with(Counter::default);
+        counter.instructions += 1;
+        counter.cycles += cycles as u64;
+        self.nodes[self.current_node].cycles += cycles as u64;
+        match opcode {
+            // CALL and RST
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC |
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                if sp_after == sp_before.wrapping_sub(2) {
+                    self.enter((memory.rom_bank(pc_after), pc_after));
+                }
+            }
+            // RET and RETI
+            0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9 => {
+                if sp_after == sp_before.wrapping_add(2) {
+                    self.leave();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn record_interrupt(&mut self, handler: Location, cycles: u8) {
+        self.enter(handler);
+        self.nodes[self.current_node].cycles += cycles as u64;
+    }
+
+    pub fn record_halted(&mut self, cycles: u8) {
+        self.halted_cycles += cycles as u64;
+    }
+
+    fn enter(&mut self, function: Location) {
+        if self.depth >= MAX_CALL_DEPTH {
+            self.overflow_depth += 1;
+            return;
+        }
+        let parent = self.current_node;
+        let next_index = self.nodes.len();
+        let index = *self.nodes[parent].children.entry(function).or_insert(next_index);
+        if index == next_index {
+            self.nodes.push(CallNode {
+                function: Some(function),
+                parent,
+                children: HashMap::new(),
+                calls: 0,
+                cycles: 0,
+            });
+        }
+        self.nodes[index].calls += 1;
+        self.current_node = index;
+        self.depth += 1;
+    }
+
+    fn leave(&mut self) {
+        if self.overflow_depth > 0 {
+            self.overflow_depth -= 1;
+        } else if self.depth > 0 {
+            self.current_node = self.nodes[self.current_node].parent;
+            self.depth -= 1;
+        }
+    }
+
+    fn stack(&self, node: usize) -> Vec<Location> {
+        let mut stack = Vec::new();
+        let mut node = node;
+        while node != 0 {
+            stack.push(self.nodes[node].function.unwrap());
+            node = self.nodes[node].parent;
+        }
+        stack.reverse();
+        stack
+    }
+
+    // Writes the hot spots sorted by cycles and the called functions sorted
+    // by cycles including their callees, labeled with the given symbols
+    pub fn write_report(&self, output: &mut Write, symbols: &Symbols) {
+        // The nodes also hold the cycles of interrupt dispatches
+        let total_cycles: u64 = self.nodes.iter().map(|node| node.cycles).sum();
+        let percentage = |cycles: u64| 100.0 * cycles as f64 / total_cycles.max(1) as f64;
+        let mut locations: Vec<(&Location, &Counter)> = self.locations.iter().collect();
+        locations.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+
+        // Functions are listed with calls, own cycles and inclusive cycles
+        let mut functions: HashMap<Location, (u64, u64, u64)> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            let stack = self.stack(index);
+            let function = functions.entry(node.function.unwrap()).or_insert((0, 0, 0));
+            function.0 += node.calls;
+            function.1 += node.cycles;
+            // Recursive calls are only counted once for inclusive cycles
+            for (depth, location) in stack.iter().enumerate() {
+                if !stack[..depth].contains(location) {
+                    functions.entry(*location).or_insert((0, 0, 0)).2 += node.cycles;
+                }
+            }
+        }
+        let mut functions: Vec<(Location, (u64, u64, u64))> = functions.into_iter().collect();
+        functions.sort_by(|a, b| (b.1).2.cmp(&(a.1).2).then(a.0.cmp(&b.0)));
+
+        let result = (|| -> ::std::io::Result<()> {
+            writeln!(output, "Total cycles: {} (plus {} halted)", total_cycles, self.halted_cycles)?;
+            writeln!(output)?;
+            writeln!(output, "Hot spots:")?;
+            writeln!(output, "{:>8} {:>12} {:>14} {:>7}", "Location", "Instructions", "Cycles", "%")?;
+            for (location, counter) in locations {
+                writeln!(output, "{:>8} {:>12} {:>14} {:>6.2}% {}", format_location(*location),
+                         counter.instructions, counter.cycles, percentage(counter.cycles),
+                         describe_location(*location, symbols))?;
+            }
+            writeln!(output)?;
+            writeln!(output, "Functions:")?;
+            writeln!(output, "{:>8} {:>10} {:>14} {:>14} {:>7}",
+                     "Location", "Calls", "Self cycles", "Total cycles", "%")?;
+            for (location, (calls, self_cycles, cycles)) in functions {
+                writeln!(output, "{:>8} {:>10} {:>14} {:>14} {:>6.2}% {}", format_location(location),
+                         calls, self_cycles, cycles, percentage(cycles),
+                         describe_location(location, symbols))?;
+            }
+            Ok(())
+        })();
+        result.expect("Error writing profile");
+    }
+
+    // Writes the call stacks in the folded format read by flamegraph tools,
+    // one line per stack with its cycles, e.g. "01:4000;01:4123 1234".
+    // Functions with a label are named by it.
+    pub fn write_folded_stacks(&self, output: &mut Write, symbols: &Symbols) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.cycles == 0 {
+                continue;
+            }
+            let stack: Vec<String> = self.stack(index)
+                .into_iter()
+                .map(|location| match symbols.label(location.0, location.1) {
+                    Some(label) => label.to_string(),
+                    None => format_location(location),
+                })
+                .collect();
+            let stack = if stack.is_empty() { "root".to_string() } else { stack.join(";") };
+            writeln!(output, "{} {}", stack, node.cycles).expect("Error writing folded stacks");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use super::Registers;
+
+const FLAG_ZERO: u8 = 0x80;
+const FLAG_OPERATION: u8 = 0x40;
+const FLAG_HALFCARRY: u8 = 0x20;
+const FLAG_CARRY: u8 = 0x10;
+
+// Register values a Mooneye test ROM leaves behind when it passes. On failure
+// all of them are set to 0x42.
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+// Snapshot of the CPU state. It can be modified and written back with
+// Cpu::set_state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+
+    pub interrupt_master_enable: bool,
+    // EI was executed and interrupts get enabled after the next command
+    pub enable_interrupts_pending: bool,
+    pub halted: bool,
+    pub stopped: bool,
+
+    // Contents of the IE (0xFFFF) and IF (0xFF0F) registers
+    pub interrupt_enable: u8,
+    pub interrupt_flags: u8,
+}
+
+macro_rules! generate_pair_getter_and_setter {
+    ($name_getter:ident, $name_setter:ident, $high:ident, $low:ident) => {
+        pub fn $name_getter(&self) -> u16 {
+            ((self.$high as u16) << 8) + self.$low as u16
+        }
+
+        pub fn $name_setter(&mut self, value: u16) {
+            self.$high = (value >> 8) as u8;
+            self.$low = value as u8;
+        }
+    }
+}
+
+macro_rules! generate_flag_getter_and_setter {
+    ($name_getter:ident, $name_setter:ident, $val:expr) => {
+        pub fn $name_getter(&self) -> bool {
+            (self.f & $val) != 0
+        }
+
+        pub fn $name_setter(&mut self, value: bool) {
+            if value {
+                self.f |= $val;
+            } else {
+                self.f &= !$val;
+            }
+        }
+    }
+}
+
+impl CpuState {
+    generate_pair_getter_and_setter!(af, set_af, a, f);
+    generate_pair_getter_and_setter!(bc, set_bc, b, c);
+    generate_pair_getter_and_setter!(de, set_de, d, e);
+    generate_pair_getter_and_setter!(hl, set_hl, h, l);
+
+    generate_flag_getter_and_setter!(zero, set_zero, FLAG_ZERO);
+    generate_flag_getter_and_setter!(operation, set_operation, FLAG_OPERATION);
+    generate_flag_getter_and_setter!(halfcarry, set_halfcarry, FLAG_HALFCARRY);
+    generate_flag_getter_and_setter!(carry, set_carry, FLAG_CARRY);
+
+    // Interrupts that are both requested and enabled
+    pub fn pending_interrupts(&self) -> u8 {
+        self.interrupt_enable & self.interrupt_flags & 0x1F
+    }
+
+    pub fn is_mooneye_pass(&self) -> bool {
+        self.mooneye_registers() == MOONEYE_PASS
+    }
+
+    pub fn is_mooneye_fail(&self) -> bool {
+        self.mooneye_registers() == MOONEYE_FAIL
+    }
+
+    fn mooneye_registers(&self) -> [u8; 6] {
+        [self.b, self.c, self.d, self.e, self.h, self.l]
+    }
+
+    pub(super) fn from_registers(registers: &Registers, interrupt_enable: u8, interrupt_flags: u8) -> CpuState {
+        CpuState {
+            a: registers.a,
+            f: registers.f,
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            sp: registers.sp,
+            pc: registers.pc,
+            interrupt_master_enable: registers.interrupt_master_enable,
+            enable_interrupts_pending: registers.enable_interrupts_pending,
+            halted: registers.halt,
+            stopped: registers.stop,
+            interrupt_enable,
+            interrupt_flags,
+        }
+    }
+
+    pub(super) fn to_registers(&self, registers: &mut Registers) {
+        registers.a = self.a;
+        // The lower four bits of F are always zero
+        registers.f = self.f & 0xF0;
+        registers.b = self.b;
+        registers.c = self.c;
+        registers.d = self.d;
+        registers.e = self.e;
+        registers.h = self.h;
+        registers.l = self.l;
+        registers.sp = self.sp;
+        registers.pc = self.pc;
+        registers.interrupt_master_enable = self.interrupt_master_enable;
+        registers.enable_interrupts_pending = self.enable_interrupts_pending;
+        registers.halt = self.halted;
+        registers.stop = self.stopped;
+    }
+}
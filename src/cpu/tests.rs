@@ -0,0 +1,564 @@
+use super::*;
+use super::instruction_cache::CacheKey;
+use symbols::Symbols;
+
+use std::cell::RefCell;
+
+fn mnemonic(bytes: &[u8], address: u16) -> String {
+    disassemble_bytes(bytes, address).unwrap().mnemonic
+}
+
+#[test]
+fn test_disassemble_operands() {
+    assert_eq!(mnemonic(&[0x00], 0), "NOP");
+    assert_eq!(mnemonic(&[0x3E, 0x42], 0), "LD A,$42");
+    assert_eq!(mnemonic(&[0x21, 0x34, 0x12], 0), "LD HL,$1234");
+    assert_eq!(mnemonic(&[0x22], 0), "LD (HL+),A");
+    assert_eq!(mnemonic(&[0x3A], 0), "LD A,(HL-)");
+    assert_eq!(mnemonic(&[0xE0, 0x40], 0), "LDH ($FF40),A");
+    assert_eq!(mnemonic(&[0xEA, 0x00, 0xC0], 0), "LD ($C000),A");
+    assert_eq!(mnemonic(&[0xF8, 0xFE], 0), "LD HL,SP-$02");
+    assert_eq!(mnemonic(&[0x18, 0xFE], 0x0150), "JR $0150");
+    assert_eq!(mnemonic(&[0xFF], 0), "RST $38");
+    assert_eq!(mnemonic(&[0xCB, 0x7E], 0), "BIT 7,(HL)");
+    assert_eq!(mnemonic(&[0xD3], 0), "DB $D3");
+}
+
+#[test]
+fn test_disassemble_cycles() {
+    let call = disassemble_bytes(&[0xC4, 0x00, 0x10], 0).unwrap();
+    assert_eq!((call.length, call.cycles, call.cycles_branch_taken), (3, 12, 24));
+    let bit = disassemble_bytes(&[0xCB, 0x46], 0).unwrap();
+    assert_eq!((bit.length, bit.cycles), (2, 12));
+    let set = disassemble_bytes(&[0xCB, 0xC6], 0).unwrap();
+    assert_eq!(set.cycles, 16);
+    assert!(disassemble_bytes(&[0xC3, 0x00], 0).is_none());
+}
+
+#[test]
+fn test_disassemble_with_symbols() {
+    let symbols = Symbols::parse("00:0150 Start\n01:4000 Main.loop\n00:ff80 hCounter\n");
+    let memory = FlatMemory::new(&[]);
+    let format = |bytes: &[u8], address| {
+        disassemble_bytes(bytes, address).unwrap().format_with_symbols(&symbols, &memory)
+    };
+    assert_eq!(format(&[0xC3, 0x00, 0x40], 0), "JP Main.loop");
+    assert_eq!(format(&[0x18, 0x4E], 0x100), "JR Start");
+    assert_eq!(format(&[0xE0, 0x80], 0), "LDH (hCounter),A");
+    assert_eq!(format(&[0xCD, 0x00, 0x50], 0), "CALL $5000");
+}
+
+struct FlatMemory {
+    bytes: Vec<u8>,
+    cycles: u64,
+    // Kind, address and clock cycle of each read and write
+    accesses: RefCell<Vec<(Access, u16, u64)>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Access {
+    Read,
+    Write,
+}
+
+impl FlatMemory {
+    fn new(program: &[u8]) -> FlatMemory {
+        let mut bytes = vec![0; 0x10000];
+        bytes[..program.len()].copy_from_slice(program);
+        FlatMemory { bytes, cycles: 0, accesses: RefCell::new(Vec::new()) }
+    }
+}
+
+impl Memory for FlatMemory {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.accesses.borrow_mut().push((Access::Read, address, self.cycles));
+        self.bytes[address as usize]
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.accesses.borrow_mut().push((Access::Write, address, self.cycles));
+        self.bytes[address as usize] = value;
+    }
+
+    fn step(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+    }
+
+    // KEY1 as on the CGB: bit 0 prepares a switch, bit 7 is the speed
+    fn switch_speed(&mut self) -> bool {
+        let key1 = self.bytes[0xFF4D];
+        if key1 & 0x01 == 0 {
+            return false;
+        }
+        self.bytes[0xFF4D] = (key1 ^ 0x80) & !0x01;
+        true
+    }
+}
+
+#[test]
+fn test_illegal_opcode_locks_up() {
+    let mut memory = FlatMemory::new(&[0x00, 0xDD, 0x00]);
+    memory.bytes[0xFFFF] = 0x1F;
+    memory.bytes[0xFF0F] = 0x1F;
+    let mut cpu = Cpu::new(memory);
+    cpu.cycle();
+    cpu.cycle();
+    assert_eq!(cpu.status(), CpuStatus::Locked { pc: 0x0001 });
+    let cycles = cpu.memory().cycles;
+    for _ in 0..10 {
+        assert_eq!(cpu.cycle(), 4);
+    }
+    assert_eq!(cpu.status(), CpuStatus::Locked { pc: 0x0001 });
+    assert_eq!(cpu.memory().cycles, cycles + 40);
+}
+
+#[test]
+fn test_state_round_trip() {
+    // LD B,$03; LD C,$05
+    let mut cpu = Cpu::new(FlatMemory::new(&[0x06, 0x03, 0x0E, 0x05]));
+    let mut state = cpu.state();
+    state.set_de(0x080D);
+    state.set_hl(0x1522);
+    state.f = 0xFF;
+    state.interrupt_enable = 0x01;
+    state.interrupt_flags = 0x01;
+    cpu.set_state(&state);
+    assert!(!cpu.state().is_mooneye_pass());
+    cpu.cycle();
+    cpu.cycle();
+    let state = cpu.state();
+    assert!(state.is_mooneye_pass());
+    assert_eq!(state.f, 0xF0);
+    assert!(state.zero() && state.carry());
+    assert_eq!(state.pc, 0x0004);
+    assert_eq!(state.pending_interrupts(), 0x01);
+}
+
+fn create_cpu_with_interrupt(pc: u16, sp: u16, interrupt_enable: u8, interrupt_flags: u8) -> Cpu<FlatMemory> {
+    let mut cpu = Cpu::new(FlatMemory::new(&[]));
+    let mut state = cpu.state();
+    state.pc = pc;
+    state.sp = sp;
+    state.interrupt_master_enable = true;
+    state.interrupt_enable = interrupt_enable;
+    state.interrupt_flags = interrupt_flags;
+    cpu.set_state(&state);
+    cpu
+}
+
+#[test]
+fn test_interrupt_dispatch() {
+    let mut cpu = create_cpu_with_interrupt(0x1234, 0xD000, 0x04, 0x05);
+    assert_eq!(cpu.cycle(), 4 + 20);
+    let state = cpu.state();
+    assert_eq!(state.pc, 0x0050);
+    assert_eq!(state.sp, 0xCFFE);
+    assert_eq!(state.interrupt_flags, 0x01);
+    assert!(!state.interrupt_master_enable);
+    assert_eq!(cpu.memory().read_word(0xCFFE), 0x1235);
+}
+
+#[test]
+fn test_interrupt_dispatch_cancelled_by_ie_push() {
+    // Pushing the high byte of PC (0x12) to IE disables the VBlank interrupt
+    let mut cpu = create_cpu_with_interrupt(0x1234, 0x0000, 0x01, 0x01);
+    assert_eq!(cpu.cycle(), 4 + 20);
+    assert_eq!(cpu.state().pc, 0x0000);
+    assert_eq!(cpu.state().interrupt_flags, 0x01);
+
+    // Pushing 0x02 to IE redirects the dispatch to the LCD status interrupt
+    let mut cpu = create_cpu_with_interrupt(0x0234, 0x0000, 0x01, 0x03);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0048);
+    assert_eq!(cpu.state().interrupt_flags, 0x01);
+}
+
+#[test]
+fn test_interrupt_dispatch_from_halt() {
+    // HALT
+    let mut cpu = Cpu::new(FlatMemory::new(&[0x76]));
+    let mut state = cpu.state();
+    state.interrupt_master_enable = true;
+    state.interrupt_enable = 0x04;
+    cpu.set_state(&state);
+    cpu.cycle();
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    cpu.memory_mut().write_byte(0xFF0F, 0x04);
+    assert_eq!(cpu.cycle(), 4 + 4 + 20);
+    assert_eq!(cpu.state().pc, 0x0050);
+}
+
+#[test]
+fn test_self_modifying_code_in_ram() {
+    // 0xC000: LD A,$01; INC A; LD ($C001),A; JR $C000
+    let mut memory = FlatMemory::new(&[0xC3, 0x00, 0xC0]);
+    memory.bytes[0xC000..0xC008].copy_from_slice(&[0x3E, 0x01, 0x3C, 0xEA, 0x01, 0xC0, 0x18, 0xF8]);
+    let mut cpu = Cpu::new(memory);
+    cpu.cycle();
+    for _ in 0..3 * 4 {
+        cpu.cycle();
+    }
+    // Each iteration loads the value stored by the previous one
+    assert_eq!(cpu.state().a, 4);
+    assert_eq!(cpu.memory().read_byte(0xC001), 4);
+    assert_eq!(cpu.memory().cycles, 16 + 3 * (8 + 4 + 16 + 12));
+}
+
+#[test]
+fn test_profiler_call_tracking() {
+    // 0x00: CALL $0010
+    // 0x03: JR $0003
+    // 0x10: NOP
+    // 0x11: RET
+    let mut memory = FlatMemory::new(&[0xCD, 0x10, 0x00, 0x18, 0xFE]);
+    memory.bytes[0x10] = 0x00;
+    memory.bytes[0x11] = 0xC9;
+    let mut cpu = Cpu::new(memory);
+    let mut state = cpu.state();
+    state.sp = 0xFFFE;
+    cpu.set_state(&state);
+    cpu.set_profiler(Profiler::new());
+    for _ in 0..5 {
+        cpu.cycle();
+    }
+    let profiler = cpu.take_profiler().unwrap();
+    let mut folded = Vec::new();
+    profiler.write_folded_stacks(&mut folded, &Symbols::new());
+    // CALL and two JRs at the top level, NOP and RET within the function
+    assert_eq!(String::from_utf8(folded).unwrap(), "root 48\n00:0010 20\n");
+}
+
+#[test]
+fn test_profiler_call_depth_overflow() {
+    // 0x00: CALL $0010
+    // 0x03: JR $0003
+    // 0x10: DEC A
+    // 0x11: JR Z,$0016
+    // 0x13: CALL $0010
+    // 0x16: RET
+    let mut memory = FlatMemory::new(&[0xCD, 0x10, 0x00, 0x18, 0xFE]);
+    memory.bytes[0x10..0x17].copy_from_slice(&[0x3D, 0x28, 0x03, 0xCD, 0x10, 0x00, 0xC9]);
+    let mut cpu = Cpu::new(memory);
+    let mut state = cpu.state();
+    state.sp = 0xFFFE;
+    state.a = 70;
+    cpu.set_state(&state);
+    cpu.set_profiler(Profiler::new());
+    // The outer CALL, 70 nested calls of the function and two JRs
+    for _ in 0..(1 + 70 * 4 - 1 + 2) {
+        cpu.cycle();
+    }
+    let profiler = cpu.take_profiler().unwrap();
+    let mut folded = Vec::new();
+    profiler.write_folded_stacks(&mut folded, &Symbols::new());
+    let folded = String::from_utf8(folded).unwrap();
+    let mut root_cycles = 0;
+    let mut function_cycles = 0;
+    for line in folded.lines() {
+        let (stack, cycles) = line.split_at(line.rfind(' ').unwrap());
+        let cycles: u64 = cycles.trim().parse().unwrap();
+        if stack == "root" {
+            root_cycles += cycles;
+        } else {
+            assert!(stack.split(';').count() <= 64);
+            function_cycles += cycles;
+        }
+    }
+    // Returns from calls beyond the maximum depth stay within the function
+    assert_eq!(root_cycles, 24 + 2 * 12);
+    assert_eq!(function_cycles, 70 * 4 + 69 * 8 + 12 + 69 * 24 + 70 * 16);
+}
+
+// Memory with ROM below 0x8000, where writes only reach the MBC registers
+struct RomMemory {
+    bytes: Vec<u8>,
+}
+
+impl RomMemory {
+    fn new(program: &[u8]) -> RomMemory {
+        let mut bytes = vec![0; 0x10000];
+        bytes[..program.len()].copy_from_slice(program);
+        RomMemory { bytes }
+    }
+}
+
+impl Memory for RomMemory {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.bytes[address as usize] = value;
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000...0x7FFF => Some(address as usize),
+            _ => None,
+        }
+    }
+}
+
+// LD A,$0A; LD ($0000),A; JR $0000, which enables the cartridge RAM over
+// and over and writes to the address of its first command
+const MBC_WRITE_LOOP: [u8; 7] = [0x3E, 0x0A, 0xEA, 0x00, 0x00, 0x18, 0xF9];
+
+#[test]
+fn test_mbc_writes_keep_rom_commands_cached() {
+    let mut cpu = Cpu::new(RomMemory::new(&MBC_WRITE_LOOP));
+    for _ in 0..6 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.state().pc, 0x0000);
+    for &offset in &[0, 2, 5] {
+        assert!(cpu.instruction_cache.get(CacheKey::Rom(offset)).is_some());
+    }
+    // Writes to RAM still drop the commands there
+    cpu.instruction_cache.insert(CacheKey::Ram(0xC000), DecodedCommand::new(Opcode { b1: 0x00, b2: 0, b3: 0 }));
+    cpu.write_byte(0xE000, 0x00);
+    assert!(cpu.instruction_cache.get(CacheKey::Ram(0xC000)).is_none());
+}
+
+// Measures how long the emulation of frames with an MBC write in every loop
+// iteration takes. Run it with `cargo test --release -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn measure_frames_with_mbc_writes() {
+    const FRAMES: u32 = 600;
+    let mut cpu = Cpu::new(RomMemory::new(&MBC_WRITE_LOOP));
+    let start = ::std::time::Instant::now();
+    for _ in 0..FRAMES {
+        let mut cycles = 0;
+        while cycles < 70224 {
+            cycles += cpu.cycle() as u32;
+        }
+    }
+    println!("{:?} per frame", start.elapsed() / FRAMES);
+}
+
+// STOP; NOP
+fn create_cpu_before_stop(joypad: u8, key1: u8) -> Cpu<FlatMemory> {
+    let mut memory = FlatMemory::new(&[0x10, 0x00, 0x00]);
+    memory.bytes[0xFF00] = joypad;
+    memory.bytes[0xFF04] = 0x55;
+    memory.bytes[0xFF4D] = key1;
+    Cpu::new(memory)
+}
+
+#[test]
+fn test_stop_until_button_pressed() {
+    let mut cpu = create_cpu_before_stop(0xFF, 0x00);
+    assert_eq!(cpu.cycle(), 4);
+    assert_eq!(cpu.status(), CpuStatus::Stopped);
+    assert_eq!(cpu.memory().read_byte(0xFF04), 0x00);
+    let cycles = cpu.memory().cycles;
+    for _ in 0..10 {
+        assert_eq!(cpu.cycle(), 4);
+        assert_eq!(cpu.status(), CpuStatus::Stopped);
+    }
+    // The rest of the system is stopped as well
+    assert_eq!(cpu.memory().cycles, cycles);
+    assert_eq!(cpu.state().pc, 0x0002);
+    cpu.memory_mut().write_byte(0xFF00, 0xFE);
+    cpu.cycle();
+    assert_eq!(cpu.status(), CpuStatus::Running);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0003);
+}
+
+#[test]
+fn test_stop_with_button_held() {
+    // Without a pending interrupt, the CPU halts after the two-byte STOP
+    let mut cpu = create_cpu_before_stop(0xFE, 0x00);
+    cpu.cycle();
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!(cpu.state().pc, 0x0002);
+    assert_eq!(cpu.memory().read_byte(0xFF04), 0x55);
+
+    // With one, STOP is a one-byte command and the CPU keeps running
+    let mut cpu = create_cpu_before_stop(0xFE, 0x00);
+    let mut state = cpu.state();
+    state.interrupt_enable = 0x01;
+    state.interrupt_flags = 0x01;
+    cpu.set_state(&state);
+    cpu.cycle();
+    assert_eq!(cpu.status(), CpuStatus::Running);
+    assert_eq!(cpu.state().pc, 0x0001);
+}
+
+#[test]
+fn test_speed_switch() {
+    let mut cpu = create_cpu_before_stop(0xFF, 0x01);
+    cpu.cycle();
+    assert_eq!(cpu.memory().read_byte(0xFF4D), 0x80);
+    assert_eq!(cpu.memory().read_byte(0xFF04), 0x00);
+    // The CPU pauses for 8200 clock cycles and does not need a button press.
+    // The rest of the system keeps running meanwhile.
+    let cycles = cpu.memory().cycles;
+    for _ in 0..8200 / 4 {
+        assert_eq!(cpu.status(), CpuStatus::Stopped);
+        assert_eq!(cpu.cycle(), 4);
+    }
+    assert_eq!(cpu.memory().cycles, cycles + 8200);
+    assert_eq!(cpu.status(), CpuStatus::Running);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0003);
+}
+
+fn create_cpu_with_pending_interrupt(program: &[u8]) -> Cpu<FlatMemory> {
+    let mut cpu = Cpu::new(FlatMemory::new(program));
+    let mut state = cpu.state();
+    state.sp = 0xD000;
+    state.interrupt_enable = 0x01;
+    state.interrupt_flags = 0x01;
+    cpu.set_state(&state);
+    cpu
+}
+
+#[test]
+fn test_ei_takes_effect_after_next_command() {
+    // EI; NOP
+    let mut cpu = create_cpu_with_pending_interrupt(&[0xFB, 0x00, 0x00]);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0001);
+    assert!(!cpu.state().interrupt_master_enable);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0040);
+    assert_eq!(cpu.memory().read_word(0xCFFE), 0x0002);
+}
+
+#[test]
+fn test_di_after_ei_cancels_it() {
+    // EI; DI; NOP
+    let mut cpu = create_cpu_with_pending_interrupt(&[0xFB, 0xF3, 0x00]);
+    for _ in 0..3 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.state().pc, 0x0003);
+    assert!(!cpu.state().interrupt_master_enable);
+}
+
+#[test]
+fn test_ei_before_halt_returns_to_halt() {
+    // EI; HALT
+    let mut cpu = create_cpu_with_pending_interrupt(&[0xFB, 0x76, 0x00]);
+    cpu.cycle();
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0040);
+    assert_eq!(cpu.memory().read_word(0xCFFE), 0x0001);
+}
+
+#[test]
+fn test_reti_enables_interrupts_immediately() {
+    // RETI
+    let mut cpu = create_cpu_with_pending_interrupt(&[0xD9]);
+    cpu.memory_mut().write_byte(0xD001, 0x01);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0040);
+    assert_eq!(cpu.memory().read_word(0xD000), 0x0100);
+}
+
+#[test]
+fn test_halt_bug_repeats_next_byte() {
+    // HALT; INC A; NOP
+    let mut cpu = create_cpu_with_pending_interrupt(&[0x76, 0x3C, 0x00]);
+    cpu.cycle();
+    assert_eq!(cpu.status(), CpuStatus::Running);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0001);
+    cpu.cycle();
+    assert_eq!(cpu.state().pc, 0x0002);
+    assert_eq!(cpu.state().a, 0x02);
+
+    // HALT; LD A,$14 becomes LD A,$3E; INC D
+    let mut cpu = create_cpu_with_pending_interrupt(&[0x76, 0x3E, 0x14]);
+    for _ in 0..3 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.state().a, 0x3E);
+    assert_eq!(cpu.state().d, 0x01);
+    assert_eq!(cpu.state().pc, 0x0003);
+}
+
+// Runs a single command with SP at 0xD000, HL at 0xC000 and the zero flag as
+// given. Returns its cycles and the kind, address and clock cycle of every
+// memory access, leaving out the interrupt checks of the CPU.
+fn time_command(program: &[u8], zero: bool) -> (u8, Vec<(Access, u16, u64)>) {
+    let mut cpu = Cpu::new(FlatMemory::new(program));
+    cpu.registers.sp = 0xD000;
+    cpu.registers.h = 0xC0;
+    cpu.registers.l = 0x00;
+    cpu.registers.set_zero(zero);
+    let cycles = cpu.cycle();
+    assert_eq!(cpu.memory.cycles, cycles as u64);
+    let accesses = cpu.memory.accesses.borrow().iter()
+        .filter(|&&(_, address, _)| address != 0xFFFF && address != 0xFF0F)
+        .cloned()
+        .collect();
+    (cycles, accesses)
+}
+
+#[test]
+fn test_command_timing() {
+    use self::Access::{Read, Write};
+    // Each access happens at the end of its machine cycle
+    let table: &[(&[u8], bool, u8, &[(Access, u16, u64)])] = &[
+        // JP NZ,$1234 not taken and taken
+        (&[0xC2, 0x34, 0x12], true, 12, &[(Read, 0x0000, 4), (Read, 0x0001, 8), (Read, 0x0002, 12)]),
+        (&[0xC2, 0x34, 0x12], false, 16, &[(Read, 0x0000, 4), (Read, 0x0001, 8), (Read, 0x0002, 12)]),
+        // JR NZ,+2 not taken and taken
+        (&[0x20, 0x02], true, 8, &[(Read, 0x0000, 4), (Read, 0x0001, 8)]),
+        (&[0x20, 0x02], false, 12, &[(Read, 0x0000, 4), (Read, 0x0001, 8)]),
+        // CALL NZ,$1234 not taken and taken
+        (&[0xC4, 0x34, 0x12], true, 12, &[(Read, 0x0000, 4), (Read, 0x0001, 8), (Read, 0x0002, 12)]),
+        (&[0xC4, 0x34, 0x12], false, 24, &[
+            (Read, 0x0000, 4), (Read, 0x0001, 8), (Read, 0x0002, 12),
+            (Write, 0xCFFF, 20), (Write, 0xCFFE, 24),
+        ]),
+        // RET NZ not taken and taken, RET
+        (&[0xC0], true, 8, &[(Read, 0x0000, 4)]),
+        (&[0xC0], false, 20, &[(Read, 0x0000, 4), (Read, 0xD000, 12), (Read, 0xD001, 16)]),
+        (&[0xC9], false, 16, &[(Read, 0x0000, 4), (Read, 0xD000, 8), (Read, 0xD001, 12)]),
+        // RST $38
+        (&[0xFF], false, 16, &[(Read, 0x0000, 4), (Write, 0xCFFF, 12), (Write, 0xCFFE, 16)]),
+        // PUSH BC
+        (&[0xC5], false, 16, &[(Read, 0x0000, 4), (Write, 0xCFFF, 12), (Write, 0xCFFE, 16)]),
+        // BIT 0,(HL)
+        (&[0xCB, 0x46], false, 12, &[(Read, 0x0000, 4), (Read, 0x0001, 8), (Read, 0xC000, 12)]),
+    ];
+    for &(program, zero, cycles, accesses) in table {
+        let (actual_cycles, actual_accesses) = time_command(program, zero);
+        assert_eq!((actual_cycles, actual_accesses.as_slice()), (cycles, accesses),
+                   "{:02X?} with Z={}", program, zero);
+    }
+}
+
+#[test]
+fn test_disassembler_cycles_match_execution() {
+    // STOP, HALT and the illegal opcodes do not finish as a single command
+    let skipped = [0x10, 0x76, 0xCB, 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD];
+    let programs = (0..0x100)
+        .filter(|opcode| !skipped.contains(opcode))
+        .map(|opcode| vec![opcode as u8, 0x00, 0x00])
+        .chain((0..0x100).map(|opcode| vec![0xCB, opcode as u8]));
+    for program in programs {
+        let instruction = disassemble_bytes(&program, 0).unwrap();
+        // The carry flag is clear, so only the zero flag decides whether
+        // NZ and Z branches are taken. Both cases have to be seen then.
+        let cycles: Vec<u8> = [false, true].iter().map(|&zero| time_command(&program, zero).0).collect();
+        let mut parts = instruction.mnemonic.split(|c| c == ' ' || c == ',');
+        let condition = match parts.next() {
+            Some("JP") | Some("JR") | Some("CALL") | Some("RET") => parts.next(),
+            _ => None,
+        };
+        let expected = match condition {
+            Some("NZ") => vec![instruction.cycles_branch_taken, instruction.cycles],
+            Some("Z") => vec![instruction.cycles, instruction.cycles_branch_taken],
+            Some("NC") => vec![instruction.cycles_branch_taken; 2],
+            _ => vec![instruction.cycles; 2],
+        };
+        assert_eq!(cycles, expected, "{} ({:02X?})", instruction.mnemonic, program);
+    }
+}
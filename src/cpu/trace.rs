@@ -0,0 +1,78 @@
+use super::Registers;
+use memory::Memory;
+use symbols::Symbols;
+
+use std::io::Write;
+use std::rc::Rc;
+
+// Restricts tracing to commands at certain addresses
+#[derive(Default)]
+pub struct TraceFilter {
+    pub pc_range: Option<(u16, u16)>,
+    pub rom_bank: Option<u16>,
+}
+
+impl TraceFilter {
+    fn matches(&self, pc: u16, memory: &Memory) -> bool {
+        if let Some((start, end)) = self.pc_range {
+            if pc < start || pc > end {
+                return false;
+            }
+        }
+        if let Some(bank) = self.rom_bank {
+            if memory.rom_bank(pc) != Some(bank) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Logs the CPU state before every executed command in the format used by
+// Gameboy Doctor, e.g.
+// A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+// If symbols are given, labels are written on their own line before the
+// command at their address, e.g. "Main.loop:". Such a trace can no longer be
+// compared line by line with the logs of Gameboy Doctor.
+pub struct Tracer {
+    output: Box<Write>,
+    filter: TraceFilter,
+    symbols: Option<Rc<Symbols>>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<Write>, filter: TraceFilter, symbols: Option<Rc<Symbols>>) -> Tracer {
+        Tracer { output, filter, symbols }
+    }
+
+    pub(super) fn trace(&mut self, registers: &Registers, memory: &Memory) {
+        let pc = registers.pc;
+        if !self.filter.matches(pc, memory) {
+            return;
+        }
+        if let Some(ref symbols) = self.symbols {
+            if let Some(label) = symbols.label(memory.rom_bank(pc), pc) {
+                writeln!(self.output, "{}:", label).expect("Error writing trace");
+            }
+        }
+        writeln!(
+            self.output,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} \
+             SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            registers.a,
+            registers.f,
+            registers.b,
+            registers.c,
+            registers.d,
+            registers.e,
+            registers.h,
+            registers.l,
+            registers.sp,
+            pc,
+            memory.read_byte(pc),
+            memory.read_byte(pc.wrapping_add(1)),
+            memory.read_byte(pc.wrapping_add(2)),
+            memory.read_byte(pc.wrapping_add(3)),
+        ).expect("Error writing trace");
+    }
+}
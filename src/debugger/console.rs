@@ -0,0 +1,284 @@
+use super::{BreakReason, Breakpoint, Comparison, Condition, Debugger, Register, RunMode, Watchpoint};
+use cpu::{self, AccessKind, Cpu};
+use memory::Memory;
+use symbols::Symbols;
+
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+Commands:
+    c, continue                     Continue the emulation
+    s, step                         Execute one command
+    n, next                         Execute one command, stepping over calls
+    finish                          Run until the current function returns
+    rs, reverse-step                Go back to the previous command
+    rc, reverse-continue            Run backwards to the last breakpoint or watchpoint hit
+    b, break LOCATION [if COND]     Set a breakpoint, e.g. break 1:4000 if A == 10
+                                    or break Main.loop
+    w, watch [r|w|rw|x] START[-END] Set a watchpoint on an address range
+    d, delete ID                    Delete a breakpoint or watchpoint
+    l, list                         List breakpoints and watchpoints
+    r, regs                         Show the registers
+    set REG VALUE                   Change a register (clears the history)
+    x ADDR [COUNT]                  Show memory contents
+    disas [ADDR] [COUNT]            Disassemble commands
+    q, quit                         Quit the emulator
+All numbers are hexadecimal. Addresses can also be given as labels from the
+symbol file.";
+
+// Parses a hexadecimal number with an optional $ or 0x prefix
+fn parse_number(value: &str) -> Option<u16> {
+    let value = value.trim_start_matches('$').trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(value, 16).ok()
+}
+
+fn parse_comparison(value: &str) -> Option<Comparison> {
+    let comparison = match value {
+        "==" => Comparison::Equal,
+        "!=" => Comparison::NotEqual,
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        ">" => Comparison::Greater,
+        ">=" => Comparison::GreaterOrEqual,
+        _ => return None,
+    };
+    Some(comparison)
+}
+
+// Parses a number or a label
+fn parse_address(value: &str, symbols: &Symbols) -> Option<u16> {
+    parse_number(value).or_else(|| symbols.find(value).map(|(_, address)| address))
+}
+
+// Parses "[BANK:]ADDR" or a label. Labels in the switchable ROM area only
+// match in their bank.
+fn parse_location(value: &str, symbols: &Symbols) -> Option<(Option<u16>, u16)> {
+    if let Some(index) = value.find(':') {
+        return Some((Some(parse_number(&value[..index])?), parse_number(&value[index + 1..])?));
+    }
+    if let Some(address) = parse_number(value) {
+        return Some((None, address));
+    }
+    let (bank, address) = symbols.find(value)?;
+    match address {
+        0x4000...0x7FFF => Some((Some(bank), address)),
+        _ => Some((None, address)),
+    }
+}
+
+// Parses "LOCATION [if REG OP VALUE]"
+fn parse_breakpoint(args: &[&str], symbols: &Symbols) -> Option<Breakpoint> {
+    let (bank, address) = parse_location(args.first()?, symbols)?;
+    let condition = match args.len() {
+        1 => None,
+        5 if args[1] == "if" => Some(Condition {
+            register: Register::from_name(args[2])?,
+            comparison: parse_comparison(args[3])?,
+            value: parse_number(args[4])?,
+        }),
+        _ => return None,
+    };
+    Some(Breakpoint { address, bank, condition })
+}
+
+// Parses "[r|w|rw|x] START[-END]"
+fn parse_watchpoint(args: &[&str], symbols: &Symbols) -> Option<Watchpoint> {
+    let (kind, range) = match args.len() {
+        1 => ("rw", args[0]),
+        2 => (args[0], args[1]),
+        _ => return None,
+    };
+    let (start, end) = match range.find('-') {
+        Some(index) => (parse_address(&range[..index], symbols)?, parse_address(&range[index + 1..], symbols)?),
+        None => {
+            let address = parse_address(range, symbols)?;
+            (address, address)
+        }
+    };
+    let (read, write, execute) = match kind {
+        "r" => (true, false, false),
+        "w" => (false, true, false),
+        "rw" => (true, true, false),
+        "x" => (false, false, true),
+        _ => return None,
+    };
+    Some(Watchpoint { start, end, read, write, execute })
+}
+
+fn print_reason(reason: BreakReason) {
+    match reason {
+        BreakReason::Interrupted | BreakReason::Step => {}
+        BreakReason::Breakpoint(id) => println!("Breakpoint {} hit", id),
+        BreakReason::Execute(id) => println!("Watchpoint {} hit: execute", id),
+        BreakReason::Access(id, access) => {
+            let kind = match access.kind {
+                AccessKind::Write => "write",
+                _ => "read",
+            };
+            println!("Watchpoint {} hit: {} ${:02X} at ${:04X}", id, kind, access.value, access.address);
+        }
+    }
+}
+
+fn print_instructions<M: Memory>(cpu: &Cpu<M>, symbols: &Symbols, address: u16, count: u16) {
+    let mut address = address;
+    for _ in 0..count {
+        let instruction = cpu::disassemble(cpu.memory(), address);
+        let bytes: Vec<String> = instruction.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let rom_bank = cpu.memory().rom_bank(address);
+        let bank = match rom_bank {
+            Some(bank) => format!("{:02X}:", bank),
+            None => "   ".to_string(),
+        };
+        if let Some(label) = symbols.label(rom_bank, address) {
+            println!("{}:", label);
+        }
+        println!("{}{:04X}  {:<9} {}", bank, address, bytes.join(" "),
+                 instruction.format_with_symbols(symbols, cpu.memory()));
+        address = address.wrapping_add(instruction.length as u16);
+    }
+}
+
+fn print_registers<M: Memory>(cpu: &Cpu<M>) {
+    let state = cpu.state();
+    println!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+             state.a, state.f, state.b, state.c, state.d, state.e, state.h, state.l, state.sp, state.pc);
+    println!("Flags:{}{}{}{} IME:{} IE:{:02X} IF:{:02X} {:?}",
+             if state.zero() { " Z" } else { " -" },
+             if state.operation() { " N" } else { " -" },
+             if state.halfcarry() { " H" } else { " -" },
+             if state.carry() { " C" } else { " -" },
+             state.interrupt_master_enable as u8,
+             state.interrupt_enable,
+             state.interrupt_flags,
+             cpu.status());
+}
+
+fn print_memory<M: Memory>(cpu: &Cpu<M>, address: u16, count: u16) {
+    let mut offset = 0;
+    while offset < count {
+        let line_address = address.wrapping_add(offset);
+        let line_length = ::std::cmp::min(16, count - offset);
+        let bytes: Vec<String> = (0..line_length)
+            .map(|i| format!("{:02X}", cpu.memory().read_byte(line_address.wrapping_add(i))))
+            .collect();
+        println!("{:04X}  {}", line_address, bytes.join(" "));
+        offset += line_length;
+    }
+}
+
+fn print_points(debugger: &Debugger) {
+    for &(id, ref breakpoint) in debugger.breakpoints() {
+        let bank = breakpoint.bank.map_or(String::new(), |bank| format!("{:02X}:", bank));
+        let condition = breakpoint.condition.map_or(String::new(), |c| {
+            format!(" if {:?} {:?} {:X}", c.register, c.comparison, c.value)
+        });
+        let label = debugger.symbols().label(breakpoint.bank, breakpoint.address)
+            .map_or(String::new(), |label| format!(" ({})", label));
+        println!("{}: break {}{:04X}{}{}", id, bank, breakpoint.address, label, condition);
+    }
+    for &(id, ref watchpoint) in debugger.watchpoints() {
+        let kind = match (watchpoint.read, watchpoint.write, watchpoint.execute) {
+            (_, _, true) => "x",
+            (true, true, _) => "rw",
+            (true, false, _) => "r",
+            _ => "w",
+        };
+        println!("{}: watch {} {:04X}-{:04X}", id, kind, watchpoint.start, watchpoint.end);
+    }
+}
+
+// Runs the debugger console until the emulation is resumed. Returns false if
+// the user wants to quit.
+pub fn run_console<M: Memory>(debugger: &mut Debugger, cpu: &mut Cpu<M>, reason: BreakReason) -> bool {
+    print_reason(reason);
+    print_instructions(cpu, debugger.symbols(), cpu.state().pc, 1);
+    let stdin = io::stdin();
+    loop {
+        print!("(rustgb) ");
+        io::stdout().flush().expect("Error writing to stdout");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("Error reading from stdin") == 0 {
+            return false;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+        match command {
+            "c" | "continue" => {
+                debugger.resume(RunMode::Continue, cpu);
+                return true;
+            }
+            "s" | "step" => {
+                debugger.resume(RunMode::Step, cpu);
+                return true;
+            }
+            "n" | "next" => {
+                debugger.resume(RunMode::StepOver, cpu);
+                return true;
+            }
+            "finish" => {
+                debugger.resume(RunMode::StepOut, cpu);
+                return true;
+            }
+            "rs" | "reverse-step" => {
+                if debugger.step_back(cpu) {
+                    print_instructions(cpu, debugger.symbols(), cpu.state().pc, 1);
+                } else {
+                    println!("Reached the start of the history");
+                }
+            }
+            "rc" | "reverse-continue" => {
+                match debugger.reverse_continue(cpu) {
+                    Some(reason) => print_reason(reason),
+                    None => println!("Reached the start of the history"),
+                }
+                print_instructions(cpu, debugger.symbols(), cpu.state().pc, 1);
+            }
+            "b" | "break" => match parse_breakpoint(args, debugger.symbols()) {
+                Some(breakpoint) => println!("Breakpoint {}", debugger.add_breakpoint(breakpoint)),
+                None => println!("Usage: break [BANK:]ADDR|LABEL [if REG OP VALUE]"),
+            },
+            "w" | "watch" => match parse_watchpoint(args, debugger.symbols()) {
+                Some(watchpoint) => println!("Watchpoint {}", debugger.add_watchpoint(watchpoint)),
+                None => println!("Usage: watch [r|w|rw|x] START[-END]"),
+            },
+            "d" | "delete" => match args.first().and_then(|id| id.parse().ok()) {
+                Some(id) if debugger.delete(id) => {}
+                _ => println!("Usage: delete ID"),
+            },
+            "l" | "list" => print_points(debugger),
+            "r" | "regs" => print_registers(cpu),
+            "set" => {
+                let register = args.first().and_then(|r| Register::from_name(r));
+                let value = args.get(1).and_then(|v| parse_number(v));
+                match (register, value) {
+                    (Some(register), Some(value)) => {
+                        let mut state = cpu.state();
+                        register.set(&mut state, value);
+                        cpu.set_state(&state);
+                        debugger.clear_history();
+                    }
+                    _ => println!("Usage: set REG VALUE"),
+                }
+            }
+            "x" => match args.first().and_then(|a| parse_address(a, debugger.symbols())) {
+                Some(address) => {
+                    let count = args.get(1).and_then(|c| parse_number(c)).unwrap_or(0x10);
+                    print_memory(cpu, address, count);
+                }
+                None => println!("Usage: x ADDR [COUNT]"),
+            },
+            "disas" => {
+                let address = args.first().and_then(|a| parse_address(a, debugger.symbols())).unwrap_or(cpu.state().pc);
+                let count = args.get(1).and_then(|c| parse_number(c)).unwrap_or(0x8);
+                print_instructions(cpu, debugger.symbols(), address, count);
+            }
+            "q" | "quit" => return false,
+            "h" | "help" => println!("{}", HELP),
+            _ => println!("Unknown command: {}. Type help for a list of commands.", command),
+        }
+    }
+}
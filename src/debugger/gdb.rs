@@ -0,0 +1,383 @@
+use super::{BreakReason, Breakpoint, Debugger, RunMode, Watchpoint};
+use cpu::{AccessKind, Cpu, CpuState};
+use memory::Memory;
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// Registers as sent in g/G packets, each 16 bits wide in little endian:
+// AF, BC, DE, HL, SP, PC
+const REGISTER_COUNT: usize = 6;
+
+// Signal number reported to gdb when the target stops (SIGTRAP)
+const SIGTRAP: u8 = 5;
+
+// Stop reply when going backwards reached the start of the history
+const REPLAY_LOG_BEGIN: &str = "T05replaylog:begin;";
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    (0..value.len() / 2)
+        .map(|i| u8::from_str_radix(&value[2 * i..2 * i + 2], 16).ok())
+        .collect()
+}
+
+fn parse_number(value: &str) -> Option<u16> {
+    u16::from_str_radix(value, 16).ok()
+}
+
+// Parses "ADDR,LENGTH"
+fn parse_address_and_length(value: &str) -> Option<(u16, u16)> {
+    let mut parts = value.splitn(2, ',');
+    let address = parts.next().and_then(parse_number)?;
+    let length = parts.next().and_then(parse_number)?;
+    Some((address, length))
+}
+
+fn get_register(state: &CpuState, index: usize) -> u16 {
+    match index {
+        0 => state.af(),
+        1 => state.bc(),
+        2 => state.de(),
+        3 => state.hl(),
+        4 => state.sp,
+        _ => state.pc,
+    }
+}
+
+fn set_register(state: &mut CpuState, index: usize, value: u16) {
+    match index {
+        0 => state.set_af(value),
+        1 => state.set_bc(value),
+        2 => state.set_de(value),
+        3 => state.set_hl(value),
+        4 => state.sp = value,
+        _ => state.pc = value,
+    }
+}
+
+enum SessionEnd {
+    Resume,
+    Detach,
+    Kill,
+}
+
+// Server side of the GDB remote serial protocol. While gdb is connected, it
+// takes the place of the debugger console.
+pub struct GdbStub {
+    stream: Option<TcpStream>,
+    // Maps breakpoint and watchpoint keys (type, address, length) to debugger ids
+    points: HashMap<(u8, u16, u16), usize>,
+    awaiting_stop_reply: bool,
+}
+
+impl GdbStub {
+    // Waits for gdb to connect on the given local port
+    pub fn listen(port: u16) -> GdbStub {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .unwrap_or_else(|e| panic!("Error listening on port {}: {}", port, e));
+        println!("Waiting for gdb to connect on port {}", port);
+        let (stream, address) = listener.accept().expect("Error accepting gdb connection");
+        println!("gdb connected from {}", address);
+        stream.set_nodelay(true).expect("Error configuring gdb connection");
+        GdbStub {
+            stream: Some(stream),
+            points: HashMap::new(),
+            awaiting_stop_reply: false,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    // Checks without blocking whether gdb asked to interrupt the running
+    // target (Ctrl-C)
+    pub fn poll_interrupt(&mut self) -> bool {
+        let mut interrupted = false;
+        let mut disconnected = false;
+        if let Some(ref mut stream) = self.stream {
+            stream.set_nonblocking(true).expect("Error configuring gdb connection");
+            // Other bytes start a packet, which is left for the session
+            let mut byte = [0u8];
+            match stream.peek(&mut byte) {
+                Ok(0) => disconnected = true,
+                Ok(_) if byte[0] == 0x03 => {
+                    interrupted = stream.read(&mut byte).is_ok();
+                }
+                Ok(_) => {}
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => disconnected = true,
+            }
+            stream.set_nonblocking(false).expect("Error configuring gdb connection");
+        }
+        if disconnected {
+            self.stream = None;
+        }
+        interrupted
+    }
+
+    // Serves gdb requests while the emulation is stopped. Returns false if
+    // the emulator should quit.
+    pub fn run_session<M: Memory>(&mut self, debugger: &mut Debugger, cpu: &mut Cpu<M>,
+                                  reason: BreakReason) -> bool {
+        if self.awaiting_stop_reply {
+            let reply = self.stop_reply(debugger, reason);
+            self.send_packet(&reply);
+        }
+        loop {
+            let packet = match self.receive_packet() {
+                Some(packet) => packet,
+                None => {
+                    // gdb closed the connection
+                    self.stream = None;
+                    debugger.resume(RunMode::Continue, cpu);
+                    return true;
+                }
+            };
+            match self.handle_packet(&packet, debugger, cpu) {
+                Some(SessionEnd::Resume) => {
+                    self.awaiting_stop_reply = true;
+                    return true;
+                }
+                Some(SessionEnd::Detach) => {
+                    self.stream = None;
+                    debugger.resume(RunMode::Continue, cpu);
+                    return true;
+                }
+                Some(SessionEnd::Kill) => return false,
+                None => {}
+            }
+        }
+    }
+
+    fn stop_reply(&self, debugger: &Debugger, reason: BreakReason) -> String {
+        if let BreakReason::Access(id, access) = reason {
+            let watchpoint = debugger.watchpoints().iter().find(|&&(i, _)| i == id).map(|&(_, w)| w);
+            if let Some(watchpoint) = watchpoint {
+                let kind = match (watchpoint.read, watchpoint.write, access.kind) {
+                    (true, true, _) => "awatch",
+                    (_, _, AccessKind::Write) => "watch",
+                    _ => "rwatch",
+                };
+                return format!("T{:02x}{}:{:04x};", SIGTRAP, kind, access.address);
+            }
+        }
+        format!("S{:02x}", SIGTRAP)
+    }
+
+    fn handle_packet<M: Memory>(&mut self, packet: &str, debugger: &mut Debugger,
+                                cpu: &mut Cpu<M>) -> Option<SessionEnd> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => {
+                let state = cpu.state();
+                let registers: Vec<u8> = (0..REGISTER_COUNT)
+                    .flat_map(|i| {
+                        let value = get_register(&state, i);
+                        vec![value as u8, (value >> 8) as u8]
+                    })
+                    .collect();
+                to_hex(&registers)
+            }
+            "G" => match from_hex(args) {
+                Some(ref bytes) if bytes.len() >= 2 * REGISTER_COUNT => {
+                    let mut state = cpu.state();
+                    for i in 0..REGISTER_COUNT {
+                        let value = ((bytes[2 * i + 1] as u16) << 8) + bytes[2 * i] as u16;
+                        set_register(&mut state, i, value);
+                    }
+                    cpu.set_state(&state);
+                    debugger.clear_history();
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(index) if index < REGISTER_COUNT => {
+                    let value = get_register(&cpu.state(), index);
+                    to_hex(&[value as u8, (value >> 8) as u8])
+                }
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let index = parts.next().and_then(|i| usize::from_str_radix(i, 16).ok());
+                let bytes = parts.next().and_then(from_hex);
+                match (index, bytes) {
+                    (Some(index), Some(ref bytes)) if index < REGISTER_COUNT && bytes.len() == 2 => {
+                        let mut state = cpu.state();
+                        set_register(&mut state, index, ((bytes[1] as u16) << 8) + bytes[0] as u16);
+                        cpu.set_state(&state);
+                        debugger.clear_history();
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_address_and_length(args) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (0..length)
+                        .map(|i| cpu.memory().read_byte(address.wrapping_add(i)))
+                        .collect();
+                    to_hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let target = parts.next().and_then(parse_address_and_length);
+                let bytes = parts.next().and_then(from_hex);
+                match (target, bytes) {
+                    (Some((address, length)), Some(ref bytes)) if bytes.len() == length as usize => {
+                        // Writes go through the memory map, so writes to ROM
+                        // are interpreted as MBC commands
+                        for (i, &byte) in bytes.iter().enumerate() {
+                            cpu.memory_mut().write_byte(address.wrapping_add(i as u16), byte);
+                        }
+                        debugger.clear_history();
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "Z" | "z" => self.handle_point(command == "Z", args, debugger),
+            "c" => {
+                debugger.resume(RunMode::Continue, cpu);
+                return Some(SessionEnd::Resume);
+            }
+            "s" => {
+                debugger.resume(RunMode::Step, cpu);
+                return Some(SessionEnd::Resume);
+            }
+            // Reverse execution stays in the session, as it does not run the
+            // emulation loop
+            "b" if args == "s" => {
+                if debugger.step_back(cpu) {
+                    format!("S{:02x}", SIGTRAP)
+                } else {
+                    REPLAY_LOG_BEGIN.to_string()
+                }
+            }
+            "b" if args == "c" => match debugger.reverse_continue(cpu) {
+                Some(reason) => self.stop_reply(debugger, reason),
+                None => REPLAY_LOG_BEGIN.to_string(),
+            },
+            "D" => {
+                self.send_packet("OK");
+                return Some(SessionEnd::Detach);
+            }
+            "k" => return Some(SessionEnd::Kill),
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => "PacketSize=1000;ReverseStep+;ReverseContinue+".to_string(),
+            "q" if args == "Attached" => "1".to_string(),
+            // Empty replies tell gdb that a command is not supported
+            _ => String::new(),
+        };
+        self.send_packet(&reply);
+        None
+    }
+
+    // Handles "TYPE,ADDR,KIND" of Z (insert) and z (remove) packets
+    fn handle_point(&mut self, insert: bool, args: &str, debugger: &mut Debugger) -> String {
+        let mut parts = args.splitn(2, ',');
+        let point_type = parts.next().and_then(|t| t.parse::<u8>().ok());
+        let target = parts.next().and_then(parse_address_and_length);
+        let (point_type, address, length) = match (point_type, target) {
+            (Some(point_type), Some((address, length))) if point_type <= 4 => (point_type, address, length),
+            _ => return String::new(),
+        };
+        let key = (point_type, address, length);
+        if !insert {
+            return match self.points.remove(&key) {
+                Some(id) => {
+                    debugger.delete(id);
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            };
+        }
+        if self.points.contains_key(&key) {
+            return "OK".to_string();
+        }
+        let end = address.wrapping_add(length.max(1) - 1);
+        let id = match point_type {
+            // Software and hardware breakpoints
+            0 | 1 => debugger.add_breakpoint(Breakpoint { address, bank: None, condition: None }),
+            _ => debugger.add_watchpoint(Watchpoint {
+                start: address,
+                end,
+                read: point_type != 2,
+                write: point_type != 3,
+                execute: false,
+            }),
+        };
+        self.points.insert(key, id);
+        "OK".to_string()
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let stream = self.stream.as_mut()?;
+        let mut byte = [0u8];
+        match stream.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    // Reads the next packet "$DATA#CHECKSUM", acknowledging it. Returns None if
+    // the connection was closed. Packets that are not text, like binary memory
+    // writes, are answered as unsupported, so gdb falls back to text ones.
+    fn receive_packet(&mut self) -> Option<String> {
+        loop {
+            // Skip acknowledgements and interrupt requests between packets
+            while self.read_byte()? != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum_hex = [self.read_byte()?, self.read_byte()?];
+            let expected = ::std::str::from_utf8(&checksum_hex).ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok());
+            if expected != Some(checksum(&data)) {
+                self.write_all(b"-");
+                continue;
+            }
+            self.write_all(b"+");
+            match String::from_utf8(data) {
+                Ok(packet) => return Some(packet),
+                Err(_) => self.send_packet(""),
+            }
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.write_all(packet.as_bytes());
+    }
+
+    fn write_all(&mut self, data: &[u8]) {
+        let failed = match self.stream {
+            Some(ref mut stream) => stream.write_all(data).is_err(),
+            None => false,
+        };
+        if failed {
+            self.stream = None;
+        }
+    }
+}
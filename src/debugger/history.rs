@@ -0,0 +1,203 @@
+use cpu::{Cpu, MemoryAccess, Snapshot};
+use keyboard;
+use memory::Memory;
+
+use std::collections::VecDeque;
+
+// Calls of Cpu::cycle between two snapshots. Going back replays at most this
+// many of them from the closest snapshot.
+const SNAPSHOT_INTERVAL: u64 = 100_000;
+// Older snapshots are dropped, which limits the history to 25.6 million calls
+// of Cpu::cycle. Each takes at least 4 clock cycles, so that is at least 24
+// seconds of emulated time at normal speed (12 in double speed mode) and
+// longer for commands that take more cycles.
+const MAX_SNAPSHOTS: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Input {
+    Keys(u8),
+    KeypadInterrupt,
+}
+
+// Executes the next CPU cycle and updates the joypad register with the
+// pressed keys. The emulation has to go through here, so that it can be
+// replayed exactly.
+pub fn step<M: Memory>(cpu: &mut Cpu<M>, keys: u8) -> u8 {
+    let cycles = cpu.cycle();
+    let mut key_register = cpu.memory().read_byte(0xFF00);
+    keyboard::apply_key_mask(keys, &mut key_register);
+    cpu.write_byte_without_hooks(0xFF00, key_register);
+    cycles
+}
+
+fn request_keypad_interrupt<M: Memory>(cpu: &mut Cpu<M>) {
+    let interrupts_fired = cpu.memory().read_byte(0xFF0F);
+    cpu.write_byte_without_hooks(0xFF0F, interrupts_fired | 0b0001_0000);
+}
+
+// Records snapshots and inputs during the emulation, so that any earlier
+// position can be restored by replaying from the snapshot before it.
+// Positions count the calls of Cpu::cycle.
+pub struct History {
+    position: u64,
+    // Snapshots taken before the cycle at the given position, together with
+    // the keys pressed at that time
+    snapshots: VecDeque<(u64, Snapshot, u8)>,
+    // Inputs made before the cycle at the given position
+    inputs: VecDeque<(u64, Input)>,
+    keys: u8,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            position: 0,
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+            keys: 0,
+        }
+    }
+
+    // Forgets everything before the current position, e.g. because the
+    // state was changed in a way that can not be replayed
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.inputs.clear();
+    }
+
+    // Has to be called before every cycle with the currently pressed keys
+    pub fn record_step<M: Memory>(&mut self, cpu: &Cpu<M>, keys: u8) {
+        if keys != self.keys {
+            self.keys = keys;
+            self.record_input(Input::Keys(keys));
+        }
+        // After going back, the snapshot at the current position is taken
+        // again, as the pressed keys may have changed
+        let snapshot_due = match self.snapshots.back() {
+            Some(&(position, _, _)) if position == self.position => {
+                self.snapshots.pop_back();
+                true
+            }
+            Some(&(position, _, _)) => self.position >= position + SNAPSHOT_INTERVAL,
+            None => true,
+        };
+        if snapshot_due {
+            if let Some(snapshot) = cpu.snapshot() {
+                self.snapshots.push_back((self.position, snapshot, self.keys));
+            }
+            if self.snapshots.len() > MAX_SNAPSHOTS {
+                self.snapshots.pop_front();
+                let start = self.snapshots[0].0;
+                while self.inputs.front().map_or(false, |&(position, _)| position <= start) {
+                    self.inputs.pop_front();
+                }
+            }
+        }
+        self.position += 1;
+    }
+
+    pub fn request_keypad_interrupt<M: Memory>(&mut self, cpu: &mut Cpu<M>) {
+        self.record_input(Input::KeypadInterrupt);
+        request_keypad_interrupt(cpu);
+    }
+
+    fn record_input(&mut self, input: Input) {
+        if !self.snapshots.is_empty() {
+            self.inputs.push_back((self.position, input));
+        }
+    }
+
+    // Searches the history backwards for the last position before the
+    // current one at which matches returns a result. It gets the CPU before
+    // the cycle at that position and the memory accesses of the previous
+    // cycle. Leaves the CPU in an undefined state, so go_to has to be called
+    // afterwards.
+    pub fn find_last<M, F, R>(&mut self, cpu: &mut Cpu<M>, mut matches: F) -> Option<(u64, R)>
+        where M: Memory, F: FnMut(&Cpu<M>, &[MemoryAccess]) -> Option<R>
+    {
+        let current = self.position;
+        for index in (0..self.snapshots.len()).rev() {
+            let start = self.snapshots[index].0;
+            if start >= current {
+                continue;
+            }
+            let end = self.snapshots.get(index + 1).map_or(current, |s| s.0.min(current));
+            let mut found = None;
+            self.replay(cpu, index, end, |position, cpu, accesses| {
+                if position < current {
+                    if let Some(result) = matches(cpu, accesses) {
+                        found = Some((position, result));
+                    }
+                }
+            });
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    // Oldest position that can be restored
+    pub fn start(&self) -> Option<u64> {
+        self.snapshots.front().map(|&(position, _, _)| position)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Restores the given position and discards the history after it
+    pub fn go_to<M: Memory>(&mut self, cpu: &mut Cpu<M>, position: u64) {
+        let index = match self.snapshots.iter().rposition(|&(p, _, _)| p <= position) {
+            Some(index) => index,
+            None => return,
+        };
+        self.keys = self.replay(cpu, index, position, |_, _, _| {});
+        self.position = position;
+        self.snapshots.truncate(index + 1);
+        while self.inputs.back().map_or(false, |&(p, _)| p > position) {
+            self.inputs.pop_back();
+        }
+    }
+
+    // Restores the snapshot with the given index and executes the recorded
+    // cycles up to the end position. Calls visit at every position reached.
+    // Returns the keys pressed at the end position.
+    fn replay<M, F>(&self, cpu: &mut Cpu<M>, index: usize, end: u64, mut visit: F) -> u8
+        where M: Memory, F: FnMut(u64, &Cpu<M>, &[MemoryAccess])
+    {
+        // Tracing, profiling and memory hooks only make sense for the actual
+        // emulation
+        let tracer = cpu.take_tracer();
+        let profiler = cpu.take_profiler();
+        cpu.set_memory_hooks_suspended(true);
+        cpu.set_record_memory_accesses(true);
+
+        let (start, ref snapshot, mut keys) = self.snapshots[index];
+        cpu.restore(snapshot);
+        let mut inputs = self.inputs.iter().skip_while(|&&(p, _)| p <= start).peekable();
+        visit(start, cpu, &[]);
+        for position in start + 1..end + 1 {
+            step(cpu, keys);
+            let accesses = cpu.take_memory_accesses();
+            while let Some(&&(_, input)) = inputs.peek().filter(|&&&(p, _)| p == position) {
+                match input {
+                    Input::Keys(k) => keys = k,
+                    Input::KeypadInterrupt => request_keypad_interrupt(cpu),
+                }
+                inputs.next();
+            }
+            visit(position, cpu, &accesses);
+        }
+
+        cpu.set_record_memory_accesses(false);
+        cpu.set_memory_hooks_suspended(false);
+        if let Some(tracer) = tracer {
+            cpu.set_tracer(tracer);
+        }
+        if let Some(profiler) = profiler {
+            cpu.set_profiler(profiler);
+        }
+        keys
+    }
+}
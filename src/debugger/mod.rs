@@ -0,0 +1,411 @@
+mod console;
+mod gdb;
+mod history;
+
+#[cfg(test)]
+mod tests;
+
+use cpu::{self, AccessKind, Cpu, CpuState, CpuStatus, MemoryAccess};
+use memory::Memory;
+use symbols::Symbols;
+
+use std::rc::Rc;
+
+pub use self::console::run_console;
+pub use self::gdb::GdbStub;
+pub use self::history::step;
+
+use self::history::History;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    A, F, B, C, D, E, H, L,
+    AF, BC, DE, HL, SP, PC,
+}
+
+impl Register {
+    pub fn from_name(name: &str) -> Option<Register> {
+        let register = match name.to_uppercase().as_str() {
+            "A" => Register::A,
+            "F" => Register::F,
+            "B" => Register::B,
+            "C" => Register::C,
+            "D" => Register::D,
+            "E" => Register::E,
+            "H" => Register::H,
+            "L" => Register::L,
+            "AF" => Register::AF,
+            "BC" => Register::BC,
+            "DE" => Register::DE,
+            "HL" => Register::HL,
+            "SP" => Register::SP,
+            "PC" => Register::PC,
+            _ => return None,
+        };
+        Some(register)
+    }
+
+    pub fn get(&self, state: &CpuState) -> u16 {
+        match *self {
+            Register::A => state.a as u16,
+            Register::F => state.f as u16,
+            Register::B => state.b as u16,
+            Register::C => state.c as u16,
+            Register::D => state.d as u16,
+            Register::E => state.e as u16,
+            Register::H => state.h as u16,
+            Register::L => state.l as u16,
+            Register::AF => state.af(),
+            Register::BC => state.bc(),
+            Register::DE => state.de(),
+            Register::HL => state.hl(),
+            Register::SP => state.sp,
+            Register::PC => state.pc,
+        }
+    }
+
+    pub fn set(&self, state: &mut CpuState, value: u16) {
+        match *self {
+            Register::A => state.a = value as u8,
+            Register::F => state.f = value as u8,
+            Register::B => state.b = value as u8,
+            Register::C => state.c = value as u8,
+            Register::D => state.d = value as u8,
+            Register::E => state.e = value as u8,
+            Register::H => state.h = value as u8,
+            Register::L => state.l = value as u8,
+            Register::AF => state.set_af(value),
+            Register::BC => state.set_bc(value),
+            Register::DE => state.set_de(value),
+            Register::HL => state.set_hl(value),
+            Register::SP => state.sp = value,
+            Register::PC => state.pc = value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// Compares a register with a value, e.g. "A == 0x10"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn matches(&self, state: &CpuState) -> bool {
+        let register = self.register.get(state);
+        match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::Less => register < self.value,
+            Comparison::LessOrEqual => register <= self.value,
+            Comparison::Greater => register > self.value,
+            Comparison::GreaterOrEqual => register >= self.value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub address: u16,
+    // Only break if this ROM bank is mapped to the address
+    pub bank: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+// Watches accesses to the address range from start to end (inclusive)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    fn contains(&self, address: u16) -> bool {
+        address >= self.start && address <= self.end
+    }
+
+    fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind_matches = match access.kind {
+            AccessKind::Fetch => false,
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+        };
+        kind_matches && self.contains(access.address)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakReason {
+    // Pause requested by the user
+    Interrupted,
+    Step,
+    Breakpoint(usize),
+    Execute(usize),
+    Access(usize, MemoryAccess),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunMode {
+    Continue,
+    Step,
+    // Runs until the command following the current one is reached with the
+    // same or a higher stack pointer, i.e. CALLs and RSTs are stepped over
+    StepOver,
+    // Runs until the current function has returned
+    StepOut,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    None,
+    Next,
+    Address { pc: u16, sp: u16 },
+    Return { sp: u16 },
+}
+
+pub struct Debugger {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    target: Target,
+    pending_break: Option<BreakReason>,
+    history: History,
+    symbols: Rc<Symbols>,
+}
+
+fn find_breakpoint<M: Memory>(breakpoints: &[(usize, Breakpoint)], watchpoints: &[(usize, Watchpoint)],
+                              cpu: &Cpu<M>) -> Option<BreakReason> {
+    let state = cpu.state();
+    let bank = cpu.memory().rom_bank(state.pc);
+    for &(id, ref breakpoint) in breakpoints {
+        let hit = breakpoint.address == state.pc
+            && breakpoint.bank.map_or(true, |b| bank == Some(b))
+            && breakpoint.condition.map_or(true, |c| c.matches(&state));
+        if hit {
+            return Some(BreakReason::Breakpoint(id));
+        }
+    }
+    for &(id, ref watchpoint) in watchpoints {
+        if watchpoint.execute && watchpoint.contains(state.pc) {
+            return Some(BreakReason::Execute(id));
+        }
+    }
+    None
+}
+
+fn find_access(watchpoints: &[(usize, Watchpoint)], accesses: &[MemoryAccess]) -> Option<BreakReason> {
+    for access in accesses {
+        for &(id, ref watchpoint) in watchpoints {
+            if watchpoint.matches(access) {
+                return Some(BreakReason::Access(id, *access));
+            }
+        }
+    }
+    None
+}
+
+// Whether the opcode is a CALL, conditional or not, or an RST
+fn is_call(opcode: u8) -> bool {
+    match opcode {
+        0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC => true,
+        _ => opcode & 0xC7 == 0xC7,
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            target: Target::None,
+            pending_break: None,
+            history: History::new(),
+            symbols: Rc::new(Symbols::new()),
+        }
+    }
+
+    // Symbols for naming addresses in the console
+    pub fn set_symbols(&mut self, symbols: Rc<Symbols>) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.create_id();
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.create_id();
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    // Removes the breakpoint or watchpoint with the given id. Returns false if
+    // there is none.
+    pub fn delete(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|&(i, _)| i != id);
+        self.watchpoints.retain(|&(i, _)| i != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    // Stops the emulation before the next command
+    pub fn interrupt(&mut self) {
+        self.pending_break = Some(BreakReason::Interrupted);
+    }
+
+    pub fn resume<M: Memory>(&mut self, mode: RunMode, cpu: &Cpu<M>) {
+        let state = cpu.state();
+        self.target = match mode {
+            RunMode::Continue => Target::None,
+            RunMode::Step => Target::Next,
+            RunMode::StepOver => {
+                let instruction = cpu::disassemble(cpu.memory(), state.pc);
+                if is_call(instruction.bytes[0]) {
+                    Target::Address {
+                        pc: state.pc.wrapping_add(instruction.length as u16),
+                        sp: state.sp,
+                    }
+                } else {
+                    Target::Next
+                }
+            }
+            // The return address of the current function is on top of the
+            // stack, unless the function pushed further values
+            RunMode::StepOut => Target::Return { sp: state.sp },
+        };
+        self.pending_break = None;
+    }
+
+    // Checks whether the emulation has to stop before the CPU executes its
+    // next command. After resuming, the command at the current address has to
+    // be executed before checking again, or the same breakpoint would trigger.
+    pub fn check_before_command<M: Memory>(&mut self, cpu: &Cpu<M>) -> Option<BreakReason> {
+        if let Some(reason) = self.pending_break.take() {
+            return Some(reason);
+        }
+        if cpu.status() != CpuStatus::Running {
+            return None;
+        }
+        let state = cpu.state();
+        let target_reached = match self.target {
+            Target::None => false,
+            Target::Next => true,
+            Target::Address { pc, sp } => state.pc == pc && state.sp >= sp,
+            Target::Return { sp } => state.sp > sp,
+        };
+        if target_reached {
+            return Some(BreakReason::Step);
+        }
+        find_breakpoint(&self.breakpoints, &self.watchpoints, cpu)
+    }
+
+    // Checks the memory accesses of the last command against the watchpoints.
+    // A hit stops the emulation before the next command.
+    pub fn check_accesses(&mut self, accesses: &[MemoryAccess]) {
+        if self.pending_break.is_some() {
+            return;
+        }
+        self.pending_break = find_access(&self.watchpoints, accesses);
+    }
+
+    // Has to be called by the emulation loop before every CPU cycle, so that
+    // the execution can be reversed
+    pub fn record_step<M: Memory>(&mut self, cpu: &Cpu<M>, keys: u8) {
+        self.history.record_step(cpu, keys);
+    }
+
+    // Requests the joypad interrupt as an input that is part of the history
+    pub fn request_keypad_interrupt<M: Memory>(&mut self, cpu: &mut Cpu<M>) {
+        self.history.request_keypad_interrupt(cpu);
+    }
+
+    // Has to be called when registers or memory were changed by the user, as
+    // the execution up to this point can not be replayed anymore
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // Goes back to the previous command. Returns false if the history does
+    // not reach back further.
+    pub fn step_back<M: Memory>(&mut self, cpu: &mut Cpu<M>) -> bool {
+        let current = self.history.position();
+        let found = self.history.find_last(cpu, |cpu, _| {
+            if cpu.status() == CpuStatus::Running { Some(()) } else { None }
+        });
+        let position = found.map_or(current, |(position, _)| position);
+        self.go_to(cpu, position);
+        found.is_some()
+    }
+
+    // Runs backwards to the last breakpoint or watchpoint hit. If there is
+    // none, stops at the start of the history and returns None.
+    pub fn reverse_continue<M: Memory>(&mut self, cpu: &mut Cpu<M>) -> Option<BreakReason> {
+        let found = {
+            let breakpoints = &self.breakpoints;
+            let watchpoints = &self.watchpoints;
+            self.history.find_last(cpu, |cpu, accesses| {
+                find_access(watchpoints, accesses).or_else(|| {
+                    if cpu.status() == CpuStatus::Running {
+                        find_breakpoint(breakpoints, watchpoints, cpu)
+                    } else {
+                        None
+                    }
+                })
+            })
+        };
+        let position = match found {
+            Some((position, _)) => position,
+            None => self.history.start().unwrap_or(self.history.position()),
+        };
+        self.go_to(cpu, position);
+        found.map(|(_, reason)| reason)
+    }
+
+    fn go_to<M: Memory>(&mut self, cpu: &mut Cpu<M>, position: u64) {
+        self.history.go_to(cpu, position);
+        self.target = Target::None;
+        self.pending_break = None;
+    }
+
+    fn create_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
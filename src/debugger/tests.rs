@@ -0,0 +1,138 @@
+use super::*;
+use memory::BlockMemory;
+
+fn create_cpu(program: &[u8]) -> Cpu<BlockMemory> {
+    let mut memory = BlockMemory::new(0x10000);
+    for (address, &byte) in program.iter().enumerate() {
+        memory.write_byte(address as u16, byte);
+    }
+    let mut cpu = Cpu::new(memory);
+    let mut state = cpu.state();
+    state.sp = 0xFFFE;
+    cpu.set_state(&state);
+    cpu
+}
+
+// Runs the CPU until the debugger stops it
+fn run(debugger: &mut Debugger, cpu: &mut Cpu<BlockMemory>) -> BreakReason {
+    for _ in 0..1000 {
+        debugger.record_step(cpu, 0);
+        step(cpu, 0);
+        let accesses = cpu.take_memory_accesses();
+        debugger.check_accesses(&accesses);
+        if let Some(reason) = debugger.check_before_command(cpu) {
+            return reason;
+        }
+    }
+    panic!("Debugger did not stop");
+}
+
+// 0x00: LD A,$05
+// 0x02: CALL $0010
+// 0x05: LD ($C000),A
+// 0x08: JR $0008
+// 0x10: DEC A
+// 0x11: JR NZ,$0010
+// 0x13: RET
+const PROGRAM: [u8; 20] = [
+    0x3E, 0x05, 0xCD, 0x10, 0x00, 0xEA, 0x00, 0xC0, 0x18, 0xFE,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3D, 0x20, 0xFD, 0xC9,
+];
+
+#[test]
+fn test_conditional_breakpoint() {
+    let mut cpu = create_cpu(&PROGRAM);
+    let mut debugger = Debugger::new();
+    let condition = Condition { register: Register::A, comparison: Comparison::Equal, value: 2 };
+    let id = debugger.add_breakpoint(Breakpoint { address: 0x10, bank: None, condition: Some(condition) });
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Breakpoint(id));
+    assert_eq!(cpu.state().a, 2);
+    assert_eq!(cpu.state().pc, 0x10);
+}
+
+#[test]
+fn test_step_over_and_out() {
+    let mut cpu = create_cpu(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.resume(RunMode::Step, &cpu);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Step);
+    assert_eq!(cpu.state().pc, 0x02);
+    debugger.resume(RunMode::StepOver, &cpu);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Step);
+    assert_eq!(cpu.state().pc, 0x05);
+    assert_eq!(cpu.state().a, 0);
+
+    let mut cpu = create_cpu(&PROGRAM);
+    let id = debugger.add_breakpoint(Breakpoint { address: 0x11, bank: None, condition: None });
+    run(&mut debugger, &mut cpu);
+    debugger.delete(id);
+    debugger.resume(RunMode::StepOut, &cpu);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Step);
+    assert_eq!(cpu.state().pc, 0x05);
+}
+
+#[test]
+fn test_step_over_rst_and_conditional_call() {
+    // 0x00: RST $10
+    // 0x01: CALL NZ,$0010
+    // 0x04: JR $0004
+    // 0x10: RET
+    let mut program = [0; 0x11];
+    program[..6].copy_from_slice(&[0xD7, 0xC4, 0x10, 0x00, 0x18, 0xFE]);
+    program[0x10] = 0xC9;
+    let mut cpu = create_cpu(&program);
+    let mut debugger = Debugger::new();
+    debugger.resume(RunMode::StepOver, &cpu);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Step);
+    assert_eq!(cpu.state().pc, 0x01);
+    let mut state = cpu.state();
+    state.f = 0x00;
+    cpu.set_state(&state);
+    debugger.resume(RunMode::StepOver, &cpu);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Step);
+    assert_eq!(cpu.state().pc, 0x04);
+}
+
+#[test]
+fn test_write_watchpoint() {
+    let mut cpu = create_cpu(&PROGRAM);
+    cpu.set_record_memory_accesses(true);
+    let mut debugger = Debugger::new();
+    let id = debugger.add_watchpoint(
+        Watchpoint { start: 0xC000, end: 0xC0FF, read: false, write: true, execute: false });
+    let access = MemoryAccess { address: 0xC000, value: 0, kind: AccessKind::Write };
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Access(id, access));
+    assert_eq!(cpu.state().pc, 0x08);
+}
+
+#[test]
+fn test_reverse_execution() {
+    let mut cpu = create_cpu(&PROGRAM);
+    cpu.set_record_memory_accesses(true);
+    let mut debugger = Debugger::new();
+    let watch_id = debugger.add_watchpoint(
+        Watchpoint { start: 0xC000, end: 0xC000, read: false, write: true, execute: false });
+    let break_id = debugger.add_breakpoint(Breakpoint { address: 0x08, bank: None, condition: None });
+    let access = MemoryAccess { address: 0xC000, value: 0, kind: AccessKind::Write };
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Access(watch_id, access));
+    debugger.resume(RunMode::Continue, &cpu);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Breakpoint(break_id));
+
+    assert_eq!(debugger.reverse_continue(&mut cpu), Some(BreakReason::Access(watch_id, access)));
+    assert_eq!(cpu.state().pc, 0x08);
+    assert!(debugger.step_back(&mut cpu));
+    assert_eq!(cpu.state().pc, 0x05);
+    assert!(debugger.step_back(&mut cpu));
+    assert_eq!(cpu.state().pc, 0x13);
+    assert_eq!(cpu.state().sp, 0xFFFC);
+
+    assert_eq!(debugger.reverse_continue(&mut cpu), None);
+    assert_eq!(cpu.state().pc, 0x00);
+    assert!(!debugger.step_back(&mut cpu));
+
+    // The execution is repeated the same way
+    debugger.resume(RunMode::Continue, &cpu);
+    cpu.set_record_memory_accesses(true);
+    assert_eq!(run(&mut debugger, &mut cpu), BreakReason::Access(watch_id, access));
+    assert_eq!(cpu.state().a, 0);
+}
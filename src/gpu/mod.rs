@@ -17,6 +17,7 @@ use self::vram::Vram;
 use std::cmp;
 pub use self::constants::CLOCK_TICKS_PER_FRAME;
 
+#[derive(Clone)]
 pub struct GpuState {
     mode: Mode,
     pub bg_on: bool,
@@ -65,6 +66,31 @@ impl GpuState {
     pub fn get_current_line(&self) -> u8 {
         self.current_line
     }
+
+    // The CPU can not access VRAM while a line is drawn
+    pub fn is_vram_accessible(&self) -> bool {
+        match self.mode {
+            Mode::ScanlineVram => !self.display_on,
+            _ => true,
+        }
+    }
+
+    // The CPU can not access OAM while it is searched for sprites and while a
+    // line is drawn
+    pub fn is_oam_accessible(&self) -> bool {
+        match self.mode {
+            Mode::ScanlineOam | Mode::ScanlineVram => !self.display_on,
+            _ => true,
+        }
+    }
+}
+
+// Saved state of the GPU, excluding the display
+pub struct GpuSnapshot {
+    mode_clock: u32,
+    vram: Vram,
+    oam: BlockMemory,
+    state: GpuState,
 }
 
 pub struct Gpu<D>
@@ -115,6 +141,22 @@ impl<D> Gpu<D>
         gpu
     }
 
+    pub fn snapshot(&self) -> GpuSnapshot {
+        GpuSnapshot {
+            mode_clock: self.mode_clock,
+            vram: self.vram.clone(),
+            oam: self.oam.clone(),
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &GpuSnapshot) {
+        self.mode_clock = snapshot.mode_clock;
+        self.vram = snapshot.vram.clone();
+        self.oam = snapshot.oam.clone();
+        self.state = snapshot.state.clone();
+    }
+
     pub fn get_vram(&self) -> &Memory {
         &self.vram
     }
@@ -5,6 +5,7 @@ pub enum Palette {
     ObjectPalette1,
 }
 
+#[derive(Clone)]
 pub struct Palettes {
     pub bg: u8,
     pub obj0: u8,
@@ -159,3 +159,21 @@ fn test_simple_background_tile_map_0_set_1() {
     assert_eq!(0, pixels[0xF]);
 }
 
+
+#[test]
+fn test_video_memory_access_by_mode() {
+    let mut pixels = [0; PIXELS];
+    let mut gpu = Gpu::new(MockDisplay::new(&mut pixels));
+    gpu.state.set_display_on(true);
+    // Mode 2: searching OAM
+    assert!(gpu.state.is_vram_accessible() && !gpu.state.is_oam_accessible());
+    gpu.step(SCANLINE_OAM_TIME as u8);
+    // Mode 3: drawing
+    assert!(!gpu.state.is_vram_accessible() && !gpu.state.is_oam_accessible());
+    gpu.state.set_display_on(false);
+    assert!(gpu.state.is_vram_accessible() && gpu.state.is_oam_accessible());
+    gpu.state.set_display_on(true);
+    gpu.step(SCANLINE_VRAM_TIME as u8);
+    // Mode 0: horizontal blank
+    assert!(gpu.state.is_vram_accessible() && gpu.state.is_oam_accessible());
+}
@@ -8,6 +8,7 @@ pub const OFFSET_TILE_MAP_0: u16 = 0x1800;
 pub const OFFSET_TILE_MAP_1: u16 = 0x1C00;
 pub const TILE_SIZE_IN_BYTES: u16 = 0x10;
 
+#[derive(Clone)]
 pub struct Vram {
     memory: BlockMemory,
 }
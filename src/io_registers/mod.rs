@@ -1,10 +1,13 @@
-use apu::Apu;
+use apu::{Apu, ApuSnapshot};
 use display::Display;
 use gpu::{Gpu, TileMap, TileSet};
 use memory::{BlockMemory, Memory};
 use timer::{Timer, TimerSpeed};
 use std::cell::RefCell;
 
+#[cfg(test)]
+mod tests;
+
 const OFFSET_JOYPAD: u16 = 0x00;
 const OFFSET_SERIAL_TRANSFER_CONTROL: u16 = 0x02;
 const OFFSET_DIVIDER_REGISTER: u16 = 0x04;
@@ -41,10 +44,21 @@ const OFFSET_LY: u16 = 0x44;
 const OFFSET_LYC: u16 = 0x45;
 const OFFSET_WINDOW_Y: u16 = 0x4A;
 const OFFSET_WINDOW_X: u16 = 0x4B;
+const OFFSET_PREPARE_SPEED_SWITCH: u16 = 0x4D;
 const OFFSET_BACKGROUND_PALETTE: u16 = 0x47;
 const OFFSET_OBJECT0_PALETTE: u16 = 0x48;
 const OFFSET_OBJECT1_PALETTE: u16 = 0x49;
 
+// Saved state of the IO registers and the devices behind them. The GPU is
+// saved separately.
+pub struct IoSnapshot {
+    old_io: BlockMemory,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    apu: ApuSnapshot,
+    timer: Timer,
+}
+
 pub struct IoRegisters<'a, D>
 where
     D: Display + 'a,
@@ -53,6 +67,9 @@ where
     apu: &'a RefCell<Apu<'a>>,
     gpu: &'a RefCell<Gpu<D>>,
     timer: &'a RefCell<Timer>,
+    cgb_mode: bool,
+    double_speed: bool,
+    speed_switch_armed: bool,
 }
 
 impl<'a, D> IoRegisters<'a, D>
@@ -63,14 +80,60 @@ where
         apu: &'a RefCell<Apu<'a>>,
         gpu: &'a RefCell<Gpu<D>>,
         timer: &'a RefCell<Timer>,
+        cgb_mode: bool,
     ) -> IoRegisters<'a, D> {
         IoRegisters {
             old_io: BlockMemory::new(0x80),
             apu,
             gpu,
             timer,
+            cgb_mode,
+            double_speed: false,
+            speed_switch_armed: false,
+        }
+    }
+
+    // Advances the timer and the APU by the given number of CPU clock cycles
+    pub fn step(&self, cycles: u8) {
+        self.timer.borrow_mut().increase(cycles);
+        // The timer is clocked by the CPU, while the APU keeps running at
+        // normal speed in double speed mode
+        let apu_cycles = if self.double_speed { cycles / 2 } else { cycles };
+        self.apu.borrow_mut().step(apu_cycles);
+    }
+
+    pub fn snapshot(&self) -> IoSnapshot {
+        IoSnapshot {
+            old_io: self.old_io.clone(),
+            double_speed: self.double_speed,
+            speed_switch_armed: self.speed_switch_armed,
+            apu: self.apu.borrow().snapshot(),
+            timer: self.timer.borrow().clone(),
         }
     }
+
+    pub fn restore(&mut self, snapshot: &IoSnapshot) {
+        self.old_io = snapshot.old_io.clone();
+        self.double_speed = snapshot.double_speed;
+        self.speed_switch_armed = snapshot.speed_switch_armed;
+        self.apu.borrow_mut().restore(&snapshot.apu);
+        *self.timer.borrow_mut() = snapshot.timer.clone();
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    // Toggles the CPU speed if a speed switch has been prepared via KEY1.
+    // Returns whether the speed was switched.
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
+        }
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        true
+    }
 }
 
 impl<'a, D> Memory for IoRegisters<'a, D>
@@ -227,9 +290,13 @@ where
             OFFSET_BACKGROUND_PALETTE => self.gpu.borrow().state.palettes.bg,
             OFFSET_OBJECT0_PALETTE => self.gpu.borrow().state.palettes.obj0,
             OFFSET_OBJECT1_PALETTE => self.gpu.borrow().state.palettes.obj1,
+            OFFSET_PREPARE_SPEED_SWITCH if self.cgb_mode => {
+                let double_speed = (self.double_speed as u8) << 7;
+                let speed_switch_armed = self.speed_switch_armed as u8;
+                double_speed | speed_switch_armed | 0b0111_1110 // bits 1-6 unused
+            }
             // Completely unused bytes
-            0x03 | 0x08 | 0x09 | 0x0A | 0x0B | 0x0C | 0x0D | 0x0E | 0x15 | 0x1F | 0x27 | 0x28 |
-            0x29 | 0x4C...0x7F => 0xFF,
+            0x03 | 0x08...0x0E | 0x15 | 0x1F | 0x27...0x2F | 0x4C...0x7F => 0xFF,
             _ => old_io,
         }
     }
@@ -427,6 +494,11 @@ where
             OFFSET_BACKGROUND_PALETTE => self.gpu.borrow_mut().state.palettes.bg = value,
             OFFSET_OBJECT0_PALETTE => self.gpu.borrow_mut().state.palettes.obj0 = value,
             OFFSET_OBJECT1_PALETTE => self.gpu.borrow_mut().state.palettes.obj1 = value,
+            OFFSET_PREPARE_SPEED_SWITCH => {
+                if self.cgb_mode {
+                    self.speed_switch_armed = value & 0b0000_0001 != 0;
+                }
+            }
             _ => self.old_io.write_byte(address, value),
         }
     }
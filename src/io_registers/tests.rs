@@ -0,0 +1,35 @@
+use super::*;
+use gpu::Gpu;
+use test_utils::{NullAudioDevice, NullDisplay};
+
+// Runs for the given number of CPU clock cycles and returns the number of
+// audio samples produced and DIV
+fn run(double_speed: bool, cycles: u32) -> (usize, u8) {
+    let audio_device = NullAudioDevice::default();
+    let apu = RefCell::new(Apu::new(&audio_device));
+    let gpu = RefCell::new(Gpu::new(NullDisplay));
+    let timer = RefCell::new(Timer::new());
+    let mut io = IoRegisters::new(&apu, &gpu, &timer, true);
+    if double_speed {
+        io.write_byte(0x4D, 0x01);
+        assert!(io.switch_speed());
+    }
+    assert_eq!(io.is_double_speed(), double_speed);
+    for _ in 0..cycles / 4 {
+        io.step(4);
+    }
+    let divider = io.read_byte(0x04);
+    (audio_device.samples.get(), divider)
+}
+
+#[test]
+fn test_double_speed_clocks_timer_but_not_apu() {
+    let (normal_samples, normal_divider) = run(false, 0x21000);
+    let (double_samples, double_divider) = run(true, 0x42000);
+    assert!(normal_samples > 0);
+    // The APU needs twice as many CPU cycles for the same sound, while DIV
+    // counts every CPU cycle
+    assert_eq!(double_samples, normal_samples);
+    assert_eq!(normal_divider, 0x10);
+    assert_eq!(double_divider, 0x20);
+}
@@ -10,6 +10,7 @@ const KEY_A: Keycode = Keycode::U;
 const KEY_B: Keycode = Keycode::I;
 const KEY_START: Keycode = Keycode::Return;
 const KEY_SELECT: Keycode = Keycode::Space;
+const KEY_DEBUGGER: Keycode = Keycode::F12;
 
 fn key_to_index(keycode: Keycode) -> Option<usize> {
     match keycode {
@@ -25,10 +26,30 @@ fn key_to_index(keycode: Keycode) -> Option<usize> {
     }
 }
 
+// Updates the joypad register for the keys in the given mask, see
+// Keyboard::key_mask
+pub fn apply_key_mask(mask: u8, register: &mut u8) {
+    let pressed = |key| mask & 1 << key_to_index(key).unwrap() != 0;
+    *register |= 0xF;
+    if *register & 0x10 != 0 {
+        if pressed(KEY_A)      { *register &= !0x1; }
+        if pressed(KEY_B)      { *register &= !0x2; }
+        if pressed(KEY_SELECT) { *register &= !0x4; }
+        if pressed(KEY_START)  { *register &= !0x8; }
+    }
+    if *register & 0x20 != 0 {
+        if pressed(KEY_RIGHT)  { *register &= !0x1; }
+        if pressed(KEY_LEFT)   { *register &= !0x2; }
+        if pressed(KEY_UP)     { *register &= !0x4; }
+        if pressed(KEY_DOWN)   { *register &= !0x8; }
+    }
+}
+
 pub struct Keyboard<'a> {
     key_statuses: [bool; 8],
     event_pump: &'a mut EventPump,
     program_end_triggered: bool,
+    debugger_triggered: bool,
 }
 
 impl<'a> Keyboard<'a> {
@@ -37,35 +58,33 @@ impl<'a> Keyboard<'a> {
             key_statuses: [false; 8],
             event_pump,
             program_end_triggered: false,
+            debugger_triggered: false,
         }
     }
 
-    pub fn update_key_register(&self, register: &mut u8) {
-        *register |= 0xF;
-        if *register & 0x10 != 0 {
-            if self.key_statuses[key_to_index(KEY_A).unwrap()]      { *register &= !0x1; }
-            if self.key_statuses[key_to_index(KEY_B).unwrap()]      { *register &= !0x2; }
-            if self.key_statuses[key_to_index(KEY_SELECT).unwrap()] { *register &= !0x4; }
-            if self.key_statuses[key_to_index(KEY_START).unwrap()]  { *register &= !0x8; }
-        }
-        if *register & 0x20 != 0 {
-            if self.key_statuses[key_to_index(KEY_RIGHT).unwrap()]  { *register &= !0x1; }
-            if self.key_statuses[key_to_index(KEY_LEFT).unwrap()]   { *register &= !0x2; }
-            if self.key_statuses[key_to_index(KEY_UP).unwrap()]     { *register &= !0x4; }
-            if self.key_statuses[key_to_index(KEY_DOWN).unwrap()]   { *register &= !0x8; }
-        }
+    // Returns the pressed keys with bit n set for the key with index n
+    pub fn key_mask(&self) -> u8 {
+        self.key_statuses.iter().enumerate().fold(0, |mask, (i, &pressed)| mask | (pressed as u8) << i)
     }
 
     pub fn program_end_triggered(&self) -> bool {
         self.program_end_triggered
     }
 
+    // Returns whether the debugger was requested since the last call
+    pub fn take_debugger_triggered(&mut self) -> bool {
+        let triggered = self.debugger_triggered;
+        self.debugger_triggered = false;
+        triggered
+    }
+
     pub fn check_events(&mut self) -> bool {
         let mut pressed = false;
         while let Some(event) = self.event_pump.poll_event() {
             match event {
                 Event::Quit { .. } |
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => self.program_end_triggered = true,
+                Event::KeyDown { keycode: Some(KEY_DEBUGGER), .. } => self.debugger_triggered = true,
                 Event::KeyDown { keycode: Some(key), .. } => pressed |= self.key_down(key),
                 Event::KeyUp { keycode: Some(key), .. } => self.key_up(key),
                 _ => {}
@@ -3,28 +3,107 @@ extern crate sdl2;
 
 mod apu;
 mod audio;
+mod battery;
+mod boot;
+mod code_data_log;
 mod cpu;
+mod debugger;
 mod display;
 mod gpu;
 mod io_registers;
 mod keyboard;
 mod mbc;
 mod memory;
+mod memory_hooks;
+mod model;
+mod oam_dma;
+mod rtc;
+mod symbols;
+#[cfg(test)]
+mod test_utils;
 mod timer;
 
-use memory::Memory;
+pub use debugger::{Breakpoint, BreakReason, Comparison, Condition, Debugger, Register, RunMode, Watchpoint};
+pub use cpu::{AccessKind, MemoryAccess, Cpu, Profiler, CpuState, CpuStatus, disassemble, disassemble_all, disassemble_bytes, Instruction, Operand};
+pub use memory::Memory;
+pub use model::Model;
+pub use memory_hooks::{HookId, HookType, MemoryEvent, MemoryHooks};
+pub use symbols::Symbols;
 
 use std::cell::RefCell;
 use std::fs::File;
+use std::io::BufWriter;
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::{thread, time};
 
-const BIOS_PATH: &str = "roms/bios.gb";
+// Used if no boot ROM is given and the file exists
+const DEFAULT_BOOT_ROM_PATH: &str = "roms/bios.gb";
 const FRAME_LENGTH_IN_S: f64 = gpu::CLOCK_TICKS_PER_FRAME as f64 / cpu::CLOCK_SPEED_IN_HERTZ as f64;
 const FRAME_LENGTH_IN_NS: u32 = (FRAME_LENGTH_IN_S * 1e9) as u32;
 
-pub fn run(file: &mut File) {
+// Settings for a single emulator run, usually given on the command line
+#[derive(Default)]
+pub struct Options {
+    // Write an instruction trace in Gameboy Doctor format to this file
+    pub trace_file: Option<PathBuf>,
+    // Only trace commands with a PC in this range (inclusive)
+    pub trace_pc_range: Option<(u16, u16)>,
+    // Only trace commands in this ROM bank
+    pub trace_rom_bank: Option<u16>,
+    // Write the labels of the symbol file into the trace, which then differs
+    // from the Gameboy Doctor format
+    pub trace_labels: bool,
+    // Start in the debugger console. It can also be opened with F12, but the
+    // history for going back then only starts there.
+    pub debug: bool,
+    // Write a profile of the executed code to this file when the emulator exits
+    pub profile_file: Option<PathBuf>,
+    // Also write the profiled call stacks in the folded format used by
+    // flamegraph tools
+    pub profile_folded_file: Option<PathBuf>,
+    // Wait for gdb to connect on this local port and let it control the
+    // emulation instead of the debugger console
+    pub gdb_port: Option<u16>,
+    // Symbol file with labels for the ROM. By default, a .sym file next to
+    // the ROM is used if there is one.
+    pub symbol_file: Option<PathBuf>,
+    // Record how the ROM and cartridge RAM bytes are used (code, data or DMA
+    // source) and write the code/data log to this file at exit. An existing
+    // log is continued. Every read has to be seen, so the instruction cache
+    // is not used then. The log is only written when the emulation ends
+    // normally, i.e. not if it panics or the process is killed.
+    pub cdl_file: Option<PathBuf>,
+    // Callbacks on memory accesses and bank switches. Hooks can still be
+    // added and removed through this handle while the emulation runs.
+    pub memory_hooks: Option<Rc<RefCell<MemoryHooks>>>,
+    // Boot ROM to run before the cartridge. Without one, the emulation starts
+    // at 0x0100 in the state the boot ROM leaves behind.
+    pub boot_rom: Option<PathBuf>,
+    // Model to emulate. By default, a CGB for cartridges that support it and
+    // a DMG otherwise.
+    pub model: Option<Model>,
+    // Let the CPU access VRAM and OAM while the GPU uses them, unlike the
+    // hardware
+    pub unlock_video_memory: bool,
+}
+
+fn load_symbols(rom_path: &Path, options: &Options) -> Symbols {
+    match options.symbol_file {
+        Some(ref path) => Symbols::load(path),
+        None => {
+            let path = rom_path.with_extension("sym");
+            if path.is_file() { Symbols::load(&path) } else { Symbols::new() }
+        }
+    }
+}
+
+pub fn run(rom_path: &Path, options: &Options) {
+    let mut file = File::open(rom_path)
+        .unwrap_or_else(|e| panic!("Error opening file {}: {}", rom_path.display(), e));
+    let symbols = Rc::new(load_symbols(rom_path, options));
+
     let sdl_context = sdl2::init().unwrap();
 
     let audio_device = audio::create_audio_device(&sdl_context);
@@ -34,39 +113,121 @@ pub fn run(file: &mut File) {
     let mut event_pump = sdl_context.event_pump().unwrap();
     let mut keyboard = keyboard::Keyboard::new(&mut event_pump);
 
-    let path = Path::new(BIOS_PATH);
-    let mut bios = File::open(path).expect(&format!("Error opening file: {}", BIOS_PATH));
-
-    let rom = memory::BlockMemory::new_from_file(file);
-    let mbc = mbc::create_mbc(rom);
-    let mut bios = memory::BlockMemory::new_from_file(&mut bios);
+    let rom = memory::BlockMemory::new_from_file(&mut file);
+    let cgb_rom = rom.read_byte(0x143) & 0x80 != 0;
+    let model = options.model.unwrap_or_else(|| Model::for_rom(rom.read_byte(0x143)));
+    let code_data_log = options.cdl_file.as_ref().map(|path| {
+        let code_data_log = code_data_log::CodeDataLog::new(rom.get_memory().len(), mbc::ram_size(&rom));
+        code_data_log.load(path);
+        Rc::new(code_data_log)
+    });
+    let mbc = mbc::create_mbc(rom, code_data_log.clone());
+    let boot_rom = options.boot_rom.clone().or_else(|| {
+        let path = PathBuf::from(DEFAULT_BOOT_ROM_PATH);
+        if path.is_file() { Some(path) } else { None }
+    });
+    let mut bios = match boot_rom {
+        Some(ref path) => {
+            let mut file = File::open(path)
+                .unwrap_or_else(|e| panic!("Error opening boot ROM {}: {}", path.display(), e));
+            memory::BlockMemory::new_from_file(&mut file)
+        }
+        None => memory::BlockMemory::new(0),
+    };
     let timer = RefCell::new(timer::Timer::new());
     let gpu = RefCell::new(gpu::Gpu::new(display));
     let apu = RefCell::new(apu::Apu::new(audio_device.deref()));
-    let io = RefCell::new(io_registers::IoRegisters::new(&apu, &gpu, &timer));
-    let memory_map = memory::MemoryMap::new(&mut bios, mbc, &gpu, &io);
+    let io = RefCell::new(io_registers::IoRegisters::new(&apu, &gpu, &timer, model.is_cgb() && cgb_rom));
+    let mut memory_map = memory::MemoryMap::new(model, &mut bios, mbc, &gpu, &io);
+    if let Some(ref hooks) = options.memory_hooks {
+        memory_map.set_hooks(hooks.clone());
+    }
+    memory_map.set_lock_video_memory(!options.unlock_video_memory);
     let mut cpu = cpu::Cpu::new(memory_map);
+    if boot_rom.is_none() {
+        boot::skip_boot_rom(&mut cpu, &timer, model);
+    }
+    if let Some(ref path) = options.trace_file {
+        let trace_file = File::create(path)
+            .unwrap_or_else(|e| panic!("Error creating trace file {}: {}", path.display(), e));
+        let filter = cpu::TraceFilter {
+            pc_range: options.trace_pc_range,
+            rom_bank: options.trace_rom_bank,
+        };
+        let labels = if options.trace_labels { Some(symbols.clone()) } else { None };
+        cpu.set_tracer(cpu::Tracer::new(Box::new(BufWriter::new(trace_file)), filter, labels));
+    }
+    if options.profile_file.is_some() || options.profile_folded_file.is_some() {
+        cpu.set_profiler(cpu::Profiler::new());
+    }
 
+    let mut clock = 0u64;
     let mut next_frame = gpu::CLOCK_TICKS_PER_FRAME as u64;
     let mut frame_start = time::Instant::now();
     let frame_length = time::Duration::new(0, FRAME_LENGTH_IN_NS);
+    let mut lockup_reported = false;
+    let mut debugger = debugger::Debugger::new();
+    debugger.set_symbols(symbols.clone());
+    let mut gdb = options.gdb_port.map(debugger::GdbStub::listen);
+    if options.debug || gdb.is_some() {
+        debugger.interrupt();
+    }
+    // Memory accesses are only recorded while watchpoints need them
+    let mut watching = false;
+    // Taking snapshots for going back costs time and memory, so the history
+    // is only recorded once the debugger is used
+    let mut recording = options.debug || gdb.is_some();
     loop {
-        let cycles_of_last_command = cpu.cycle();
-        gpu.borrow_mut().step(cycles_of_last_command);
-        timer.borrow_mut().increase(cycles_of_last_command);
-
-        let mut key_register = io.borrow().read_byte(0x0);
-        keyboard.update_key_register(&mut key_register);
-        io.borrow_mut().write_byte(0x0, key_register);
+        if let Some(reason) = debugger.check_before_command(&cpu) {
+            let keep_running = match gdb {
+                Some(ref mut gdb) if gdb.is_connected() => gdb.run_session(&mut debugger, &mut cpu, reason),
+                _ => debugger::run_console(&mut debugger, &mut cpu, reason),
+            };
+            if !keep_running {
+                break;
+            }
+            frame_start = time::Instant::now();
+            recording = true;
+            watching = !debugger.watchpoints().is_empty();
+            cpu.set_record_memory_accesses(watching);
+        }
 
-        apu.borrow_mut().step(cycles_of_last_command);
+        // The CPU advances the rest of the system on every memory access
+        let keys = keyboard.key_mask();
+        if recording {
+            debugger.record_step(&cpu, keys);
+        }
+        let cycles_of_last_command = debugger::step(&mut cpu, keys);
+        if watching {
+            let accesses = cpu.take_memory_accesses();
+            debugger.check_accesses(&accesses);
+        }
+        if let cpu::CpuStatus::Locked { pc } = cpu.status() {
+            if !lockup_reported {
+                eprintln!("CPU locked up on illegal opcode {:#04X} at {:#06X}",
+                          cpu.memory().read_byte(pc), pc);
+                lockup_reported = true;
+            }
+        }
 
-        let clock = cpu.get_clock();
+        // Frames are timed in normal speed clock cycles
+        clock += if io.borrow().is_double_speed() {
+            cycles_of_last_command as u64 / 2
+        } else {
+            cycles_of_last_command as u64
+        };
         if clock > next_frame {
             let pressed = keyboard.check_events();
+            if keyboard.take_debugger_triggered() {
+                debugger.interrupt();
+            }
+            if let Some(ref mut gdb) = gdb {
+                if gdb.poll_interrupt() {
+                    debugger.interrupt();
+                }
+            }
             if pressed {
-                let interrupts_fired = io.borrow().read_byte(0x0F);
-                io.borrow_mut().write_byte(0x0F, interrupts_fired | 0b0001_0000);
+                debugger.request_keypad_interrupt(&mut cpu);
             }
             next_frame += gpu::CLOCK_TICKS_PER_FRAME as u64;
             let duration = frame_start.elapsed();
@@ -80,4 +241,24 @@ pub fn run(file: &mut File) {
             break;
         }
     }
+
+    if let Some(profiler) = cpu.take_profiler() {
+        write_profile(&profiler, &symbols, options);
+    }
+    if let (Some(code_data_log), Some(path)) = (code_data_log, options.cdl_file.as_ref()) {
+        code_data_log.write(path);
+    }
+}
+
+fn write_profile(profiler: &cpu::Profiler, symbols: &Symbols, options: &Options) {
+    if let Some(ref path) = options.profile_file {
+        let file = File::create(path)
+            .unwrap_or_else(|e| panic!("Error creating profile file {}: {}", path.display(), e));
+        profiler.write_report(&mut BufWriter::new(file), symbols);
+    }
+    if let Some(ref path) = options.profile_folded_file {
+        let file = File::create(path)
+            .unwrap_or_else(|e| panic!("Error creating folded stack file {}: {}", path.display(), e));
+        profiler.write_folded_stacks(&mut BufWriter::new(file), symbols);
+    }
 }
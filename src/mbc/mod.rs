@@ -0,0 +1,940 @@
+use battery;
+use battery::Battery;
+use code_data_log::CodeDataLog;
+use memory::{BlockMemory, Memory, MemorySnapshot, ReadKind};
+use rtc;
+use rtc::Rtc;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+// Creates the memory bank controller for the ROM. Reads by the CPU and the
+// DMA are recorded in the code/data log if one is given.
+pub fn create_mbc(rom: BlockMemory, code_data_log: Option<Rc<CodeDataLog>>) -> Box<Memory> {
+    let mbc_type = rom.read_byte(0x147);
+    println!("MBC type: {:#X}", mbc_type);
+    match mbc_type {
+        0x0 => Box::new(RomOnly { rom, code_data_log }),
+        0x1 | 0x2 | 0x3 => Box::new(Mbc1::new(rom, code_data_log)),
+        0x5 | 0x6 => Box::new(Mbc2::new(rom, code_data_log)),
+        0x8 | 0x9 => Box::new(RomRam::new(rom, code_data_log)),
+        0x0F ... 0x13 => Box::new(Mbc3::new(rom, code_data_log)),
+        0x19 ... 0x1E => Box::new(Mbc5::new(rom, code_data_log)),
+        _ => panic!("Unsupported Memory Bank Controller {:#X}", mbc_type),
+    }
+}
+
+// Size of the cartridge RAM the memory bank controller for the ROM provides,
+// without the clock of the MBC3. The code/data log covers the same bytes.
+pub fn ram_size(rom: &BlockMemory) -> usize {
+    match rom.read_byte(0x147) {
+        0x1 | 0x2 | 0x3 => 0x8000,
+        // The MBC2 has 512 half-bytes built in
+        0x5 | 0x6 => 0x200,
+        0x8 | 0x9 => 0x2000,
+        0x10 | 0x12 | 0x13 => header_ram_size(rom.read_byte(0x149)),
+        0x19 ... 0x1E => 0x20000,
+        _ => 0,
+    }
+}
+
+// Size of the cartridge RAM as given in the cartridge header
+fn header_ram_size(ram_size_code: u8) -> usize {
+    match ram_size_code {
+        0x01 => 0x800,
+        0x02 => 0x2000,
+        0x03 => 0x8000,
+        0x04 => 0x20000,
+        0x05 => 0x10000,
+        _ => 0,
+    }
+}
+
+// Calls one of the logging functions of the code/data log, if there is one
+// and the read is to be logged
+fn log_read(code_data_log: &Option<Rc<CodeDataLog>>, kind: Option<ReadKind>,
+            log: fn(&CodeDataLog, usize, ReadKind), offset: usize) {
+    if let (&Some(ref code_data_log), Some(kind)) = (code_data_log, kind) {
+        log(code_data_log, offset, kind);
+    }
+}
+
+// Cartridge with 32 KB ROM and no memory bank controller
+struct RomOnly {
+    rom: BlockMemory,
+    code_data_log: Option<Rc<CodeDataLog>>,
+}
+
+impl Memory for RomOnly {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000 ... 0x7FFF => self.rom.read_byte(address),
+            // There is no cartridge RAM, so the bus is left floating
+            _ => 0xFF,
+        }
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        if address < 0x8000 {
+            log_read(&self.code_data_log, Some(kind), CodeDataLog::log_rom, address as usize);
+        }
+        self.read_byte(address)
+    }
+
+    fn write_byte(&mut self, _address: u16, _value: u8) {}
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000 ... 0x7FFF => Some(address as usize),
+            _ => None,
+        }
+    }
+
+    // Logged reads can not be skipped by the instruction cache
+    fn bypass_instruction_cache(&self) -> bool {
+        self.code_data_log.is_some()
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        self.rom.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        self.rom.restore(snapshot);
+    }
+}
+
+// Cartridge with 32 KB ROM and 8 KB RAM but no memory bank controller
+struct RomRam {
+    rom: BlockMemory,
+    ram: BlockMemory,
+    code_data_log: Option<Rc<CodeDataLog>>,
+    battery: Option<Battery>,
+}
+
+impl RomRam {
+    fn new(rom: BlockMemory, code_data_log: Option<Rc<CodeDataLog>>) -> RomRam {
+        let battery = if rom.read_byte(0x147) == 0x9 { Some(Battery::new(&rom)) } else { None };
+        let ram = battery::create_ram(&battery, ram_size(&rom));
+        RomRam {
+            rom,
+            ram,
+            code_data_log,
+            battery,
+        }
+    }
+
+    // Reads a byte and logs it in the code/data log if a kind is given
+    fn read(&self, address: u16, kind: Option<ReadKind>) -> u8 {
+        match address {
+            0x0000 ... 0x7FFF => {
+                log_read(&self.code_data_log, kind, CodeDataLog::log_rom, address as usize);
+                self.rom.read_byte(address)
+            }
+            0xA000 ... 0xBFFF => {
+                let address = (address & 0x1FFF) as usize;
+                log_read(&self.code_data_log, kind, CodeDataLog::log_ram, address);
+                self.ram.read_byte_usize(address)
+            }
+            _ => panic!("RomRam cannot handle read from address {:#X}.", address)
+        }
+    }
+}
+
+impl Memory for RomRam {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read(address, None)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        self.read(address, Some(kind))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xA000 ... 0xBFFF => self.ram.write_byte(address & 0x1FFF, value),
+            _ => (),
+        }
+    }
+
+    fn ram_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0xA000 ... 0xBFFF => Some(0),
+            _ => None,
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000 ... 0x7FFF => Some(address as usize),
+            _ => None,
+        }
+    }
+
+    fn bypass_instruction_cache(&self) -> bool {
+        self.code_data_log.is_some()
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        self.ram.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        self.ram.restore(snapshot);
+    }
+}
+
+impl Drop for RomRam {
+    fn drop(&mut self) {
+        if let Some(ref battery) = self.battery {
+            battery.save(&self.ram);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Mbc1Snapshot {
+    ram: BlockMemory,
+    current_zero_bank: u8,
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    ram_enabled: bool,
+    mode: RomRamMode,
+    lower_bits: u8,
+    upper_bits: u8,
+}
+
+struct Mbc1 {
+    rom: BlockMemory,
+    ram: BlockMemory,
+    code_data_log: Option<Rc<CodeDataLog>>,
+    has_ram: bool,
+    // Collection cartridges (MBC1M) with the upper bank bits wired to bits
+    // 4-5 of the bank number instead of 5-6
+    multicart: bool,
+    // ROM bank at 0x0000-0x3FFF, which only multicarts switch
+    current_zero_bank: u8,
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    ram_enabled: bool,
+    mode: RomRamMode,
+    lower_bits: u8,
+    upper_bits: u8,
+    battery: Option<Battery>,
+}
+
+impl Mbc1 {
+    fn new(rom: BlockMemory, code_data_log: Option<Rc<CodeDataLog>>) -> Mbc1 {
+        let mbc_type = rom.read_byte(0x147);
+        let has_ram = mbc_type == 0x2 || mbc_type == 0x3;
+        let battery = if mbc_type == 0x3 { Some(Battery::new(&rom)) } else { None };
+        let ram = battery::create_ram(&battery, ram_size(&rom));
+        let multicart = is_multicart(&rom);
+        Mbc1 {
+            rom,
+            ram,
+            code_data_log,
+            has_ram,
+            multicart,
+            current_zero_bank: 0,
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            ram_enabled: false,
+            mode: RomRamMode::RomBankingMode,
+            lower_bits: 1,
+            upper_bits: 0,
+            battery,
+        }
+    }
+
+    // Reads a byte and logs it in the code/data log if a kind is given
+    fn read(&self, address: u16, kind: Option<ReadKind>) -> u8 {
+        match address {
+            0x0000 ... 0x7FFF => {
+                let address = self.rom_address(address);
+                log_read(&self.code_data_log, kind, CodeDataLog::log_rom, address);
+                self.rom.read_byte_usize(address)
+            }
+            0xA000 ... 0xBFFF => {
+                if self.ram_enabled {
+                    let address = (address & 0x1FFF) as usize + self.current_ram_bank as usize * 0x2000;
+                    log_read(&self.code_data_log, kind, CodeDataLog::log_ram, address);
+                    self.ram.read_byte_usize(address)
+                } else {
+                    0xFF
+                }
+            }
+            _ => panic!("Mbc1 cannot handle read from address {:#X}.", address)
+        }
+    }
+
+    fn rom_address(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000 ... 0x3FFF => self.current_zero_bank,
+            _ => self.current_rom_bank,
+        };
+        // Banks beyond the end of the ROM wrap around
+        ((address & 0x3FFF) as usize + bank as usize * 0x4000) % self.rom.get_memory().len()
+    }
+
+    fn update_bank_numbers(&mut self) {
+        if self.multicart {
+            // Bit 4 of the lower bits is not connected, so the games can
+            // select their own bank 0 at 0x4000-0x7FFF
+            self.current_rom_bank = (self.lower_bits & 0xF) + (self.upper_bits << 4);
+            self.current_zero_bank = match self.mode {
+                RomRamMode::RomBankingMode => 0,
+                RomRamMode::RamBankingMode => self.upper_bits << 4,
+            };
+            return;
+        }
+        match self.mode {
+            RomRamMode::RomBankingMode => {
+                self.current_rom_bank = self.lower_bits + (self.upper_bits << 5);
+                self.current_ram_bank = 0;
+            }
+            RomRamMode::RamBankingMode => {
+                self.current_rom_bank = self.lower_bits;
+                self.current_ram_bank = self.upper_bits;
+            }
+        }
+    }
+}
+
+impl Memory for Mbc1 {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read(address, None)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        self.read(address, Some(kind))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ... 0x1FFF => self.ram_enabled = self.has_ram && (value & 0xF == 0xA),
+            0x2000 ... 0x3FFF => {
+                self.lower_bits = value & 0x1F;
+                if self.lower_bits == 0x00 || self.lower_bits == 0x20 ||
+                    self.lower_bits == 0x40 || self.lower_bits == 0x60 {
+                    self.lower_bits += 1;
+                }
+                self.update_bank_numbers();
+            }
+            0x4000 ... 0x5FFF => {
+                self.upper_bits = value & 0x3;
+                self.update_bank_numbers();
+            }
+            0x6000 ... 0x7FFF => {
+                if self.has_ram || self.multicart {
+                    self.mode = if value & 0x1 != 0 {
+                        RomRamMode::RamBankingMode
+                    } else {
+                        RomRamMode::RomBankingMode
+                    };
+                    self.update_bank_numbers();
+                }
+            }
+            0xA000 ... 0xBFFF => {
+                if self.ram_enabled {
+                    let address = (address & 0x1FFF) + self.current_ram_bank as u16 * 0x2000;
+                    self.ram.write_byte(address, value);
+                }
+            }
+            _ => panic!("Mbc1 cannot handle write to address {:#X}.", address)
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000 ... 0x3FFF => Some(self.current_zero_bank as u16),
+            0x4000 ... 0x7FFF => Some(self.current_rom_bank as u16),
+            _ => None,
+        }
+    }
+
+    fn ram_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0xA000 ... 0xBFFF if self.has_ram => Some(self.current_ram_bank as u16),
+            _ => None,
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000 ... 0x7FFF => Some(self.rom_address(address)),
+            _ => None,
+        }
+    }
+
+    fn bypass_instruction_cache(&self) -> bool {
+        self.code_data_log.is_some()
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        Some(Box::new(Mbc1Snapshot {
+            ram: self.ram.clone(),
+            current_zero_bank: self.current_zero_bank,
+            current_rom_bank: self.current_rom_bank,
+            current_ram_bank: self.current_ram_bank,
+            ram_enabled: self.ram_enabled,
+            mode: self.mode,
+            lower_bits: self.lower_bits,
+            upper_bits: self.upper_bits,
+        }))
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        let snapshot = snapshot.downcast_ref::<Mbc1Snapshot>().expect("Invalid snapshot").clone();
+        self.ram = snapshot.ram;
+        self.current_zero_bank = snapshot.current_zero_bank;
+        self.current_rom_bank = snapshot.current_rom_bank;
+        self.current_ram_bank = snapshot.current_ram_bank;
+        self.ram_enabled = snapshot.ram_enabled;
+        self.mode = snapshot.mode;
+        self.lower_bits = snapshot.lower_bits;
+        self.upper_bits = snapshot.upper_bits;
+    }
+}
+
+impl Drop for Mbc1 {
+    fn drop(&mut self) {
+        if let Some(ref battery) = self.battery {
+            battery.save(&self.ram);
+        }
+    }
+}
+
+// Logo in the header of every cartridge, which the boot ROM checks
+pub const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// Multicarts are 1 MB and contain a menu and up to three games of 256 KB
+// each. Like the menu, the games start with the Nintendo logo in their header.
+fn is_multicart(rom: &BlockMemory) -> bool {
+    let rom = rom.get_memory();
+    if rom.len() != 0x100000 {
+        return false;
+    }
+    let games = (1..4).filter(|game| {
+        let header = game * 0x40000 + 0x104;
+        rom[header..header + 0x30] == NINTENDO_LOGO
+    }).count();
+    games > 0
+}
+
+#[derive(Clone, Copy)]
+enum RomRamMode {
+    RomBankingMode,
+    RamBankingMode,
+}
+
+#[derive(Clone)]
+struct Mbc2Snapshot {
+    ram: BlockMemory,
+    current_rom_bank: u8,
+    ram_enabled: bool,
+}
+
+// MBC2 with up to 16 ROM banks and 512 half-bytes of RAM built in
+struct Mbc2 {
+    rom: BlockMemory,
+    ram: BlockMemory,
+    code_data_log: Option<Rc<CodeDataLog>>,
+    current_rom_bank: u8,
+    ram_enabled: bool,
+    battery: Option<Battery>,
+}
+
+impl Mbc2 {
+    fn new(rom: BlockMemory, code_data_log: Option<Rc<CodeDataLog>>) -> Mbc2 {
+        let battery = if rom.read_byte(0x147) == 0x6 { Some(Battery::new(&rom)) } else { None };
+        let ram = battery::create_ram(&battery, ram_size(&rom));
+        Mbc2 {
+            rom,
+            ram,
+            code_data_log,
+            current_rom_bank: 1,
+            ram_enabled: false,
+            battery,
+        }
+    }
+
+    fn rom_address(&self, address: u16) -> usize {
+        let address = match address {
+            0x0000 ... 0x3FFF => address as usize,
+            _ => (address & 0x3FFF) as usize + self.current_rom_bank as usize * 0x4000,
+        };
+        // Banks beyond the end of the ROM wrap around
+        address % self.rom.get_memory().len()
+    }
+
+    // Reads a byte and logs it in the code/data log if a kind is given
+    fn read(&self, address: u16, kind: Option<ReadKind>) -> u8 {
+        match address {
+            0x0000 ... 0x7FFF => {
+                let address = self.rom_address(address);
+                log_read(&self.code_data_log, kind, CodeDataLog::log_rom, address);
+                self.rom.read_byte_usize(address)
+            }
+            0xA000 ... 0xBFFF => {
+                if self.ram_enabled {
+                    // The RAM is mirrored over the whole area. Only the lower
+                    // nibble is connected, the upper one reads as 1s.
+                    let address = (address & 0x1FF) as usize;
+                    log_read(&self.code_data_log, kind, CodeDataLog::log_ram, address);
+                    self.ram.read_byte_usize(address) | 0xF0
+                } else {
+                    0xFF
+                }
+            }
+            _ => panic!("Mbc2 cannot handle read from address {:#X}.", address)
+        }
+    }
+}
+
+impl Memory for Mbc2 {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read(address, None)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        self.read(address, Some(kind))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            // Bit 8 of the address selects the register
+            0x0000 ... 0x3FFF if address & 0x100 == 0 => self.ram_enabled = value & 0xF == 0xA,
+            0x0000 ... 0x3FFF => {
+                self.current_rom_bank = value & 0xF;
+                if self.current_rom_bank == 0 {
+                    self.current_rom_bank = 1;
+                }
+            }
+            0x4000 ... 0x7FFF => (),
+            0xA000 ... 0xBFFF => {
+                if self.ram_enabled {
+                    self.ram.write_byte(address & 0x1FF, value & 0xF);
+                }
+            }
+            _ => panic!("Mbc2 cannot handle write to address {:#X}.", address)
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000 ... 0x3FFF => Some(0),
+            0x4000 ... 0x7FFF => Some(self.current_rom_bank as u16),
+            _ => None,
+        }
+    }
+
+    // The built-in RAM has a single bank, which is mirrored over the area
+    fn ram_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0xA000 ... 0xBFFF => Some(0),
+            _ => None,
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000 ... 0x7FFF => Some(self.rom_address(address)),
+            _ => None,
+        }
+    }
+
+    fn bypass_instruction_cache(&self) -> bool {
+        self.code_data_log.is_some()
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        Some(Box::new(Mbc2Snapshot {
+            ram: self.ram.clone(),
+            current_rom_bank: self.current_rom_bank,
+            ram_enabled: self.ram_enabled,
+        }))
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        let snapshot = snapshot.downcast_ref::<Mbc2Snapshot>().expect("Invalid snapshot").clone();
+        self.ram = snapshot.ram;
+        self.current_rom_bank = snapshot.current_rom_bank;
+        self.ram_enabled = snapshot.ram_enabled;
+    }
+}
+
+impl Drop for Mbc2 {
+    fn drop(&mut self) {
+        if let Some(ref battery) = self.battery {
+            battery.save(&self.ram);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Mbc3Snapshot {
+    ram: BlockMemory,
+    rtc: Option<Rtc>,
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    ram_enabled: bool,
+}
+
+// MBC3 with up to 128 ROM banks, 4 RAM banks and an optional real-time clock
+struct Mbc3 {
+    rom: BlockMemory,
+    // With a battery backed clock, the clock state is saved after the RAM
+    ram: BlockMemory,
+    rtc: Option<Rtc>,
+    code_data_log: Option<Rc<CodeDataLog>>,
+    // Size of the RAM given in the header, 0 if there is none
+    ram_size: usize,
+    current_rom_bank: u8,
+    // RAM bank 0x00-0x03 or clock register 0x08-0x0C mapped to 0xA000-0xBFFF
+    current_ram_bank: u8,
+    ram_enabled: bool,
+    battery: Option<Battery>,
+}
+
+impl Mbc3 {
+    fn new(rom: BlockMemory, code_data_log: Option<Rc<CodeDataLog>>) -> Mbc3 {
+        let mbc_type = rom.read_byte(0x147);
+        let has_rtc = mbc_type == 0x0F || mbc_type == 0x10;
+        let has_battery = mbc_type == 0x0F || mbc_type == 0x10 || mbc_type == 0x13;
+        let ram_size = ram_size(&rom);
+        // Like in the saves of other emulators, the clock follows the RAM
+        let save_size = if has_rtc { ram_size + rtc::SAVE_SIZE } else { ram_size };
+        let battery = if has_battery { Some(Battery::new(&rom)) } else { None };
+        let ram = battery::create_ram(&battery, save_size);
+        let rtc = if has_rtc { Some(Rtc::load(&ram.get_memory()[ram_size..])) } else { None };
+        Mbc3 {
+            rom,
+            ram,
+            rtc,
+            code_data_log,
+            ram_size,
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            ram_enabled: false,
+            battery,
+        }
+    }
+
+    fn rom_address(&self, address: u16) -> usize {
+        let address = match address {
+            0x0000 ... 0x3FFF => address as usize,
+            _ => (address & 0x3FFF) as usize + self.current_rom_bank as usize * 0x4000,
+        };
+        // Banks beyond the end of the ROM wrap around
+        address % self.rom.get_memory().len()
+    }
+
+    // Banks beyond the end of the RAM wrap around as well
+    fn ram_address(&self, address: u16) -> usize {
+        ((address & 0x1FFF) as usize + self.current_ram_bank as usize * 0x2000) % self.ram_size
+    }
+
+    // Reads a byte and logs it in the code/data log if a kind is given
+    fn read(&self, address: u16, kind: Option<ReadKind>) -> u8 {
+        match address {
+            0x0000 ... 0x7FFF => {
+                let address = self.rom_address(address);
+                log_read(&self.code_data_log, kind, CodeDataLog::log_rom, address);
+                self.rom.read_byte_usize(address)
+            }
+            0xA000 ... 0xBFFF if self.ram_enabled => match (self.current_ram_bank, &self.rtc) {
+                (0x00 ... 0x03, _) if self.ram_size > 0 => {
+                    let address = self.ram_address(address);
+                    log_read(&self.code_data_log, kind, CodeDataLog::log_ram, address);
+                    self.ram.read_byte_usize(address)
+                }
+                (0x08 ... 0x0C, &Some(ref rtc)) => rtc.read(self.current_ram_bank),
+                _ => 0xFF,
+            },
+            0xA000 ... 0xBFFF => 0xFF,
+            _ => panic!("Mbc3 cannot handle read from address {:#X}.", address)
+        }
+    }
+}
+
+impl Memory for Mbc3 {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read(address, None)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        self.read(address, Some(kind))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ... 0x1FFF => self.ram_enabled = value & 0xF == 0xA,
+            0x2000 ... 0x3FFF => {
+                self.current_rom_bank = value & 0x7F;
+                if self.current_rom_bank == 0 {
+                    self.current_rom_bank = 1;
+                }
+            }
+            0x4000 ... 0x5FFF => self.current_ram_bank = value & 0xF,
+            0x6000 ... 0x7FFF => {
+                if let Some(ref mut rtc) = self.rtc {
+                    rtc.write_latch(value);
+                }
+            }
+            0xA000 ... 0xBFFF if self.ram_enabled => match (self.current_ram_bank, &mut self.rtc) {
+                (0x00 ... 0x03, _) if self.ram_size > 0 => {
+                    let address = self.ram_address(address);
+                    self.ram.write_byte_usize(address, value);
+                }
+                (0x08 ... 0x0C, &mut Some(ref mut rtc)) => rtc.write(self.current_ram_bank, value),
+                _ => (),
+            },
+            0xA000 ... 0xBFFF => (),
+            _ => panic!("Mbc3 cannot handle write to address {:#X}.", address)
+        }
+    }
+
+    fn step(&mut self, cycles: u8) {
+        if let Some(ref mut rtc) = self.rtc {
+            rtc.step(cycles);
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000 ... 0x3FFF => Some(0),
+            0x4000 ... 0x7FFF => Some(self.current_rom_bank as u16),
+            _ => None,
+        }
+    }
+
+    fn ram_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0xA000 ... 0xBFFF if self.ram_size > 0 && self.current_ram_bank <= 0x03 => {
+                Some(self.current_ram_bank as u16)
+            }
+            _ => None,
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000 ... 0x7FFF => Some(self.rom_address(address)),
+            _ => None,
+        }
+    }
+
+    fn bypass_instruction_cache(&self) -> bool {
+        self.code_data_log.is_some()
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        Some(Box::new(Mbc3Snapshot {
+            ram: self.ram.clone(),
+            rtc: self.rtc.clone(),
+            current_rom_bank: self.current_rom_bank,
+            current_ram_bank: self.current_ram_bank,
+            ram_enabled: self.ram_enabled,
+        }))
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        let snapshot = snapshot.downcast_ref::<Mbc3Snapshot>().expect("Invalid snapshot").clone();
+        self.ram = snapshot.ram;
+        self.rtc = snapshot.rtc;
+        self.current_rom_bank = snapshot.current_rom_bank;
+        self.current_ram_bank = snapshot.current_ram_bank;
+        self.ram_enabled = snapshot.ram_enabled;
+    }
+}
+
+impl Drop for Mbc3 {
+    fn drop(&mut self) {
+        if let Some(ref battery) = self.battery {
+            if let Some(ref rtc) = self.rtc {
+                for (i, &byte) in rtc.save().iter().enumerate() {
+                    self.ram.write_byte_usize(self.ram_size + i, byte);
+                }
+            }
+            battery.save(&self.ram);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Mbc5Snapshot {
+    ram: BlockMemory,
+    current_rom_bank: u16,
+    current_ram_bank: u8,
+    ram_enabled: bool,
+    lower_bits: u8,
+    upper_bits: u8,
+}
+
+struct Mbc5 {
+    rom: BlockMemory,
+    ram: BlockMemory,
+    code_data_log: Option<Rc<CodeDataLog>>,
+    has_ram: bool,
+    current_rom_bank: u16,
+    current_ram_bank: u8,
+    ram_enabled: bool,
+    lower_bits: u8,
+    upper_bits: u8,
+    battery: Option<Battery>,
+}
+
+impl Mbc5 {
+    fn new(rom: BlockMemory, code_data_log: Option<Rc<CodeDataLog>>) -> Mbc5 {
+        println!("new MBC5");
+        let mbc_type = rom.read_byte(0x147);
+        let has_ram = mbc_type == 0x1A || mbc_type == 0x1B ||
+                      mbc_type == 0x1D || mbc_type == 0x1E;
+        let has_battery = mbc_type == 0x1B || mbc_type == 0x1E;
+        println!("has_battery: {}", has_battery);
+        let battery = if has_battery { Some(Battery::new(&rom)) } else { None };
+        let ram = battery::create_ram(&battery, ram_size(&rom));
+        Mbc5 {
+            rom,
+            ram,
+            code_data_log,
+            has_ram,
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            ram_enabled: false,
+            lower_bits: 1,
+            upper_bits: 0,
+            battery,
+        }
+    }
+
+    // Reads a byte and logs it in the code/data log if a kind is given
+    fn read(&self, address: u16, kind: Option<ReadKind>) -> u8 {
+        match address {
+            0x0000 ... 0x3FFF => {
+                log_read(&self.code_data_log, kind, CodeDataLog::log_rom, address as usize);
+                self.rom.read_byte(address)
+            }
+            0x4000 ... 0x7FFF => {
+                let address = (address & 0x3FFF) as usize + self.current_rom_bank as usize * 0x4000;
+                log_read(&self.code_data_log, kind, CodeDataLog::log_rom, address);
+                self.rom.read_byte_usize(address)
+            }
+            0xA000 ... 0xBFFF => {
+                if self.ram_enabled {
+                    let address = (address & 0x1FFF) as usize + self.current_ram_bank as usize * 0x2000;
+                    log_read(&self.code_data_log, kind, CodeDataLog::log_ram, address);
+                    self.ram.read_byte_usize(address)
+                } else {
+                    0xFF
+                }
+            }
+            _ => panic!("Mbc5 cannot handle read from address {:#X}.", address)
+        }
+    }
+
+    fn update_rom_bank(&mut self) {
+        self.current_rom_bank = ((self.upper_bits as u16) << 8) + self.lower_bits as u16;
+    }
+}
+
+impl Memory for Mbc5 {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read(address, None)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        self.read(address, Some(kind))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ... 0x1FFF => self.ram_enabled = self.has_ram && (value & 0xF == 0xA),
+            0x2000 ... 0x2FFF => {
+                self.lower_bits = value;
+                self.update_rom_bank();
+            }
+            0x3000 ... 0x3FFF => {
+                self.upper_bits = value;
+                self.update_rom_bank();
+            }
+            0x4000 ... 0x5FFF => {
+                if self.has_ram {
+                    self.current_ram_bank = value & 0xF;
+                }
+            }
+            0xA000 ... 0xBFFF => {
+                if self.ram_enabled {
+                    let address = (address & 0x1FFF) as usize + self.current_ram_bank as usize * 0x2000;
+                    self.ram.write_byte_usize(address, value);
+                }
+            }
+            //_ => panic!("Mbc5 cannot handle write to address {:#X}.", address)
+            _ => (),
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000 ... 0x3FFF => Some(0),
+            0x4000 ... 0x7FFF => Some(self.current_rom_bank),
+            _ => None,
+        }
+    }
+
+    fn ram_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0xA000 ... 0xBFFF if self.has_ram => Some(self.current_ram_bank as u16),
+            _ => None,
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000 ... 0x3FFF => Some(address as usize),
+            0x4000 ... 0x7FFF => Some((address & 0x3FFF) as usize + self.current_rom_bank as usize * 0x4000),
+            _ => None,
+        }
+    }
+
+    fn bypass_instruction_cache(&self) -> bool {
+        self.code_data_log.is_some()
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        Some(Box::new(Mbc5Snapshot {
+            ram: self.ram.clone(),
+            current_rom_bank: self.current_rom_bank,
+            current_ram_bank: self.current_ram_bank,
+            ram_enabled: self.ram_enabled,
+            lower_bits: self.lower_bits,
+            upper_bits: self.upper_bits,
+        }))
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        let snapshot = snapshot.downcast_ref::<Mbc5Snapshot>().expect("Invalid snapshot").clone();
+        self.ram = snapshot.ram;
+        self.current_rom_bank = snapshot.current_rom_bank;
+        self.current_ram_bank = snapshot.current_ram_bank;
+        self.ram_enabled = snapshot.ram_enabled;
+        self.lower_bits = snapshot.lower_bits;
+        self.upper_bits = snapshot.upper_bits;
+    }
+}
+
+impl Drop for Mbc5 {
+    fn drop(&mut self) {
+        println!("Dropping");
+        if let Some(ref battery) = self.battery {
+            battery.save(&self.ram);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use super::*;
+
+// Creates a ROM of the given MBC type and number of 16 KB banks, in which
+// every bank starts with its number
+fn create_rom(mbc_type: u8, banks: usize) -> BlockMemory {
+    let mut rom = BlockMemory::new(banks * 0x4000);
+    for bank in 0..banks {
+        rom.write_byte_usize(bank * 0x4000, bank as u8);
+    }
+    rom.write_byte(0x147, mbc_type);
+    rom
+}
+
+#[test]
+fn test_mbc2_registers() {
+    let mut mbc = Mbc2::new(create_rom(0x5, 16), None);
+    assert_eq!(mbc.read_byte(0x4000), 1);
+    // Bit 8 of the address selects the ROM bank register
+    mbc.write_byte(0x2100, 0x03);
+    assert_eq!(mbc.read_byte(0x4000), 3);
+    mbc.write_byte(0x0100, 0x0A);
+    assert_eq!(mbc.read_byte(0x4000), 10);
+    assert_eq!(mbc.read_byte(0xA000), 0xFF);
+    // Otherwise it selects the RAM enable register
+    mbc.write_byte(0x2000, 0x0A);
+    assert_eq!(mbc.read_byte(0x4000), 10);
+    assert_eq!(mbc.read_byte(0xA000), 0xF0);
+    mbc.write_byte(0x0000, 0x00);
+    assert_eq!(mbc.read_byte(0xA000), 0xFF);
+}
+
+#[test]
+fn test_mbc2_bank_0_selects_bank_1() {
+    let mut mbc = Mbc2::new(create_rom(0x5, 16), None);
+    mbc.write_byte(0x2100, 0x05);
+    mbc.write_byte(0x2100, 0x00);
+    assert_eq!(mbc.read_byte(0x4000), 1);
+    // Only the lower four bits are used
+    mbc.write_byte(0x2100, 0x10);
+    assert_eq!(mbc.read_byte(0x4000), 1);
+    mbc.write_byte(0x2100, 0x1F);
+    assert_eq!(mbc.read_byte(0x4000), 15);
+    assert_eq!(mbc.rom_bank(0x4000), Some(15));
+}
+
+#[test]
+fn test_mbc2_half_byte_ram() {
+    let mut mbc = Mbc2::new(create_rom(0x5, 16), None);
+    mbc.write_byte(0x0000, 0x0A);
+    mbc.write_byte(0xA000, 0x5A);
+    mbc.write_byte(0xA1FF, 0x03);
+    // The upper nibble is not stored and reads as 1s
+    assert_eq!(mbc.read_byte(0xA000), 0xFA);
+    assert_eq!(mbc.read_byte(0xA1FF), 0xF3);
+    // The 512 half-bytes are mirrored over the whole area
+    assert_eq!(mbc.read_byte(0xA200), 0xFA);
+    assert_eq!(mbc.read_byte(0xBE00), 0xFA);
+    assert_eq!(mbc.read_byte(0xBFFF), 0xF3);
+    mbc.write_byte(0xB001, 0x07);
+    assert_eq!(mbc.read_byte(0xA001), 0xF7);
+    assert_eq!(mbc.ram_bank(0xBFFF), Some(0));
+    assert_eq!(mbc.ram_bank(0x4000), None);
+}
+
+// A 1 MB MBC1 ROM with the Nintendo logo in the headers of the given 256 KB
+// blocks
+fn create_rom_with_logos(blocks: &[usize]) -> BlockMemory {
+    let mut rom = create_rom(0x1, 64);
+    for block in blocks {
+        for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+            rom.write_byte_usize(block * 0x40000 + 0x104 + i, byte);
+        }
+    }
+    rom
+}
+
+#[test]
+fn test_multicart_detection() {
+    assert!(is_multicart(&create_rom_with_logos(&[0, 1, 2, 3])));
+    assert!(is_multicart(&create_rom_with_logos(&[0, 2])));
+    assert!(!is_multicart(&create_rom_with_logos(&[0])));
+    assert!(!is_multicart(&create_rom(0x1, 64)));
+    let mut rom = create_rom(0x1, 32);
+    for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+        rom.write_byte_usize(0x104 + i, byte);
+        rom.write_byte_usize(0x40104 + i, byte);
+    }
+    assert!(!is_multicart(&rom));
+}
+
+#[test]
+fn test_multicart_banks() {
+    let mut mbc = Mbc1::new(create_rom_with_logos(&[0, 1, 2, 3]), None);
+    assert!(mbc.multicart);
+    // The upper bits are bits 4-5 of the bank number
+    mbc.write_byte(0x4000, 0x01);
+    mbc.write_byte(0x2000, 0x02);
+    assert_eq!(mbc.read_byte(0x4000), 0x12);
+    // Bit 4 of the lower bits is not connected, but still counts for the
+    // bank 0 check, so a game can map its own bank 0
+    mbc.write_byte(0x2000, 0x10);
+    assert_eq!(mbc.read_byte(0x4000), 0x10);
+    mbc.write_byte(0x2000, 0x00);
+    assert_eq!(mbc.read_byte(0x4000), 0x11);
+    // Mode 1 also switches 0x0000-0x3FFF to the bank 0 of the game
+    assert_eq!(mbc.read_byte(0x0000), 0x00);
+    mbc.write_byte(0x6000, 0x01);
+    assert_eq!(mbc.read_byte(0x0000), 0x10);
+    assert_eq!(mbc.rom_bank(0x0000), Some(0x10));
+    assert_eq!(mbc.read_byte(0x4000), 0x11);
+    mbc.write_byte(0x4000, 0x03);
+    assert_eq!(mbc.read_byte(0x0000), 0x30);
+    assert_eq!(mbc.read_byte(0x4000), 0x31);
+    mbc.write_byte(0x6000, 0x00);
+    assert_eq!(mbc.read_byte(0x0000), 0x00);
+}
+
+#[test]
+fn test_mbc1_banks() {
+    let mut mbc = Mbc1::new(create_rom_with_logos(&[0]), None);
+    assert!(!mbc.multicart);
+    mbc.write_byte(0x4000, 0x01);
+    mbc.write_byte(0x2000, 0x02);
+    assert_eq!(mbc.read_byte(0x4000), 0x22);
+    mbc.write_byte(0x2000, 0x10);
+    assert_eq!(mbc.read_byte(0x4000), 0x30);
+    assert_eq!(mbc.read_byte(0x0000), 0x00);
+}
+
+#[test]
+fn test_mbc1_banks_wrap_around() {
+    let mut mbc = Mbc1::new(create_rom(0x1, 4), None);
+    mbc.write_byte(0x2000, 0x06);
+    assert_eq!(mbc.read_byte(0x4000), 2);
+    assert_eq!(mbc.rom_offset(0x4000), Some(0x8000));
+    // The upper bits select bank 0x20 in mode 1, which is bank 0 here
+    mbc.write_byte(0x4000, 0x01);
+    mbc.write_byte(0x6000, 0x01);
+    assert_eq!(mbc.read_byte(0x0000), 0);
+    assert_eq!(mbc.read_byte(0x4000), 2);
+}
+
+#[test]
+fn test_ram_size_matches_mbc() {
+    // The code/data log is sized by it, so it has to cover the RAM of the MBC
+    let mut rom = create_rom(0x2, 4);
+    rom.write_byte(0x149, 0x02);
+    assert_eq!(ram_size(&rom), Mbc1::new(rom, None).ram.get_memory().len());
+    let mut rom = create_rom(0x1A, 4);
+    rom.write_byte(0x149, 0x02);
+    assert_eq!(ram_size(&rom), Mbc5::new(rom, None).ram.get_memory().len());
+    let mut rom = create_rom(0x12, 4);
+    rom.write_byte(0x149, 0x03);
+    assert_eq!(ram_size(&rom), 0x8000);
+    assert_eq!(Mbc3::new(rom, None).ram_size, 0x8000);
+}
+
+#[test]
+fn test_code_data_log_bypasses_instruction_cache() {
+    let mbc = create_mbc(create_rom(0x1, 4), None);
+    assert!(!mbc.bypass_instruction_cache());
+    let code_data_log = Rc::new(CodeDataLog::new(4 * 0x4000, 0));
+    let mbc = create_mbc(create_rom(0x1, 4), Some(code_data_log));
+    // The ROM is still mapped, only the cache is bypassed
+    assert!(mbc.bypass_instruction_cache());
+    assert_eq!(mbc.rom_offset(0x4000), Some(0x4000));
+}
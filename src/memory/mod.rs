@@ -0,0 +1,551 @@
+use display::Display;
+use io_registers::{IoRegisters, IoSnapshot};
+use memory_hooks::{HookType, MemoryEvent, MemoryHooks};
+use gpu::{Gpu, GpuSnapshot};
+use model::Model;
+use oam_dma::OamDma;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Deref;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+// Saved state of a memory and the devices behind it
+pub type MemorySnapshot = Box<Any>;
+
+// Why a byte is read by the system, see Memory::read_byte_as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadKind {
+    Data,
+    Opcode,
+    Operand,
+    Dma,
+}
+
+pub trait Memory {
+    fn read_byte(&self, address: u16) -> u8;
+    fn write_byte(&mut self, address: u16, value: u8);
+
+    // Reads a byte on behalf of the CPU or the DMA, which memories can log.
+    // Plain reads, e.g. by the debugger, are not logged.
+    fn read_byte_as(&self, address: u16, _kind: ReadKind) -> u8 {
+        self.read_byte(address)
+    }
+
+    fn read_word(&self, address: u16) -> u16 {
+        let low_byte = self.read_byte(address);
+        let high_byte = self.read_byte(address + 1);
+        ((high_byte as u16) << 8) + low_byte as u16
+    }
+
+    fn read_4_bytes(&self, address: u16) -> [u8; 4] {
+        [
+            self.read_byte(address),
+            self.read_byte(address + 1),
+            self.read_byte(address + 2),
+            self.read_byte(address + 3),
+        ]
+    }
+
+    fn write_word(&mut self, address: u16, value: u16) {
+        let low_byte = (value & 0xFF) as u8;
+        let high_byte = ((value & 0xFF00) >> 8) as u8;
+        self.write_byte(address, low_byte);
+        self.write_byte(address + 1, high_byte);
+    }
+
+    // Advances the rest of the system by the given number of clock cycles
+    fn step(&mut self, _cycles: u8) {}
+
+    // FIXME: fix this
+    fn in_bios(&self) -> bool {
+        false
+    }
+
+    fn leave_bios(&mut self) {}
+
+    // Performs a CGB speed switch if one has been prepared. Returns whether
+    // the speed was switched.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+
+    // Saves the state for reverse execution. Returns None if the memory does
+    // not support snapshots.
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        None
+    }
+
+    fn restore(&mut self, _snapshot: &MemorySnapshot) {}
+
+    // Returns the ROM bank that is mapped to the given address, or None if
+    // the address is not mapped to the cartridge ROM.
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000...0x3FFF => Some(0),
+            0x4000...0x7FFF => Some(1),
+            _ => None,
+        }
+    }
+
+    // Returns the offset in the cartridge ROM of the byte mapped to the
+    // given address, or None if the address is not mapped to the ROM. The
+    // instruction cache uses it as key for ROM commands.
+    fn rom_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
+
+    // Returns the cartridge RAM bank that is mapped to the given address, or
+    // None if the address is not mapped to banked cartridge RAM
+    fn ram_bank(&self, _address: u16) -> Option<u16> {
+        None
+    }
+
+    // Whether commands have to be read from the memory every time, e.g.
+    // because an OAM DMA puts other values on the bus or because opcode
+    // fetches are observed by hooks or the code/data log
+    fn bypass_instruction_cache(&self) -> bool {
+        false
+    }
+
+    // Writes a byte without calling memory hooks, for writes that are not
+    // made by the emulated program, like the joypad state of the frontend
+    fn write_byte_without_hooks(&mut self, address: u16, value: u8) {
+        self.write_byte(address, value);
+    }
+
+    // Stops calling memory hooks until resumed, e.g. while the debugger
+    // replays the history
+    fn set_hooks_suspended(&mut self, _suspended: bool) {}
+}
+
+pub struct MemoryMap<'a, 'b, D>
+where
+    'b: 'a,
+    D: Display + 'b,
+{
+    model: Model,
+    bios_active: bool,
+    bios: &'b mut BlockMemory,
+    mbc: Box<Memory>,
+    working_ram: BlockMemory,
+    zero_page: BlockMemory,
+    oam_dma: OamDma,
+    gpu: &'b RefCell<Gpu<D>>,
+    io: &'a RefCell<IoRegisters<'b, D>>,
+    hooks: Option<Rc<RefCell<MemoryHooks>>>,
+    hooks_suspended: bool,
+    // Whether the CPU is locked out of VRAM and OAM while the GPU uses them
+    lock_video_memory: bool,
+    // Clock cycles since the start, passed to the hooks
+    cycle: u64,
+}
+
+impl<'a, 'b, D> MemoryMap<'a, 'b, D>
+where
+    D: Display,
+{
+    pub fn new(
+        model: Model,
+        bios: &'b mut BlockMemory,
+        mbc: Box<Memory>,
+        gpu: &'b RefCell<Gpu<D>>,
+        io: &'a RefCell<IoRegisters<'b, D>>,
+    ) -> MemoryMap<'a, 'b, D> {
+        MemoryMap {
+            model,
+            bios_active: true,
+            bios,
+            mbc,
+            working_ram: BlockMemory::new(0x2000),
+            zero_page: BlockMemory::new(0x80),
+            oam_dma: OamDma::new(),
+            gpu,
+            io,
+            hooks: None,
+            hooks_suspended: false,
+            lock_video_memory: true,
+            cycle: 0,
+        }
+    }
+
+    pub fn set_hooks(&mut self, hooks: Rc<RefCell<MemoryHooks>>) {
+        self.hooks = Some(hooks);
+    }
+
+    // Lets the CPU access VRAM and OAM in every GPU mode if disabled, which
+    // can help when debugging
+    pub fn set_lock_video_memory(&mut self, enabled: bool) {
+        self.lock_video_memory = enabled;
+    }
+
+    // Whether the GPU keeps the CPU from accessing the address. Reads return
+    // 0xFF then and writes are ignored.
+    fn is_locked_by_gpu(&self, address: u16) -> bool {
+        if !self.lock_video_memory {
+            return false;
+        }
+        let gpu = self.gpu.borrow();
+        match address {
+            0x8000...0x9FFF => !gpu.state.is_vram_accessible(),
+            0xFE00...0xFEFF => !gpu.state.is_oam_accessible(),
+            _ => false,
+        }
+    }
+
+    fn hooks_active(&self) -> bool {
+        self.hooks.is_some() && !self.hooks_suspended
+    }
+
+    fn call_hooks(&self, hook_type: HookType, address: u16, value: u8, bank: Option<u16>) {
+        if let Some(ref hooks) = self.hooks {
+            let event = MemoryEvent { hook_type, address, value, bank, cycle: self.cycle };
+            hooks.borrow_mut().call(&event);
+        }
+    }
+
+    // The ROM or cartridge RAM bank of an address
+    fn bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0xA000...0xBFFF => self.ram_bank(address),
+            _ => self.rom_bank(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address == 0xFF46 {
+            self.oam_dma.start(value);
+            return;
+        }
+        if self.is_locked_by_gpu(address) {
+            return;
+        }
+        let (memory_type, address) = self.address_to_type(address);
+        match memory_type {
+            MemoryType::GraphicsVram => {
+                self.gpu.borrow_mut().get_vram_mut().write_byte(
+                    address,
+                    value,
+                )
+            }
+            MemoryType::Bios => self.bios.write_byte(address, value),
+            MemoryType::Mbc => self.mbc.write_byte(address, value),
+            MemoryType::WorkingRam => self.working_ram.write_byte(address, value),
+            MemoryType::Sprites if self.oam_dma.is_active() => (),
+            MemoryType::Sprites => {
+                self.gpu.borrow_mut().get_oam_mut().write_byte(
+                    address,
+                    value,
+                )
+            }
+            MemoryType::ZeroPage => self.zero_page.write_byte(address, value),
+            MemoryType::Io => self.io.borrow_mut().write_byte(address, value),
+            MemoryType::Unusable => (),
+        };
+    }
+
+    fn address_to_type(&self, address: u16) -> (MemoryType, u16) {
+        match address {
+            0x0000...0x00FF if self.bios_active => (MemoryType::Bios, address),
+            0x0000...0x7FFF => (MemoryType::Mbc, address),
+            0x8000...0x9FFF => (MemoryType::GraphicsVram, address & 0x1FFF),
+            0xA000...0xBFFF => (MemoryType::Mbc, address),
+            0xC000...0xDFFF => (MemoryType::WorkingRam, address & 0x1FFF),
+            // Echo RAM mirrors 0xC000-0xDDFF
+            0xE000...0xFDFF => self.address_to_type(address - 0x2000),
+            0xFE00...0xFE9F => (MemoryType::Sprites, address & 0xFF),
+            0xFEA0...0xFEFF => (MemoryType::Unusable, address),
+            0xFF00...0xFF7F => (MemoryType::Io, address & 0x7F),
+            0xFF80...0xFFFF => (MemoryType::ZeroPage, address & 0x7F),
+            _ => panic!("Memory address not known"),
+        }
+    }
+
+    // Reads from 0xFEA0-0xFEFF, which is not connected to any memory. Writes
+    // to it are ignored.
+    fn read_unusable(&self, address: u16) -> u8 {
+        match self.model {
+            // The high nibble of the lower address byte twice, e.g. 0xFEB4
+            // reads 0xBB
+            Model::Cgb => {
+                let nibble = (address >> 4) as u8 & 0x0F;
+                nibble << 4 | nibble
+            }
+            Model::Dmg0 | Model::Dmg | Model::Mgb | Model::Sgb => 0x00,
+        }
+    }
+
+    // While the OAM DMA runs, OAM can not be accessed by the CPU and reads
+    // from the bus used by the DMA return the byte it transfers. Only IO
+    // registers and high RAM are safe.
+    fn dma_conflict(&self, address: u16) -> Option<u8> {
+        let source = self.oam_dma.source()?;
+        let is_video_bus = |address| 0x8000 <= address && address <= 0x9FFF;
+        match address {
+            0xFE00...0xFEFF => Some(0xFF),
+            0xFF00...0xFFFF => None,
+            _ if is_video_bus(address) == is_video_bus(source) => Some(self.oam_dma.bus_value()),
+            _ => None,
+        }
+    }
+
+    // Copies a byte to OAM in every machine cycle of a running OAM DMA
+    fn step_oam_dma(&mut self, cycles: u8) {
+        for _ in 0..self.oam_dma.add_cycles(cycles) {
+            if let Some((source, offset)) = self.oam_dma.machine_cycle() {
+                let value = self.read_byte_as(source, ReadKind::Dma);
+                self.gpu.borrow_mut().get_oam_mut().write_byte(offset, value);
+                self.oam_dma.set_bus_value(value);
+            }
+        }
+    }
+}
+
+impl<'a, 'b, D> Memory for MemoryMap<'a, 'b, D>
+where
+    D: Display,
+{
+    fn read_byte(&self, address: u16) -> u8 {
+        if address == 0xFF46 {
+            return self.oam_dma.register();
+        }
+        let (memory_type, address) = self.address_to_type(address);
+        let gpu = self.gpu.borrow();
+        let io = self.io.borrow();
+        let memory = match memory_type {
+            MemoryType::GraphicsVram => gpu.get_vram(),
+            MemoryType::Bios => self.bios,
+            MemoryType::Mbc => self.mbc.deref(),
+            MemoryType::WorkingRam => &self.working_ram,
+            MemoryType::Sprites => gpu.get_oam(),
+            MemoryType::ZeroPage => &self.zero_page,
+            MemoryType::Io => io.deref(),
+            MemoryType::Unusable => return self.read_unusable(address),
+        };
+        memory.read_byte(address)
+    }
+
+    fn read_byte_as(&self, address: u16, kind: ReadKind) -> u8 {
+        let value = match self.dma_conflict(address) {
+            Some(value) if kind != ReadKind::Dma => value,
+            _ if kind != ReadKind::Dma && self.is_locked_by_gpu(address) => 0xFF,
+            _ => match self.address_to_type(address) {
+                (MemoryType::Mbc, address) => self.mbc.read_byte_as(address, kind),
+                _ => self.read_byte(address),
+            },
+        };
+        if self.hooks_active() {
+            match kind {
+                ReadKind::Data => self.call_hooks(HookType::Read, address, value, self.bank(address)),
+                ReadKind::Opcode => self.call_hooks(HookType::Execute, address, value, self.bank(address)),
+                _ => (),
+            }
+        }
+        value
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if !self.hooks_active() {
+            self.write(address, value);
+            return;
+        }
+        let banks = (self.rom_bank(0x4000), self.ram_bank(0xA000));
+        self.write(address, value);
+        self.call_hooks(HookType::Write, address, value, self.bank(address));
+        if address < 0x8000 {
+            let (rom_bank, ram_bank) = (self.rom_bank(0x4000), self.ram_bank(0xA000));
+            if rom_bank != banks.0 {
+                self.call_hooks(HookType::RomBankSwitch, address, value, rom_bank);
+            }
+            if ram_bank != banks.1 {
+                self.call_hooks(HookType::RamBankSwitch, address, value, ram_bank);
+            }
+        }
+    }
+
+    fn step(&mut self, cycles: u8) {
+        self.cycle += cycles as u64;
+        self.step_oam_dma(cycles);
+        let io = self.io.borrow();
+        // In double speed mode the GPU keeps running at normal speed
+        let gpu_cycles = if io.is_double_speed() { cycles / 2 } else { cycles };
+        self.gpu.borrow_mut().step(gpu_cycles);
+        // So does the real-time clock of the cartridge
+        self.mbc.step(gpu_cycles);
+        io.step(cycles);
+    }
+
+    fn in_bios(&self) -> bool {
+        self.bios_active
+    }
+
+    fn leave_bios(&mut self) {
+        self.bios_active = false;
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        self.io.borrow_mut().switch_speed()
+    }
+
+    fn rom_bank(&self, address: u16) -> Option<u16> {
+        match self.address_to_type(address) {
+            (MemoryType::Mbc, _) => self.mbc.rom_bank(address),
+            _ => None,
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        match self.address_to_type(address) {
+            (MemoryType::Mbc, _) => self.mbc.rom_offset(address),
+            _ => None,
+        }
+    }
+
+    fn ram_bank(&self, address: u16) -> Option<u16> {
+        match self.address_to_type(address) {
+            (MemoryType::Mbc, _) => self.mbc.ram_bank(address),
+            _ => None,
+        }
+    }
+
+    fn bypass_instruction_cache(&self) -> bool {
+        self.oam_dma.is_active() || self.mbc.bypass_instruction_cache() ||
+            self.hooks_active() && self.hooks.as_ref().map_or(false, |hooks| hooks.borrow().has(HookType::Execute))
+    }
+
+    fn write_byte_without_hooks(&mut self, address: u16, value: u8) {
+        self.write(address, value);
+    }
+
+    fn set_hooks_suspended(&mut self, suspended: bool) {
+        self.hooks_suspended = suspended;
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        let snapshot = MemoryMapSnapshot {
+            bios_active: self.bios_active,
+            mbc: self.mbc.snapshot()?,
+            working_ram: self.working_ram.clone(),
+            zero_page: self.zero_page.clone(),
+            oam_dma: self.oam_dma.clone(),
+            cycle: self.cycle,
+            gpu: self.gpu.borrow().snapshot(),
+            io: self.io.borrow().snapshot(),
+        };
+        Some(Box::new(snapshot))
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        let snapshot = snapshot.downcast_ref::<MemoryMapSnapshot>().expect("Invalid snapshot");
+        self.bios_active = snapshot.bios_active;
+        self.mbc.restore(&snapshot.mbc);
+        self.working_ram = snapshot.working_ram.clone();
+        self.zero_page = snapshot.zero_page.clone();
+        self.oam_dma = snapshot.oam_dma.clone();
+        self.cycle = snapshot.cycle;
+        self.gpu.borrow_mut().restore(&snapshot.gpu);
+        self.io.borrow_mut().restore(&snapshot.io);
+    }
+}
+
+struct MemoryMapSnapshot {
+    bios_active: bool,
+    mbc: MemorySnapshot,
+    working_ram: BlockMemory,
+    zero_page: BlockMemory,
+    oam_dma: OamDma,
+    cycle: u64,
+    gpu: GpuSnapshot,
+    io: IoSnapshot,
+}
+
+enum MemoryType {
+    Bios,
+    Mbc,
+    GraphicsVram,
+    WorkingRam,
+    Sprites,
+    ZeroPage,
+    Io,
+    Unusable,
+}
+
+#[derive(Clone)]
+pub struct BlockMemory {
+    memory: Vec<u8>,
+    read_only: bool,
+}
+
+impl BlockMemory {
+    pub fn new(size: usize) -> BlockMemory {
+        BlockMemory {
+            memory: vec![0; size],
+            read_only: false,
+        }
+    }
+
+    pub fn new_from_file(file: &mut File) -> BlockMemory {
+        let mut memory = Vec::<u8>::new();
+        file.read_to_end(&mut memory).unwrap();
+        BlockMemory {
+            memory,
+            read_only: true,
+        }
+    }
+
+    pub fn new_mut_from_file(file: &mut File) -> BlockMemory {
+        let mut memory = Vec::<u8>::new();
+        file.read_to_end(&mut memory).unwrap();
+        BlockMemory {
+            memory,
+            read_only: false,
+        }
+    }
+
+    pub fn read_byte_usize(&self, address: usize) -> u8 {
+        self.memory[address]
+    }
+
+    pub fn write_byte_usize(&mut self, address: usize, value: u8) {
+        if !self.read_only {
+            self.memory[address] = value
+        }
+    }
+
+    pub fn get_memory(&self) -> &Vec<u8> {
+        &self.memory
+    }
+}
+
+impl Memory for BlockMemory {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if !self.read_only {
+            self.memory[address as usize] = value;
+        }
+    }
+
+    fn snapshot(&self) -> Option<MemorySnapshot> {
+        // The contents of read only memory never change
+        if self.read_only {
+            Some(Box::new(()))
+        } else {
+            Some(Box::new(self.memory.clone()))
+        }
+    }
+
+    fn restore(&mut self, snapshot: &MemorySnapshot) {
+        if !self.read_only {
+            let memory = snapshot.downcast_ref::<Vec<u8>>().expect("Invalid snapshot");
+            self.memory.copy_from_slice(memory);
+        }
+    }
+}
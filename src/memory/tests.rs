@@ -0,0 +1,147 @@
+use super::*;
+use apu::Apu;
+use mbc::create_mbc;
+use test_utils::{NullAudioDevice, NullDisplay};
+use timer::Timer;
+
+// Runs the test with a memory map of the given model around the cartridge
+// ROM, with the boot ROM already left
+fn with_memory_map<F>(model: Model, rom: BlockMemory, test: F)
+where
+    F: FnOnce(&mut MemoryMap<NullDisplay>),
+{
+    let audio_device = NullAudioDevice::default();
+    let apu = RefCell::new(Apu::new(&audio_device));
+    let gpu = RefCell::new(Gpu::new(NullDisplay));
+    let timer = RefCell::new(Timer::new());
+    let io = RefCell::new(IoRegisters::new(&apu, &gpu, &timer, model.is_cgb()));
+    let mut bios = BlockMemory::new(0x100);
+    let mut memory = MemoryMap::new(model, &mut bios, create_mbc(rom, None), &gpu, &io);
+    memory.leave_bios();
+    test(&mut memory);
+}
+
+// ROM of a cartridge without memory bank controller
+fn create_rom() -> BlockMemory {
+    BlockMemory::new(0x8000)
+}
+
+#[test]
+fn test_oam_dma_bus_conflicts() {
+    with_memory_map(Model::Dmg, create_rom(), |memory| {
+        for i in 0..0xA0 {
+            memory.write_byte(0xC100 + i, i as u8 + 0x40);
+        }
+        memory.write_byte(0xD000, 0x12);
+        memory.write_byte(0x8000, 0x34);
+        memory.write_byte(0xFF90, 0x56);
+        memory.write_byte(0xFF46, 0xC1);
+        // Setup and six bytes
+        for _ in 0..7 {
+            memory.step(4);
+        }
+        // Reads from the bus of the DMA return the byte it copied last
+        assert_eq!(memory.read_byte_as(0xD000, ReadKind::Data), 0x45);
+        assert_eq!(memory.read_byte_as(0x0100, ReadKind::Opcode), 0x45);
+        assert_eq!(memory.read_byte_as(0xFE00, ReadKind::Data), 0xFF);
+        // Other buses, IO registers and high RAM can be used
+        assert_eq!(memory.read_byte_as(0x8000, ReadKind::Data), 0x34);
+        assert_eq!(memory.read_byte_as(0xFF90, ReadKind::Data), 0x56);
+        assert_eq!(memory.read_byte_as(0xFF46, ReadKind::Data), 0xC1);
+        for _ in 7..0xA1 {
+            memory.step(4);
+        }
+        assert_eq!(memory.read_byte_as(0xD000, ReadKind::Data), 0x12);
+        assert_eq!(memory.read_byte_as(0xFE00, ReadKind::Data), 0x40);
+        assert_eq!(memory.read_byte_as(0xFE9F, ReadKind::Data), 0xDF);
+    });
+}
+
+#[test]
+fn test_oam_dma_from_vram() {
+    with_memory_map(Model::Dmg, create_rom(), |memory| {
+        memory.write_byte(0x8000, 0x34);
+        memory.write_byte(0xC000, 0x12);
+        memory.write_byte(0xFF46, 0x80);
+        memory.step(4);
+        memory.step(4);
+        // The conflict is on the video bus now
+        assert_eq!(memory.read_byte_as(0x9000, ReadKind::Data), 0x34);
+        assert_eq!(memory.read_byte_as(0xC000, ReadKind::Data), 0x12);
+    });
+}
+
+#[test]
+fn test_echo_ram() {
+    with_memory_map(Model::Dmg, create_rom(), |memory| {
+        memory.write_byte(0xC123, 0x12);
+        assert_eq!(memory.read_byte(0xE123), 0x12);
+        memory.write_byte(0xFDFF, 0x34);
+        assert_eq!(memory.read_byte(0xDDFF), 0x34);
+        memory.write_byte(0xE000, 0x56);
+        assert_eq!(memory.read_byte(0xC000), 0x56);
+        // 0xDE00-0xDFFF is not mirrored, as OAM follows the echo
+        memory.write_byte(0xDE00, 0x78);
+        assert_eq!(memory.read_byte(0xFE00), 0x00);
+    });
+}
+
+#[test]
+fn test_unusable_memory() {
+    with_memory_map(Model::Dmg, create_rom(), |memory| {
+        memory.write_byte(0xFEA0, 0x12);
+        assert_eq!(memory.read_byte(0xFEA0), 0x00);
+        assert_eq!(memory.read_byte(0xFEB4), 0x00);
+        assert_eq!(memory.read_byte(0xFEFF), 0x00);
+    });
+    with_memory_map(Model::Cgb, create_rom(), |memory| {
+        memory.write_byte(0xFEA0, 0x12);
+        assert_eq!(memory.read_byte(0xFEA0), 0xAA);
+        assert_eq!(memory.read_byte(0xFEB4), 0xBB);
+        assert_eq!(memory.read_byte(0xFEFF), 0xFF);
+    });
+}
+
+#[test]
+fn test_unused_io_registers() {
+    with_memory_map(Model::Dmg, create_rom(), |memory| {
+        for &address in &[0xFF03, 0xFF08, 0xFF0E, 0xFF15, 0xFF1F, 0xFF27, 0xFF2F, 0xFF4C, 0xFF7F] {
+            memory.write_byte(address, 0x00);
+            assert_eq!(memory.read_byte(address), 0xFF, "{:#06X}", address);
+        }
+    });
+}
+
+#[test]
+fn test_missing_cartridge_ram() {
+    with_memory_map(Model::Dmg, create_rom(), |memory| {
+        memory.write_byte(0xA000, 0x12);
+        assert_eq!(memory.read_byte(0xA000), 0xFF);
+        assert_eq!(memory.read_byte(0xBFFF), 0xFF);
+    });
+    // MBC1 without RAM, which can not be enabled
+    let mut rom = BlockMemory::new(0x8000);
+    rom.write_byte(0x147, 0x01);
+    with_memory_map(Model::Dmg, rom, |memory| {
+        memory.write_byte(0x0000, 0x0A);
+        memory.write_byte(0xA000, 0x12);
+        assert_eq!(memory.read_byte(0xA000), 0xFF);
+    });
+}
+
+#[test]
+fn test_disabled_cartridge_ram() {
+    let mut rom = BlockMemory::new(0x8000);
+    rom.write_byte(0x147, 0x02);
+    rom.write_byte(0x149, 0x02);
+    with_memory_map(Model::Dmg, rom, |memory| {
+        memory.write_byte(0xA000, 0x12);
+        assert_eq!(memory.read_byte(0xA000), 0xFF);
+        memory.write_byte(0x0000, 0x0A);
+        assert_eq!(memory.read_byte(0xA000), 0x00);
+        memory.write_byte(0xA000, 0x12);
+        assert_eq!(memory.read_byte(0xA000), 0x12);
+        memory.write_byte(0x0000, 0x00);
+        assert_eq!(memory.read_byte(0xA000), 0xFF);
+    });
+}
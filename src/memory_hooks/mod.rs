@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookType {
+    // Data read by the CPU
+    Read,
+    // Write by the CPU or the debugger
+    Write,
+    // Opcode fetched by the CPU
+    Execute,
+    // Write to an MBC register that switched the ROM bank at 0x4000-0x7FFF
+    RomBankSwitch,
+    // Write to an MBC register that switched the cartridge RAM bank
+    RamBankSwitch,
+}
+
+// Passed to the hooks. For bank switches, the address and value are the
+// ones written to the MBC and bank is the new bank. Otherwise bank is the
+// ROM or cartridge RAM bank of the address, if any. The cycle counts the
+// clock cycles since the start of the emulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryEvent {
+    pub hook_type: HookType,
+    pub address: u16,
+    pub value: u8,
+    pub bank: Option<u16>,
+    pub cycle: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HookId(u64);
+
+struct Hook {
+    id: HookId,
+    hook_type: HookType,
+    start: u16,
+    end: u16,
+    callback: Box<FnMut(&MemoryEvent)>,
+}
+
+// Callbacks on memory accesses and bank switches for tools like cheat
+// finders, achievements or auto splitters. Hooks are registered before or
+// during the emulation through the handle in Options. Callbacks must not add
+// or remove hooks themselves.
+#[derive(Default)]
+pub struct MemoryHooks {
+    hooks: Vec<Hook>,
+    next_id: u64,
+}
+
+impl MemoryHooks {
+    pub fn new() -> MemoryHooks {
+        MemoryHooks::default()
+    }
+
+    pub fn shared() -> Rc<RefCell<MemoryHooks>> {
+        Rc::new(RefCell::new(MemoryHooks::new()))
+    }
+
+    // Calls the callback for events of the given type with an address in the
+    // range (inclusive). For bank switches, the range applies to the address
+    // of the MBC register.
+    pub fn add<F>(&mut self, hook_type: HookType, start: u16, end: u16, callback: F) -> HookId
+        where F: FnMut(&MemoryEvent) + 'static
+    {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push(Hook { id, hook_type, start, end, callback: Box::new(callback) });
+        id
+    }
+
+    pub fn remove(&mut self, id: HookId) {
+        self.hooks.retain(|hook| hook.id != id);
+    }
+
+    pub fn has(&self, hook_type: HookType) -> bool {
+        self.hooks.iter().any(|hook| hook.hook_type == hook_type)
+    }
+
+    pub fn call(&mut self, event: &MemoryEvent) {
+        for hook in &mut self.hooks {
+            if hook.hook_type == event.hook_type && hook.start <= event.address && event.address <= hook.end {
+                (hook.callback)(event);
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use super::*;
+
+fn event(hook_type: HookType, address: u16) -> MemoryEvent {
+    MemoryEvent { hook_type, address, value: 0x42, bank: None, cycle: 0 }
+}
+
+#[test]
+fn test_hooks_filter_by_type_and_range() {
+    let addresses = Rc::new(RefCell::new(Vec::new()));
+    let mut hooks = MemoryHooks::new();
+    let recorded = addresses.clone();
+    hooks.add(HookType::Write, 0xC000, 0xC0FF, move |event| recorded.borrow_mut().push(event.address));
+    assert!(hooks.has(HookType::Write));
+    assert!(!hooks.has(HookType::Read));
+    hooks.call(&event(HookType::Write, 0xBFFF));
+    hooks.call(&event(HookType::Write, 0xC000));
+    hooks.call(&event(HookType::Read, 0xC010));
+    hooks.call(&event(HookType::Write, 0xC0FF));
+    hooks.call(&event(HookType::Write, 0xC100));
+    assert_eq!(*addresses.borrow(), vec![0xC000, 0xC0FF]);
+}
+
+#[test]
+fn test_remove_hook() {
+    let calls = Rc::new(RefCell::new(0));
+    let mut hooks = MemoryHooks::new();
+    let first_calls = calls.clone();
+    let first = hooks.add(HookType::Execute, 0x0000, 0xFFFF, move |_| *first_calls.borrow_mut() += 1);
+    let second_calls = calls.clone();
+    hooks.add(HookType::Execute, 0x0000, 0xFFFF, move |_| *second_calls.borrow_mut() += 10);
+    hooks.call(&event(HookType::Execute, 0x0100));
+    hooks.remove(first);
+    hooks.call(&event(HookType::Execute, 0x0100));
+    assert_eq!(*calls.borrow(), 21);
+    assert!(hooks.has(HookType::Execute));
+}
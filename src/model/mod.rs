@@ -0,0 +1,46 @@
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests;
+
+// Game Boy hardware models. Some parts of the system behave differently
+// depending on the model.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    // Early DMG with a different boot ROM
+    Dmg0,
+    Dmg,
+    // Game Boy Pocket
+    Mgb,
+    // Super Game Boy
+    Sgb,
+    // CGB revision E, which behaves like the AGB in most respects
+    Cgb,
+}
+
+impl Model {
+    // The model the cartridge is meant for: a CGB if it supports CGB
+    // functions, otherwise a DMG
+    pub fn for_rom(cgb_flag: u8) -> Model {
+        if cgb_flag & 0x80 != 0 { Model::Cgb } else { Model::Dmg }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        *self == Model::Cgb
+    }
+}
+
+impl FromStr for Model {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Model, ()> {
+        match name.to_lowercase().as_str() {
+            "dmg0" => Ok(Model::Dmg0),
+            "dmg" => Ok(Model::Dmg),
+            "mgb" => Ok(Model::Mgb),
+            "sgb" => Ok(Model::Sgb),
+            "cgb" => Ok(Model::Cgb),
+            _ => Err(()),
+        }
+    }
+}
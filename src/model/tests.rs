@@ -0,0 +1,18 @@
+use super::*;
+
+#[test]
+fn test_model_for_rom() {
+    assert_eq!(Model::for_rom(0x00), Model::Dmg);
+    assert_eq!(Model::for_rom(0x80), Model::Cgb);
+    assert_eq!(Model::for_rom(0xC0), Model::Cgb);
+    assert!(Model::for_rom(0x80).is_cgb());
+    assert!(!Model::Sgb.is_cgb());
+}
+
+#[test]
+fn test_parse_model() {
+    assert_eq!("dmg0".parse(), Ok(Model::Dmg0));
+    assert_eq!("MGB".parse(), Ok(Model::Mgb));
+    assert_eq!("Cgb".parse(), Ok(Model::Cgb));
+    assert_eq!("gba".parse::<Model>(), Err(()));
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests;
+
+// Number of bytes copied to OAM, one per machine cycle
+const TRANSFER_LENGTH: u16 = 0xA0;
+
+// OAM DMA, started by writing the upper byte of the source address to FF46.
+// After a machine cycle of setup, it copies one byte per machine cycle.
+// Writing FF46 again restarts it, while the running transfer continues
+// during the setup of the new one.
+#[derive(Clone)]
+pub struct OamDma {
+    // Last value written to FF46, which can be read back
+    register: u8,
+    // Source address of a transfer that is being set up
+    pending_source: Option<u16>,
+    // Source address of the running transfer and number of bytes copied
+    transfer: Option<(u16, u16)>,
+    // Last byte read by the DMA, which the CPU sees on a conflicting bus
+    bus_value: u8,
+    // Clock cycles not yet spent on a machine cycle
+    cycles: u8,
+}
+
+impl OamDma {
+    pub fn new() -> OamDma {
+        OamDma {
+            register: 0xFF,
+            pending_source: None,
+            transfer: None,
+            bus_value: 0xFF,
+            cycles: 0,
+        }
+    }
+
+    pub fn register(&self) -> u8 {
+        self.register
+    }
+
+    pub fn start(&mut self, value: u8) {
+        self.register = value;
+        // Sources above 0xDF00 read from working RAM like echo RAM does
+        let page = if value >= 0xE0 { value - 0x20 } else { value };
+        self.pending_source = Some((page as u16) << 8);
+    }
+
+    // Whether OAM is used by the DMA
+    pub fn is_active(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // The address read by the running transfer, which determines the bus
+    // the CPU can not access
+    pub fn source(&self) -> Option<u16> {
+        self.transfer.map(|(source, copied)| source + copied)
+    }
+
+    pub fn bus_value(&self) -> u8 {
+        self.bus_value
+    }
+
+    pub fn set_bus_value(&mut self, value: u8) {
+        self.bus_value = value;
+    }
+
+    // Adds the given number of clock cycles and returns the number of
+    // machine cycles to run
+    pub fn add_cycles(&mut self, cycles: u8) -> u16 {
+        let cycles = self.cycles as u16 + cycles as u16;
+        self.cycles = (cycles % 4) as u8;
+        cycles / 4
+    }
+
+    // Runs a machine cycle. Returns the source address and the OAM offset of
+    // the byte to copy in it, if any.
+    pub fn machine_cycle(&mut self) -> Option<(u16, u16)> {
+        let copy = self.transfer.map(|(source, copied)| (source + copied, copied));
+        if let Some((source, copied)) = self.transfer {
+            self.transfer = if copied + 1 < TRANSFER_LENGTH { Some((source, copied + 1)) } else { None };
+        }
+        if let Some(source) = self.pending_source.take() {
+            self.transfer = Some((source, 0));
+        }
+        copy
+    }
+}
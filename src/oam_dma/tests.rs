@@ -0,0 +1,65 @@
+use super::*;
+
+#[test]
+fn test_setup_delay() {
+    let mut dma = OamDma::new();
+    dma.start(0xC1);
+    assert!(!dma.is_active());
+    // The first machine cycle sets the transfer up without copying
+    assert_eq!(dma.machine_cycle(), None);
+    assert!(dma.is_active());
+    assert_eq!(dma.source(), Some(0xC100));
+    assert_eq!(dma.machine_cycle(), Some((0xC100, 0x00)));
+}
+
+#[test]
+fn test_one_byte_per_machine_cycle() {
+    let mut dma = OamDma::new();
+    dma.start(0xC1);
+    dma.machine_cycle();
+    for offset in 0..TRANSFER_LENGTH {
+        assert!(dma.is_active());
+        assert_eq!(dma.machine_cycle(), Some((0xC100 + offset, offset)));
+    }
+    assert!(!dma.is_active());
+    assert_eq!(dma.machine_cycle(), None);
+    assert_eq!(dma.register(), 0xC1);
+}
+
+#[test]
+fn test_machine_cycles() {
+    let mut dma = OamDma::new();
+    assert_eq!(dma.add_cycles(2), 0);
+    assert_eq!(dma.add_cycles(8), 2);
+    assert_eq!(dma.add_cycles(2), 1);
+}
+
+#[test]
+fn test_restart() {
+    let mut dma = OamDma::new();
+    dma.start(0xC1);
+    for _ in 0..0x11 {
+        dma.machine_cycle();
+    }
+    dma.start(0xD0);
+    // The running transfer goes on while the new one is set up
+    assert!(dma.is_active());
+    assert_eq!(dma.machine_cycle(), Some((0xC110, 0x10)));
+    assert_eq!(dma.machine_cycle(), Some((0xD000, 0x00)));
+    for _ in 1..TRANSFER_LENGTH {
+        dma.machine_cycle();
+    }
+    assert!(!dma.is_active());
+}
+
+#[test]
+fn test_echo_ram_source() {
+    let mut dma = OamDma::new();
+    dma.start(0xE3);
+    dma.machine_cycle();
+    assert_eq!(dma.register(), 0xE3);
+    assert_eq!(dma.source(), Some(0xC300));
+    dma.start(0xFE);
+    dma.machine_cycle();
+    assert_eq!(dma.source(), Some(0xDE00));
+}
@@ -0,0 +1,194 @@
+use cpu::CLOCK_SPEED_IN_HERTZ;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+mod tests;
+
+// Size of the clock state that follows the cartridge RAM in save files. The
+// layout is the one used by BGB and VBA: the five registers and the five
+// latched registers as 32 bit values, followed by a 64 bit UNIX timestamp,
+// all little endian.
+pub const SAVE_SIZE: usize = 48;
+
+const DAY_HIGH: u8 = 0x01;
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
+#[derive(Clone, Copy, Default)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day_low: u8,
+    // Bit 0 is bit 8 of the day counter, bit 6 halts the clock and bit 7 is
+    // set when the day counter overflows
+    day_high: u8,
+}
+
+impl Registers {
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds & 0x3F,
+            0x09 => self.minutes & 0x3F,
+            0x0A => self.hours & 0x1F,
+            0x0B => self.day_low,
+            0x0C => self.day_high & (DAY_HIGH | HALT | DAY_CARRY),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.day_low = value,
+            0x0C => self.day_high = value & (DAY_HIGH | HALT | DAY_CARRY),
+            _ => (),
+        }
+    }
+
+    fn days(&self) -> u16 {
+        ((self.day_high & DAY_HIGH) as u16) << 8 | self.day_low as u16
+    }
+
+    fn set_days(&mut self, days: u16) {
+        self.day_low = days as u8;
+        self.day_high = self.day_high & !DAY_HIGH | (days >> 8) as u8 & DAY_HIGH;
+    }
+
+    // Counters set to invalid values by the game count up to the limit of
+    // their bits before wrapping around, without a carry
+    fn tick(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        let days = self.days() + 1;
+        if days == 0x200 {
+            self.day_high |= DAY_CARRY;
+        }
+        self.set_days(days & 0x1FF);
+    }
+
+    // Advances a clock with valid counters by a number of seconds at once
+    fn advance(&mut self, seconds: u64) {
+        let total = self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 +
+            self.days() as u64 * 86400 + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days >= 0x200 {
+            self.day_high |= DAY_CARRY;
+        }
+        self.set_days((days % 0x200) as u16);
+    }
+
+    fn to_bytes(&self) -> [u8; 5] {
+        [self.seconds, self.minutes, self.hours, self.day_low, self.day_high]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Registers {
+        // Every register takes four bytes, of which only the first is used
+        Registers {
+            seconds: bytes[0],
+            minutes: bytes[4],
+            hours: bytes[8],
+            day_low: bytes[12],
+            day_high: bytes[16],
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+// Real-time clock of the MBC3. It runs with the emulated time while the
+// emulator runs and catches up with the time that passed in between when a
+// save is loaded.
+#[derive(Clone)]
+pub struct Rtc {
+    registers: Registers,
+    latched: Registers,
+    // Clock cycles since the last second
+    cycles: u64,
+    // Last value written to the latch register
+    latch_value: u8,
+}
+
+impl Rtc {
+    // Advances the clock by the given number of clock cycles at normal speed
+    pub fn step(&mut self, cycles: u8) {
+        if self.registers.day_high & HALT != 0 {
+            return;
+        }
+        self.cycles += cycles as u64;
+        if self.cycles >= CLOCK_SPEED_IN_HERTZ {
+            self.cycles -= CLOCK_SPEED_IN_HERTZ;
+            self.registers.tick();
+        }
+    }
+
+    // Writing 0x00 and then 0x01 copies the clock to the latched registers,
+    // which are the ones that can be read
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_value == 0x00 && value == 0x01 {
+            self.latched = self.registers;
+        }
+        self.latch_value = value;
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched.read(register)
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        if register == 0x08 {
+            self.cycles = 0;
+        }
+        self.registers.write(register, value);
+        self.latched.write(register, value);
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut bytes = vec![0; SAVE_SIZE];
+        for (i, &value) in self.registers.to_bytes().iter().chain(&self.latched.to_bytes()).enumerate() {
+            bytes[i * 4] = value;
+        }
+        let timestamp = now();
+        for i in 0..8 {
+            bytes[40 + i] = (timestamp >> (i * 8)) as u8;
+        }
+        bytes
+    }
+
+    // Restores a saved clock and lets it run for the time since it was saved
+    pub fn load(bytes: &[u8]) -> Rtc {
+        let mut rtc = Rtc {
+            registers: Registers::from_bytes(&bytes[0..20]),
+            latched: Registers::from_bytes(&bytes[20..40]),
+            cycles: 0,
+            latch_value: 0xFF,
+        };
+        let timestamp = (0..8).fold(0, |timestamp, i| timestamp | (bytes[40 + i] as u64) << (i * 8));
+        let now = now();
+        if rtc.registers.day_high & HALT == 0 && timestamp != 0 && now > timestamp {
+            rtc.registers.advance(now - timestamp);
+        }
+        rtc
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+
+fn registers(days: u16, hours: u8, minutes: u8, seconds: u8) -> Registers {
+    let mut registers = Registers { seconds, minutes, hours, ..Registers::default() };
+    registers.set_days(days);
+    registers
+}
+
+fn time(registers: &Registers) -> (u16, u8, u8, u8) {
+    (registers.days(), registers.hours, registers.minutes, registers.seconds)
+}
+
+// A clock that was saved without a timestamp
+fn new_rtc() -> Rtc {
+    Rtc::load(&[0; SAVE_SIZE])
+}
+
+#[test]
+fn test_tick_wraps_invalid_counters_without_carry() {
+    let mut clock = registers(0, 0, 5, 63);
+    clock.tick();
+    assert_eq!(time(&clock), (0, 0, 5, 0));
+    let mut clock = registers(0, 3, 63, 59);
+    clock.tick();
+    assert_eq!(time(&clock), (0, 3, 0, 0));
+    let mut clock = registers(7, 31, 59, 59);
+    clock.tick();
+    assert_eq!(time(&clock), (7, 0, 0, 0));
+}
+
+#[test]
+fn test_tick_into_next_day() {
+    let mut clock = registers(255, 23, 59, 59);
+    clock.tick();
+    assert_eq!(time(&clock), (256, 0, 0, 0));
+    assert_eq!(clock.day_high, DAY_HIGH);
+
+    let mut clock = registers(511, 23, 59, 59);
+    clock.tick();
+    assert_eq!(time(&clock), (0, 0, 0, 0));
+    assert_eq!(clock.day_high, DAY_CARRY);
+}
+
+#[test]
+fn test_advance_across_days() {
+    let mut clock = registers(1, 23, 59, 30);
+    clock.advance(90);
+    assert_eq!(time(&clock), (2, 0, 1, 0));
+    assert_eq!(clock.day_high & DAY_CARRY, 0);
+
+    let mut clock = registers(10, 12, 0, 0);
+    clock.advance(510 * 86400);
+    assert_eq!(time(&clock), (8, 12, 0, 0));
+    assert_eq!(clock.day_high & DAY_CARRY, DAY_CARRY);
+}
+
+fn run_for_a_second(rtc: &mut Rtc) {
+    for _ in 0..CLOCK_SPEED_IN_HERTZ / 4 {
+        rtc.step(4);
+    }
+}
+
+#[test]
+fn test_halt_stops_clock() {
+    let mut rtc = new_rtc();
+    rtc.write(0x0C, HALT);
+    run_for_a_second(&mut rtc);
+    assert_eq!(time(&rtc.registers), (0, 0, 0, 0));
+    rtc.write(0x0C, 0x00);
+    run_for_a_second(&mut rtc);
+    assert_eq!(time(&rtc.registers), (0, 0, 0, 1));
+}
+
+#[test]
+fn test_latch() {
+    let mut rtc = new_rtc();
+    rtc.write(0x08, 5);
+    assert_eq!(rtc.read(0x08), 5);
+    run_for_a_second(&mut rtc);
+    assert_eq!(rtc.read(0x08), 5);
+    // Only writing 0x00 and then 0x01 latches the clock
+    rtc.write_latch(0x01);
+    assert_eq!(rtc.read(0x08), 5);
+    rtc.write_latch(0x00);
+    assert_eq!(rtc.read(0x08), 5);
+    rtc.write_latch(0x01);
+    assert_eq!(rtc.read(0x08), 6);
+    run_for_a_second(&mut rtc);
+    rtc.write_latch(0x01);
+    assert_eq!(rtc.read(0x08), 6);
+}
+
+#[test]
+fn test_save_and_load() {
+    // A halted clock does not catch up
+    let mut rtc = new_rtc();
+    rtc.write(0x0A, 17);
+    rtc.write(0x0B, 0x2A);
+    rtc.write(0x0C, HALT | DAY_HIGH);
+    let bytes = rtc.save();
+    assert_eq!(bytes.len(), SAVE_SIZE);
+    assert_eq!(&bytes[8..20], &[17, 0, 0, 0, 0x2A, 0, 0, 0, HALT | DAY_HIGH, 0, 0, 0]);
+    let loaded = Rtc::load(&bytes);
+    assert_eq!(time(&loaded.registers), (0x12A, 17, 0, 0));
+    assert_eq!(loaded.read(0x0C), HALT | DAY_HIGH);
+
+    // A running clock catches up with the time since the save
+    let mut bytes = new_rtc().save();
+    let timestamp = now() - 3600;
+    for i in 0..8 {
+        bytes[40 + i] = (timestamp >> (i * 8)) as u8;
+    }
+    let loaded = Rtc::load(&bytes);
+    let (days, hours, minutes, seconds) = time(&loaded.registers);
+    assert_eq!((days, hours, minutes), (0, 1, 0));
+    assert!(seconds <= 1);
+}
@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+// Symbols are stored by bank and address. Only the switchable ROM area
+// distinguishes banks, everything else is stored in bank 0.
+type Key = (u16, u16);
+
+fn key(bank: Option<u16>, address: u16) -> Key {
+    match address {
+        0x4000...0x7FFF => (bank.unwrap_or(1), address),
+        _ => (0, address),
+    }
+}
+
+// Start of the memory area containing the address. Labels are not used for
+// addresses in other areas.
+fn area_start(address: u16) -> u16 {
+    match address {
+        0x0000...0x3FFF => 0x0000,
+        0x4000...0x7FFF => 0x4000,
+        0x8000...0x9FFF => 0x8000,
+        0xA000...0xBFFF => 0xA000,
+        0xC000...0xFDFF => 0xC000,
+        0xFE00...0xFEFF => 0xFE00,
+        0xFF00...0xFF7F => 0xFF00,
+        _ => 0xFF80,
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    let value = value.trim_start_matches("0x").trim_start_matches("0X").trim_start_matches('$');
+    u32::from_str_radix(value, 16).ok()
+}
+
+// Parses "BANK:ADDR NAME" as written by RGBDS and no$gmb, or "DEF NAME VALUE"
+// as written by GBDK, where the bank is stored above the 16 bit address
+fn parse_line(line: &str) -> Option<(u16, u16, &str)> {
+    let line = line.splitn(2, ';').next().unwrap();
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.len() {
+        2 => {
+            let mut location = words[0].splitn(2, ':');
+            let bank = location.next().and_then(parse_hex)?;
+            let address = location.next().and_then(parse_hex)?;
+            if bank > 0xFFFF || address > 0xFFFF {
+                return None;
+            }
+            Some((bank as u16, address as u16, words[1]))
+        }
+        3 if words[0] == "DEF" => {
+            // Start and length of the linker areas are no addresses
+            if words[1].starts_with("l__") || words[1].starts_with("s__") || words[1].starts_with('.') {
+                return None;
+            }
+            let value = parse_hex(words[2])?;
+            Some(((value >> 16) as u16, value as u16, words[1]))
+        }
+        _ => None,
+    }
+}
+
+// Labels for ROM and RAM addresses, usually loaded from the symbol file of the
+// assembler or compiler
+#[derive(Default)]
+pub struct Symbols {
+    labels: BTreeMap<Key, String>,
+    addresses: HashMap<String, Key>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    // Lines that can not be parsed are skipped
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::new();
+        for (bank, address, name) in text.lines().filter_map(parse_line) {
+            symbols.add(bank, address, name);
+        }
+        symbols
+    }
+
+    pub fn load(path: &Path) -> Symbols {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Error reading symbol file {}: {}", path.display(), e));
+        Symbols::parse(&text)
+    }
+
+    // If an address has several labels, the first one is used for it
+    pub fn add(&mut self, bank: u16, address: u16, name: &str) {
+        let key = key(Some(bank), address);
+        self.labels.entry(key).or_insert_with(|| name.to_string());
+        self.addresses.entry(name.to_string()).or_insert(key);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // Returns the label of exactly this address. The bank is the one mapped
+    // to the address, see Memory::rom_bank.
+    pub fn label(&self, bank: Option<u16>, address: u16) -> Option<&str> {
+        self.labels.get(&key(bank, address)).map(|name| name.as_str())
+    }
+
+    // Describes the address relative to the closest label before it in the
+    // same memory area, e.g. "Main.loop+$3"
+    pub fn describe(&self, bank: Option<u16>, address: u16) -> Option<String> {
+        let key = key(bank, address);
+        let start = (key.0, area_start(address));
+        let (&(_, label_address), name) = self.labels.range(start..=key).next_back()?;
+        let offset = address - label_address;
+        if offset == 0 {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+${:X}", name, offset))
+        }
+    }
+
+    // Returns the bank and address of a label. C symbols can be given without
+    // their leading underscore.
+    pub fn find(&self, name: &str) -> Option<(u16, u16)> {
+        self.addresses.get(name)
+            .or_else(|| self.addresses.get(&format!("_{}", name)))
+            .cloned()
+    }
+}
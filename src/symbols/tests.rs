@@ -0,0 +1,32 @@
+use super::*;
+
+#[test]
+fn test_parse_formats() {
+    let symbols = Symbols::parse("\
+; File generated by rgblink
+00:0150 Start
+00:0150 Main
+01:4000 Main.loop
+02:4000 Data
+00:c000 wCounter
+DEF _main 0x34200
+DEF l__CODE 0x1234
+invalid line
+");
+    assert_eq!(symbols.find("Main.loop"), Some((1, 0x4000)));
+    assert_eq!(symbols.find("main"), Some((3, 0x4200)));
+    assert_eq!(symbols.find("l__CODE"), None);
+    assert_eq!(symbols.label(Some(0), 0x150), Some("Start"));
+    assert_eq!(symbols.label(Some(2), 0x4000), Some("Data"));
+    assert_eq!(symbols.label(None, 0xC000), Some("wCounter"));
+}
+
+#[test]
+fn test_describe() {
+    let symbols = Symbols::parse("00:0150 Start\n01:4000 Main.loop\n");
+    assert_eq!(symbols.describe(Some(0), 0x0153), Some("Start+$3".to_string()));
+    assert_eq!(symbols.describe(Some(1), 0x4000), Some("Main.loop".to_string()));
+    // Labels in other banks and memory areas are not used
+    assert_eq!(symbols.describe(Some(2), 0x4010), None);
+    assert_eq!(symbols.describe(Some(1), 0x8000), None);
+}
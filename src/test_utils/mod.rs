@@ -0,0 +1,35 @@
+use audio::AudioDevice;
+use display::{Display, COLS};
+
+use std::cell::Cell;
+
+#[cfg(test)]
+mod tests;
+
+// Stand-ins for the SDL devices in tests of the parts behind the memory map
+
+pub struct NullDisplay;
+
+impl Display for NullDisplay {
+    fn redraw(&mut self) {}
+
+    fn clear(&mut self) {}
+
+    fn set_line(&mut self, _line: u8, _pixels: &[u8; COLS]) {}
+}
+
+// Counts the queued samples instead of playing them
+#[derive(Default)]
+pub struct NullAudioDevice {
+    pub samples: Cell<usize>,
+}
+
+impl AudioDevice for NullAudioDevice {
+    fn queue(&self, bytes: &[i16]) {
+        self.samples.set(self.samples.get() + bytes.len());
+    }
+
+    fn queue_size(&self) -> usize {
+        0
+    }
+}
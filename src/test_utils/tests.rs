@@ -0,0 +1,10 @@
+use super::*;
+
+#[test]
+fn test_null_audio_device_counts_samples() {
+    let device = NullAudioDevice::default();
+    device.queue(&[0; 4]);
+    device.queue(&[1, -1]);
+    assert_eq!(device.samples.get(), 6);
+    assert_eq!(device.queue_size(), 0);
+}
@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Timer {
     internal_counter: u16,
 
@@ -47,6 +48,11 @@ impl Timer {
         self.change_counter_to(0);
     }
 
+    // Sets the internal counter, whose upper byte is the divider register
+    pub fn set_counter(&mut self, counter: u16) {
+        self.change_counter_to(counter);
+    }
+
     pub fn get_divider(&self) -> u8 {
         ((self.internal_counter & 0xFF00) >> 8) as u8
     }
@@ -67,6 +73,7 @@ impl Timer {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum TimerSpeed {
     ClockOver1024,
     ClockOver16,
@@ -0,0 +1,204 @@
+// Runs the SM83 single step tests (https://github.com/SingleStepTests/sm83).
+// Every test sets up the CPU and memory, executes a single command and
+// compares registers, memory and bus activity with the expected values.
+//
+// The test files are not part of the repository, so the test is ignored by
+// default. Put the contents of the v1 directory into tests/sm83/v1 or point
+// SM83_TESTS_DIR to it and run it with `cargo test -- --ignored`.
+
+extern crate rustgb;
+extern crate serde_json;
+
+use rustgb::{AccessKind, Cpu, CpuState, Memory};
+use serde_json::Value;
+
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+// Failures printed per test file
+const MAX_REPORTED_FAILURES: usize = 5;
+
+// Opcodes whose tests can not be run on a single CPU step: HALT waits for an
+// interrupt and STOP depends on the joypad and resets the divider
+const SKIPPED_FILES: [&str; 2] = ["10.json", "76.json"];
+
+struct TestMemory {
+    bytes: Vec<u8>,
+}
+
+impl Memory for TestMemory {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.bytes[address as usize] = value;
+    }
+}
+
+fn get_u16(state: &Value, key: &str) -> u16 {
+    state[key].as_u64().unwrap_or_else(|| panic!("Missing value {}", key)) as u16
+}
+
+fn get_u8(state: &Value, key: &str) -> u8 {
+    get_u16(state, key) as u8
+}
+
+fn set_up(cpu: &mut Cpu<TestMemory>, initial: &Value) {
+    for entry in initial["ram"].as_array().expect("Missing ram") {
+        cpu.memory_mut().write_byte(entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8);
+    }
+    let mut state = cpu.state();
+    state.a = get_u8(initial, "a");
+    state.f = get_u8(initial, "f");
+    state.b = get_u8(initial, "b");
+    state.c = get_u8(initial, "c");
+    state.d = get_u8(initial, "d");
+    state.e = get_u8(initial, "e");
+    state.h = get_u8(initial, "h");
+    state.l = get_u8(initial, "l");
+    state.sp = get_u16(initial, "sp");
+    state.pc = get_u16(initial, "pc");
+    state.interrupt_master_enable = get_u8(initial, "ime") != 0;
+    if !initial["ie"].is_null() {
+        state.interrupt_enable = get_u8(initial, "ie");
+    }
+    cpu.set_state(&state);
+}
+
+fn compare_registers(state: &CpuState, expected: &Value, errors: &mut Vec<String>) {
+    let registers = [
+        ("a", state.a as u16), ("f", state.f as u16),
+        ("b", state.b as u16), ("c", state.c as u16),
+        ("d", state.d as u16), ("e", state.e as u16),
+        ("h", state.h as u16), ("l", state.l as u16),
+        ("sp", state.sp), ("pc", state.pc),
+    ];
+    for &(name, value) in &registers {
+        let expected_value = get_u16(expected, name);
+        if value != expected_value {
+            errors.push(format!("{} is {:#X}, expected {:#X}", name, value, expected_value));
+        }
+    }
+    // The tests do not model the delay of EI
+    let ime = state.interrupt_master_enable || state.enable_interrupts_pending;
+    if ime != (get_u8(expected, "ime") != 0) {
+        errors.push(format!("ime is {}, expected {}", ime, !ime));
+    }
+}
+
+fn compare_memory(cpu: &Cpu<TestMemory>, expected: &Value, errors: &mut Vec<String>) {
+    for entry in expected["ram"].as_array().expect("Missing ram") {
+        let address = entry[0].as_u64().unwrap() as u16;
+        let expected_value = entry[1].as_u64().unwrap() as u8;
+        let value = cpu.memory().read_byte(address);
+        if value != expected_value {
+            errors.push(format!("memory at {:#06X} is {:#04X}, expected {:#04X}", address, value, expected_value));
+        }
+    }
+}
+
+// Compares the clock cycles and the sequence of reads and writes
+fn compare_cycles(cycles: u8, accesses: &[(u16, u8, bool)], expected: &Value, errors: &mut Vec<String>) {
+    let expected_cycles = expected.as_array().expect("Missing cycles");
+    if cycles as usize != expected_cycles.len() * 4 {
+        errors.push(format!("took {} cycles, expected {}", cycles, expected_cycles.len() * 4));
+    }
+    let expected_accesses: Vec<(u16, u8, bool)> = expected_cycles
+        .iter()
+        .filter_map(|cycle| {
+            let kind = cycle[2].as_str()?;
+            if !kind.contains('r') && !kind.contains('w') {
+                return None;
+            }
+            Some((cycle[0].as_u64()? as u16, cycle[1].as_u64()? as u8, kind.contains('w')))
+        })
+        .collect();
+    if accesses != &expected_accesses[..] {
+        errors.push(format!("bus accesses {:X?}, expected {:X?}", accesses, expected_accesses));
+    }
+}
+
+// Runs a single test case and returns the differences to the expected result
+fn run_test(test: &Value) -> Vec<String> {
+    let mut cpu = Cpu::new(TestMemory { bytes: vec![0; 0x10000] });
+    set_up(&mut cpu, &test["initial"]);
+    cpu.set_record_memory_accesses(true);
+    let cycles = cpu.cycle();
+    let accesses: Vec<(u16, u8, bool)> = cpu.take_memory_accesses()
+        .iter()
+        .map(|access| (access.address, access.value, access.kind == AccessKind::Write))
+        .collect();
+
+    let mut errors = Vec::new();
+    compare_registers(&cpu.state(), &test["final"], &mut errors);
+    compare_memory(&cpu, &test["final"], &mut errors);
+    compare_cycles(cycles, &accesses, &test["cycles"], &mut errors);
+    errors
+}
+
+fn tests_dir() -> PathBuf {
+    match env::var("SM83_TESTS_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/sm83/v1"),
+    }
+}
+
+#[test]
+#[ignore]
+fn test_sm83_single_step() {
+    let dir = tests_dir();
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("No SM83 tests found in {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+
+    let mut failed_files = Vec::new();
+    for path in paths {
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if !file_name.ends_with(".json") || SKIPPED_FILES.contains(&file_name.as_str()) {
+            continue;
+        }
+        let file = File::open(&path).unwrap_or_else(|e| panic!("Error opening {}: {}", path.display(), e));
+        let tests: Value = serde_json::from_reader(file)
+            .unwrap_or_else(|e| panic!("Error parsing {}: {}", path.display(), e));
+        let mut failures = 0;
+        for test in tests.as_array().expect("Expected a list of tests") {
+            let errors = run_test(test);
+            if errors.is_empty() {
+                continue;
+            }
+            if failures < MAX_REPORTED_FAILURES {
+                println!("{}: {}", test["name"].as_str().unwrap_or("?"), errors.join(", "));
+            }
+            failures += 1;
+        }
+        if failures > 0 {
+            failed_files.push(format!("{} ({} failures)", file_name, failures));
+        }
+    }
+    assert!(failed_files.is_empty(), "Failed tests: {}", failed_files.join(", "));
+}
+
+// Checks the harness itself with a hand written test in the same format
+#[test]
+fn test_harness() {
+    // Fetch of the LD (HL),A opcode followed by the write to (HL)
+    let test: Value = serde_json::from_str(r#"{
+        "name": "77 0000",
+        "initial": {
+            "pc": 256, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0,
+            "f": 176, "h": 192, "l": 16, "ime": 0, "ie": 0,
+            "ram": [[256, 119]]
+        },
+        "final": {
+            "pc": 257, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0,
+            "f": 176, "h": 192, "l": 16, "ime": 0, "ie": 0,
+            "ram": [[256, 119], [49168, 66]]
+        },
+        "cycles": [[256, 119, "r-m"], [49168, 66, "-wm"]]
+    }"#).unwrap();
+    assert_eq!(run_test(&test), Vec::<String>::new());
+}